@@ -0,0 +1,12 @@
+package com.example.blastemst
+
+import kotlinx.serialization.Serializable
+
+// A milestone from the Rust core. unlocked_at is null while the milestone is still locked.
+@Serializable
+data class Achievement(
+    val id: String,
+    val title: String,
+    val description: String,
+    val unlocked_at: String? = null
+)
@@ -0,0 +1,35 @@
+package com.example.blastemst
+
+import kotlinx.serialization.Serializable
+
+// A structured check-in taken before ("pre") or after ("post") a session. Fatigue and
+// breathlessness are 0-10; perceived_exertion is the Borg RPE scale (6-20).
+@Serializable
+data class Checkin(
+    val session_id: Long,
+    val kind: String,
+    val fatigue: Int,
+    val breathlessness: Int,
+    val dizziness: Boolean,
+    val perceived_exertion: Int? = null,
+    val notes: String = "",
+    val flags: List<String> = emptyList(), // Filled in by the Rust core on save
+    val recorded_at: String? = null
+)
+
+@Serializable
+data class CheckinResult(
+    val checkin: Checkin? = null,
+    val errors: List<ProfileFieldError> = emptyList()
+)
+
+// Shown before starting a session when the last check-in reported adverse symptoms.
+@Serializable
+data class SafetyWarning(
+    val session_id: Long,
+    val kind: String,
+    val recorded_at: String? = null,
+    val flags: List<String>,
+    val messages: List<String>,
+    val advice: String
+)
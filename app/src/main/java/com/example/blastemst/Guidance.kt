@@ -0,0 +1,37 @@
+package com.example.blastemst
+
+import kotlinx.serialization.Serializable
+
+// Returned by the guided session functions in RustBridge after every tick or event.
+@Serializable
+data class GuidanceUpdate(
+    val session_id: Long,
+    val state: GuidanceState,
+    val cues: List<GuidanceCue> = emptyList()
+)
+
+@Serializable
+data class GuidanceState(
+    val phase: String, // ready, inhale, blow, rest, set_rest, paused or complete
+    val paused_phase: String? = null,
+    val seconds_remaining: Int? = null,
+    val rep: Int,
+    val set: Int,
+    val reps_completed: Int,
+    val total_reps: Int,
+    val total_sets: Int
+)
+
+// type is phase_started, countdown, rep_completed, set_completed or session_completed;
+// only the fields of that cue are set.
+@Serializable
+data class GuidanceCue(
+    val type: String,
+    val phase: String? = null,
+    val at: String? = null,
+    val seconds: Int? = null,
+    val seconds_left: Int? = null,
+    val rep: Int? = null,
+    val set: Int? = null,
+    val reps: Int? = null
+)
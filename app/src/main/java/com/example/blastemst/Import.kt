@@ -0,0 +1,47 @@
+package com.example.blastemst
+
+import kotlinx.serialization.Serializable
+
+// Returned by RustBridge.previewCsvImport and importCsv. error is null on success, otherwise
+// one of "invalid_mapping", "invalid_csv", "missing_column", "no_header",
+// "unknown_date_format" or "error".
+@Serializable
+data class CsvImportResult(
+    val report: ImportReport? = null,
+    val error: String? = null
+)
+
+@Serializable
+data class ImportReport(
+    val dry_run: Boolean,
+    val delimiter: String,
+    val date_format: String,
+    // Non-empty when the dates read just as well another way, e.g. month-first. Ask the
+    // user which is meant and pass it back as date_format in the mapping.
+    val other_date_formats: List<String> = emptyList(),
+    val rows: List<ImportRow> = emptyList(),
+    val ready: Int = 0,
+    val errors: Int = 0,
+    val duplicates: Int = 0,
+    val imported: Int = 0
+)
+
+@Serializable
+data class ImportRow(
+    val line: Long, // Counting the header
+    val status: String, // "ready", "error", "duplicate" or "imported"
+    val errors: List<ProfileFieldError> = emptyList(),
+    val session: ImportedSession? = null,
+    val duplicate_of: Long? = null, // Existing session id
+    val duplicate_of_line: Long? = null, // Earlier row in the same file
+    val session_id: Long? = null
+)
+
+@Serializable
+data class ImportedSession(
+    val start_time: String,
+    val time_known: Boolean,
+    val pressure_setting: Int, // cmH2O
+    val reps: Long,
+    val notes: String = ""
+)
@@ -0,0 +1,45 @@
+package com.example.blastemst
+
+import kotlinx.serialization.Serializable
+
+// Returned by RustBridge.getAllSessionsChecked. Skipped sessions couldn't be read and are
+// reported with a repair by checkIntegrity.
+@Serializable
+data class CheckedSessions(
+    val sessions: List<Session> = emptyList(),
+    val skipped: List<SkippedSession> = emptyList()
+)
+
+@Serializable
+data class SkippedSession(
+    val session_id: Long,
+    val problem: String
+)
+
+// Returned by RustBridge.checkIntegrity and repairIntegrity
+@Serializable
+data class IntegrityReport(
+    val ok: Boolean,
+    val issues: List<IntegrityIssue> = emptyList(),
+    val repaired: Int = 0 // Only set by repairIntegrity
+)
+
+@Serializable
+data class IntegrityIssue(
+    val id: String, // Pass back to repairIntegrity to apply the repair
+    // "corruption", "bad_timestamp", "orphaned_rep", "end_before_start" or
+    // "duplicate_open_session"
+    val kind: String,
+    val table: String,
+    val row_id: Long? = null,
+    val column: String? = null,
+    val profile_id: Long? = null,
+    val detail: String,
+    val repair: IntegrityRepair? = null // null when only restoring a backup can help
+)
+
+@Serializable
+data class IntegrityRepair(
+    val action: String, // "set_timestamp" or "delete_row"
+    val value: String? = null // The new timestamp for set_timestamp
+)
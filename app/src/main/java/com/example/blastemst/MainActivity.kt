@@ -115,6 +115,7 @@ class MainActivity : ComponentActivity() {
             val activeSession by viewModel.activeSession.collectAsStateWithLifecycle()
             val repCount by viewModel.repCount.collectAsStateWithLifecycle()
             val userProfile by viewModel.userProfile.collectAsStateWithLifecycle()
+            val profileErrors by viewModel.profileErrors.collectAsStateWithLifecycle()
             val settings by viewModel.settings.collectAsStateWithLifecycle()
             val sessionsByDate by viewModel.sessionsByDate.collectAsStateWithLifecycle()
             val currentMonth by viewModel.currentMonth.collectAsStateWithLifecycle()
@@ -126,6 +127,7 @@ class MainActivity : ComponentActivity() {
                     activeSession = activeSession,
                     repCount = repCount,
                     userProfile = userProfile,
+                    profileErrors = profileErrors,
                     settings = settings,
                     sessionsByDate = sessionsByDate,
                     currentMonth = currentMonth,
@@ -133,7 +135,8 @@ class MainActivity : ComponentActivity() {
                     onStartSession = { viewModel.startNewSession() },
                     onAddRep = { viewModel.addRep() },
                     onFinishSession = { viewModel.finishActiveSession() },
-                    onSaveProfile = { updatedProfile -> viewModel.saveProfile(updatedProfile) },
+                    onSaveProfile = { updatedProfile, onSaved -> viewModel.saveProfile(updatedProfile, onSaved) },
+                    onDiscardProfileErrors = { viewModel.clearProfileErrors() },
                     onSaveSettings = { newSettings -> viewModel.saveSettings(newSettings)},
                     onUpdateRepSoundUri = { uri -> viewModel.updateRepSoundUri(uri) },
                     onUpdateHapticFeedback = { isEnabled -> viewModel.updateHapticFeedback(isEnabled) },
@@ -155,6 +158,7 @@ fun MainAppRouter(
     activeSession: Session?,
     repCount: Long,
     userProfile: UserProfile?,
+    profileErrors: List<ProfileFieldError>,
     settings: AppSettings,
     sessionsByDate: Map<LocalDate, List<Session>>,
     currentMonth: YearMonth,
@@ -162,7 +166,8 @@ fun MainAppRouter(
     onStartSession: () -> Unit,
     onAddRep: () -> Unit,
     onFinishSession: () -> Unit,
-    onSaveProfile: (UserProfile) -> Unit,
+    onSaveProfile: (UserProfile, () -> Unit) -> Unit,
+    onDiscardProfileErrors: () -> Unit,
     onSaveSettings: (AppSettings) -> Unit,
     onUpdateRepSoundUri: (Uri?) -> Unit,
     onUpdateHapticFeedback: (Boolean) -> Unit,
@@ -207,8 +212,12 @@ fun MainAppRouter(
             )
             is AppScreen.Profile -> ProfileScreen(
                 userProfile = userProfile,
+                errors = profileErrors,
                 onSaveProfile = onSaveProfile,
-                onNavigateHome = { currentScreen = AppScreen.Home }
+                onNavigateHome = {
+                    onDiscardProfileErrors()
+                    currentScreen = AppScreen.Home
+                }
             )
             is AppScreen.Settings -> SettingsScreen(
                 settings = settings,
@@ -11,13 +11,14 @@ import kotlinx.coroutines.flow.MutableStateFlow
 import kotlinx.coroutines.flow.asStateFlow
 import kotlinx.coroutines.flow.update
 import kotlinx.coroutines.launch
+import kotlinx.coroutines.withContext
 import kotlinx.serialization.encodeToString
 import kotlinx.serialization.json.Json
 import java.time.Instant
 import java.time.LocalDate
 import java.time.YearMonth
+import java.time.ZoneId
 import java.time.format.DateTimeFormatter
-import java.util.concurrent.TimeUnit
 import androidx.core.net.toUri
 
 data class HomeScreenState(
@@ -32,7 +33,9 @@ data class HomeScreenState(
 data class AppSettings(
     val defaultReps: Int = 25,
     val weeklySessionGoal: Int = 5,
+    val dailySessionGoal: Int = 0,
     val defaultPressure: Int = 30,
+    val pressureUnit: String = "cmh2o",
     val appTheme: String = "system",
     val remindersEnabled: Boolean = false,
     val repSoundUri: String? = null,
@@ -53,6 +56,9 @@ class MainViewModel(application: Application) : AndroidViewModel(application) {
     private val _userProfile = MutableStateFlow<UserProfile?>(null)
     val userProfile = _userProfile.asStateFlow()
 
+    private val _profileErrors = MutableStateFlow<List<ProfileFieldError>>(emptyList())
+    val profileErrors = _profileErrors.asStateFlow()
+
     private val _settings = MutableStateFlow(AppSettings())
     val settings = _settings.asStateFlow()
 
@@ -62,6 +68,10 @@ class MainViewModel(application: Application) : AndroidViewModel(application) {
     private val _currentMonth = MutableStateFlow(YearMonth.now())
     val currentMonth = _currentMonth.asStateFlow()
 
+    // Achievements unlocked by the last finished session, for the UI to celebrate
+    private val _newAchievements = MutableStateFlow<List<Achievement>>(emptyList())
+    val newAchievements = _newAchievements.asStateFlow()
+
     private val _activeSessionNotes = MutableStateFlow("")
     val activeSessionNotes = _activeSessionNotes.asStateFlow()
 
@@ -152,7 +162,8 @@ class MainViewModel(application: Application) : AndroidViewModel(application) {
     private fun loadSettings() {
         val defaultRepsStr = RustBridge.getSetting("default_reps", "25")
         val weeklyGoalStr = RustBridge.getSetting("goal_sessions_per_week", "5")
-        val defaultPressureStr = RustBridge.getSetting("default_pressure", "30")
+        val dailyGoalStr = RustBridge.getSetting("goal_sessions_per_day", "0")
+        val pressureUnitStr = RustBridge.getPressureUnit()
         val themeStr = RustBridge.getSetting("theme", "system")
         val remindersStr = RustBridge.getSetting("reminders_enabled", "false")
         val soundUriStr = RustBridge.getSetting("rep_sound_uri", "")
@@ -162,7 +173,9 @@ class MainViewModel(application: Application) : AndroidViewModel(application) {
             it.copy(
                 defaultReps = defaultRepsStr.toIntOrNull() ?: 25,
                 weeklySessionGoal = weeklyGoalStr.toIntOrNull() ?: 5,
-                defaultPressure = defaultPressureStr.toIntOrNull() ?: 30,
+                dailySessionGoal = dailyGoalStr.toIntOrNull() ?: 0,
+                defaultPressure = RustBridge.getDefaultPressure(),
+                pressureUnit = pressureUnitStr,
                 appTheme = themeStr,
                 remindersEnabled = remindersStr.toBoolean(),
                 repSoundUri = soundUriStr.ifEmpty { null },
@@ -179,28 +192,16 @@ class MainViewModel(application: Application) : AndroidViewModel(application) {
         viewModelScope.launch(Dispatchers.IO) {
             RustBridge.setSetting("default_reps", newSettings.defaultReps.toString())
             RustBridge.setSetting("goal_sessions_per_week", newSettings.weeklySessionGoal.toString())
+            RustBridge.setSetting("goal_sessions_per_day", newSettings.dailySessionGoal.toString())
             RustBridge.setSetting("default_pressure", newSettings.defaultPressure.toString())
+            RustBridge.setSetting("pressure_unit", newSettings.pressureUnit)
             RustBridge.setSetting("theme", newSettings.appTheme)
             RustBridge.setSetting("reminders_enabled", newSettings.remindersEnabled.toString())
             RustBridge.setSetting("rep_sound_uri", newSettings.repSoundUri ?: "")
             RustBridge.setSetting("haptic_feedback_enabled", newSettings.isHapticFeedbackEnabled.toString())
 
             if (newSettings.remindersEnabled) {
-                val lastSessionTimeStr = RustBridge.getLastSessionEndTime()
-                if (lastSessionTimeStr.isEmpty()) {
-                    reminderManager.scheduleInactivityCheck(delayInMinutes = 1440)
-                } else {
-                    val lastSessionMillis = Instant.parse(lastSessionTimeStr).toEpochMilli()
-                    val twentyFourHoursInMillis = TimeUnit.HOURS.toMillis(24)
-                    val triggerAtMillis = lastSessionMillis + twentyFourHoursInMillis
-                    val nowMillis = System.currentTimeMillis()
-                    val delayInMillis = triggerAtMillis - nowMillis
-                    if (delayInMillis > 0) {
-                        reminderManager.scheduleInactivityCheck(TimeUnit.MILLISECONDS.toMinutes(delayInMillis))
-                    } else {
-                        reminderManager.scheduleInactivityCheck(delayInMinutes = 10)
-                    }
-                }
+                reminderManager.scheduleNextReminder()
             } else {
                 reminderManager.cancelInactiveCheck()
             }
@@ -248,25 +249,38 @@ class MainViewModel(application: Application) : AndroidViewModel(application) {
     fun finishActiveSession() {
         _activeSession.value?.let { currentSession ->
             viewModelScope.launch(Dispatchers.IO) {
-                RustBridge.endSession(currentSession.id, _activeSessionNotes.value)
+                val offsetMinutes = ZoneId.systemDefault().rules.getOffset(Instant.now()).totalSeconds / 60
+                val unlockedJson = RustBridge.endSession(currentSession.id, _activeSessionNotes.value, offsetMinutes)
+                _newAchievements.value = Json.decodeFromString(unlockedJson)
                 _activeSession.value = null
                 _activeSessionNotes.value = ""
                 if (_settings.value.remindersEnabled) {
-                    reminderManager.scheduleInactivityCheck(delayInMinutes = 1440)
+                    reminderManager.scheduleNextReminder()
                 }
                 loadInitialData()
             }
         }
     }
 
-    fun saveProfile(profileToSave: UserProfile) {
+    // Saves the profile and calls onSaved if it was accepted. Otherwise the field errors are
+    // published in profileErrors and the stored profile stays as it was.
+    fun saveProfile(profileToSave: UserProfile, onSaved: () -> Unit) {
         viewModelScope.launch(Dispatchers.IO) {
             val profileJson = Json.encodeToString(profileToSave)
-            RustBridge.updateProfile(profileJson)
+            val errorsJson = RustBridge.updateProfile(profileJson)
+            val errors: List<ProfileFieldError> = Json.decodeFromString(errorsJson)
+            _profileErrors.value = errors
             loadProfile()
+            if (errors.isEmpty()) {
+                withContext(Dispatchers.Main) { onSaved() }
+            }
         }
     }
 
+    fun clearProfileErrors() {
+        _profileErrors.value = emptyList()
+    }
+
     fun onNextMonth() {
         _currentMonth.update { it.plusMonths(1) }
     }
@@ -286,6 +300,10 @@ class MainViewModel(application: Application) : AndroidViewModel(application) {
         }
     }
 
+    fun onAchievementsShown() {
+        _newAchievements.value = emptyList()
+    }
+
     fun onActiveSessionNotesChanged(newNotes: String) {
         _activeSessionNotes.value = newNotes
     }
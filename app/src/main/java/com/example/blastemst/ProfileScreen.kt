@@ -12,9 +12,11 @@ import androidx.compose.material.icons.automirrored.filled.ArrowBack
 import androidx.compose.material3.ExperimentalMaterial3Api
 import androidx.compose.material3.Icon
 import androidx.compose.material3.IconButton
+import androidx.compose.material3.MaterialTheme
 import androidx.compose.material3.OutlinedTextField
 import androidx.compose.material3.Scaffold
 import androidx.compose.material3.Text
+import androidx.compose.material3.TextButton
 import androidx.compose.material3.TopAppBar
 import androidx.compose.runtime.Composable
 import androidx.compose.runtime.getValue
@@ -28,15 +30,21 @@ import androidx.compose.ui.unit.dp
 @Composable
 fun ProfileScreen(
     userProfile: UserProfile,
-    onSaveProfile: (UserProfile) -> Unit,
+    errors: List<ProfileFieldError>,
+    onSaveProfile: (UserProfile, onSaved: () -> Unit) -> Unit,
     onNavigateHome: () -> Unit
 ) {
     // Local states for UI elements, initialized from userProfile
     var firstName by remember(userProfile.id) { mutableStateOf(userProfile.first_name) }
     var lastName by remember(userProfile.id) { mutableStateOf(userProfile.last_name) }
-    var dob by remember(userProfile.id) { mutableStateOf(userProfile.dob) }
+    var dob by remember(userProfile.id) { mutableStateOf(userProfile.dob ?: "") }
     var speechTherapist by remember(userProfile.id) { mutableStateOf(userProfile.speech_therapist) }
 
+    val shownFields = setOf("first_name", "last_name", "dob", "speech_therapist")
+    fun errorFor(field: String) = errors.firstOrNull { it.field == field }?.message
+    // Problems with fields this screen doesn't edit, or with the profile as a whole
+    val otherErrors = errors.filter { it.field !in shownFields }
+
     Scaffold(
         topBar = {
             TopAppBar(
@@ -49,14 +57,19 @@ fun ProfileScreen(
                             dob = dob,
                             speech_therapist = speechTherapist
                         )
-                        onSaveProfile(updatedProfile) // Save the profile
-                        onNavigateHome()              // Navigate back
+                        // Only leave once the profile was accepted; otherwise the errors show below
+                        onSaveProfile(updatedProfile) { onNavigateHome() }
                     }) {
                         Icon(
                             imageVector = Icons.AutoMirrored.Filled.ArrowBack,
                             contentDescription = "Back and Save"
                         )
                     }
+                },
+                actions = {
+                    if (errors.isNotEmpty()) {
+                        TextButton(onClick = onNavigateHome) { Text("Discard") }
+                    }
                 }
             )
         }
@@ -71,10 +84,16 @@ fun ProfileScreen(
 
             // horizontalAlignment = Alignment.CenterHorizontally // Optional, if you want items centered
         ) {
+            otherErrors.forEach { error ->
+                Text(text = error.message, color = MaterialTheme.colorScheme.error)
+            }
+
             OutlinedTextField(
                 value = firstName,
                 onValueChange = { firstName = it },
                 label = { Text("First Name") },
+                isError = errorFor("first_name") != null,
+                supportingText = errorFor("first_name")?.let { { Text(it) } },
                 modifier = Modifier.fillMaxWidth()
             )
 
@@ -82,13 +101,20 @@ fun ProfileScreen(
                 value = lastName,
                 onValueChange = { lastName = it },
                 label = { Text("Surname") },
+                isError = errorFor("last_name") != null,
+                supportingText = errorFor("last_name")?.let { { Text(it) } },
                 modifier = Modifier.fillMaxWidth()
             )
 
+            // A date of birth from an older version that couldn't be read is shown for re-entry
+            val dobHint = errorFor("dob")
+                ?: userProfile.dob_legacy?.let { "Previously entered as \"$it\"; please re-enter as YYYY-MM-DD" }
             OutlinedTextField(
                 value = dob,
                 onValueChange = { dob = it },
-                label = { Text("Date of Birth") },
+                label = { Text("Date of Birth (YYYY-MM-DD)") },
+                isError = errorFor("dob") != null,
+                supportingText = dobHint?.let { { Text(it) } },
                 modifier = Modifier.fillMaxWidth()
             )
 
@@ -96,6 +122,8 @@ fun ProfileScreen(
                 value = speechTherapist,
                 onValueChange = { speechTherapist = it },
                 label = { Text("Speech Therapist") },
+                isError = errorFor("speech_therapist") != null,
+                supportingText = errorFor("speech_therapist")?.let { { Text(it) } },
                 modifier = Modifier.fillMaxWidth()
             )
         }
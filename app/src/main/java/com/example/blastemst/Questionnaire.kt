@@ -0,0 +1,52 @@
+package com.example.blastemst
+
+import kotlinx.serialization.Serializable
+
+// A built-in questionnaire such as EAT-10, as returned by RustBridge.getQuestionnaires().
+@Serializable
+data class QuestionnaireDefinition(
+    val id: String,
+    val name: String,
+    val description: String,
+    val items: List<String>,
+    val options: List<AnswerOption>,
+    val max_score: Int,
+    val abnormal_threshold: Int,
+    val meaningful_change: Int
+)
+
+@Serializable
+data class AnswerOption(
+    val value: Int,
+    val label: String
+)
+
+@Serializable
+data class QuestionnaireResponse(
+    val id: Long,
+    val instrument: String,
+    val completed_on: String, // YYYY-MM-DD
+    val answers: List<Int>,
+    val total: Int,
+    val abnormal: Boolean,
+    val recorded_at: String
+)
+
+// Higher totals mean worse symptoms, so a negative change is an improvement.
+@Serializable
+data class QuestionnaireTrend(
+    val instrument: String,
+    val points: List<QuestionnaireTrendPoint> = emptyList(),
+    val baseline: Int? = null,
+    val latest: Int? = null,
+    val change: Int? = null,
+    val improved: Boolean = false,
+    val worsened: Boolean = false,
+    val latest_abnormal: Boolean = false
+)
+
+@Serializable
+data class QuestionnaireTrendPoint(
+    val completed_on: String,
+    val total: Int
+)
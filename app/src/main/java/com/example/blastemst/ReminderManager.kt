@@ -8,12 +8,34 @@ import android.util.Log
 import androidx.work.ExistingWorkPolicy
 import androidx.work.OneTimeWorkRequestBuilder
 import androidx.work.WorkManager
+import kotlinx.serialization.Serializable
+import kotlinx.serialization.json.Json
+import java.time.Instant
+import java.time.ZoneId
 import java.util.concurrent.TimeUnit
 
+// Mirrors the core's ReminderDecision. When remind is false, `at` is when to check again.
+@Serializable
+data class ReminderDecision(
+    val remind: Boolean,
+    val at: String,
+    val reason: String
+)
+
 class ReminderManager(private val context: Context) {
 
     companion object {
         private const val REMINDER_WORK_TAG = "blast_emst_inactivity_reminder"
+
+        /**
+         * Asks the Rust core whether a reminder is due, using the device's current UTC offset.
+         */
+        fun decide(): ReminderDecision? {
+            val offsetMinutes = ZoneId.systemDefault().rules.getOffset(Instant.now()).totalSeconds / 60
+            val decisionJson = RustBridge.nextReminder(offsetMinutes)
+            if (decisionJson.isEmpty()) return null
+            return Json.decodeFromString(decisionJson)
+        }
     }
 
     init {
@@ -47,6 +69,20 @@ class ReminderManager(private val context: Context) {
         Log.d("ReminderManager", "Inactivity check scheduled for $delayInMinutes minutes from now.")
     }
 
+    /**
+     * Schedules the next reminder check at the time chosen by the Rust core. A reminder that
+     * is already due is checked again shortly so the worker can post it.
+     */
+    fun scheduleNextReminder() {
+        val decision = decide() ?: run {
+            scheduleInactivityCheck(delayInMinutes = 1440)
+            return
+        }
+        val delayInMillis = Instant.parse(decision.at).toEpochMilli() - System.currentTimeMillis()
+        val delayInMinutes = TimeUnit.MILLISECONDS.toMinutes(delayInMillis)
+        scheduleInactivityCheck(delayInMinutes.coerceAtLeast(if (decision.remind) 10 else 1))
+    }
+
     /**
      * Cancels any pending inactivity check.
      */
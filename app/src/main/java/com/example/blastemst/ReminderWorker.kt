@@ -24,21 +24,18 @@ class ReminderWorker(appContext: Context, workerParams: WorkerParameters) :
         Log.d("ReminderWorker", "Worker triggered, checking conditions...")
 
         try {
-            // Get the user's goal from settings
-            val weeklyGoalStr = RustBridge.getSetting("goal_sessions_per_week", "5")
-            val weeklyGoal = weeklyGoalStr.toIntOrNull() ?: 5
+            // The Rust core decides whether a reminder is due and when to check next
+            val decision = ReminderManager.decide()
+            Log.d("ReminderWorker", "Reminder decision: $decision")
 
-            // Get the number of sessions completed this week
-            val sessionsThisWeek = RustBridge.getSessionCountForWeek()
-
-            Log.d("ReminderWorker", "Sessions this week: $sessionsThisWeek, Goal: $weeklyGoal")
-
-            // Only show the notification if the goal has not been met
-            if (sessionsThisWeek < weeklyGoal) {
-                Log.d("ReminderWorker", "Goal not met. Showing notification.")
-                showNotification()
-            } else {
-                Log.d("ReminderWorker", "Goal has been met. No notification will be shown.")
+            val reminderManager = ReminderManager(applicationContext)
+            when {
+                decision == null -> reminderManager.scheduleInactivityCheck(delayInMinutes = 1440)
+                decision.remind -> {
+                    showNotification()
+                    reminderManager.scheduleInactivityCheck(delayInMinutes = 1440)
+                }
+                else -> reminderManager.scheduleNextReminder()
             }
         } catch (e: Exception) {
             Log.e("ReminderWorker", "Error executing work: ${e.message}")
@@ -7,15 +7,28 @@ object RustBridge {
 
     // General functions
     external fun initDatabase(path: String)
+    // Needs a build with CARGO_FEATURES=sqlcipher; keyHex is a 256-bit keystore key as 64 hex digits
+    external fun initEncryptedDatabase(dbPath: String, keyHex: String): Boolean
 
     // Session functions
     external fun startSession(pressure_setting: Int, notes: String): Long
     external fun getAllSessions(): String
+    external fun getAllSessionsChecked(): String
     external fun getActiveSession(): String?
-    external fun endSession(sessionId: Long, notes: String)
+    external fun endSession(sessionId: Long, notes: String, utcOffsetMinutes: Int): String
     external fun getSessionCountForWeek(): Int
     external fun getLastSessionEndTime(): String
+    external fun getDailyProgress(utcOffsetMinutes: Int): String
+    external fun getWeeklyAdherence(utcOffsetMinutes: Int): String
     external fun deleteSession(sessionId: Long)
+    external fun listDeletedSessions(): String
+    external fun restoreSession(sessionId: Long)
+    // Days a deleted session stays in the trash, for every profile on the device
+    external fun getTrashRetentionDays(): Int
+    external fun setTrashRetentionDays(days: Int): Boolean
+
+    // Achievement functions
+    external fun getAchievements(): String
 
     // Rep functions
     external fun addRep(sessionId: Long)
@@ -23,7 +36,93 @@ object RustBridge {
 
     // Profile functions
     external fun getProfile(): String
-    external fun updateProfile(profileJson: String)
+    external fun updateProfile(profileJson: String): String
+    external fun listProfiles(): String
+    external fun createProfile(profileJson: String): Long
+    external fun getActiveProfileId(): Long
+    external fun switchProfile(profileId: Long): Boolean
+    external fun deleteProfile(profileId: Long)
+
+    // Trainer device functions
+    external fun getTrainerDevices(): String
+    external fun getDefaultPressure(): Int
+
+    // Pressure unit functions
+    external fun getPressureUnit(): String
+    external fun toCanonicalPressure(value: Double, unit: String): Int
+    external fun formatPressure(pressureCmH2O: Int): String
+    external fun getPressureTrend(utcOffsetMinutes: Int): String
+    external fun getScoreSummary(): String
+    external fun getSessionCadence(sessionId: Long): String
+
+    // Guided session functions. Timestamps are System.currentTimeMillis().
+    external fun startGuidance(sessionId: Long, nowMillis: Long): String
+    external fun guidanceTick(nowMillis: Long): String
+    external fun guidanceEvent(event: String, nowMillis: Long): String
+    external fun clearGuidance()
+
+    // Questionnaire functions
+    external fun getQuestionnaires(): String
+    external fun submitQuestionnaire(instrument: String, completedOn: String, answersJson: String): String
+    external fun getQuestionnaireResponses(instrument: String): String
+    external fun getQuestionnaireTrend(instrument: String): String
+
+    // Check-in functions
+    external fun saveCheckin(checkinJson: String): String
+    external fun getSessionCheckins(sessionId: Long): String
+    external fun getSafetyWarning(): String
+
+    // Snapshot functions. Snapshots are taken automatically on the configured schedule;
+    // restoreSnapshot returns "" on success, otherwise "not_found", "wrong_key" or "error",
+    // and snapshots the current state first so the restore can be undone.
+    external fun listSnapshots(): String
+    external fun takeSnapshot(): Boolean
+    external fun restoreSnapshot(snapshotId: String): String
+    external fun getSnapshotSettings(): String
+    external fun setSnapshotSettings(settingsJson: String): Boolean
+
+    // Integrity functions. Both return an IntegrityReport; repairIntegrity takes a JSON
+    // array of issue ids from checkIntegrity and checks again afterwards.
+    external fun checkIntegrity(): String
+    external fun repairIntegrity(issueIdsJson: String): String
+
+    // Backup functions. importBackup returns "" on success, otherwise "wrong_passphrase",
+    // "corrupted", "not_a_backup", "unsupported_version" or "error".
+    external fun exportBackup(path: String, passphrase: String): Boolean
+    external fun importBackup(path: String, passphrase: String): String
+
+    // Returns the active profile as an HL7 FHIR R4 Bundle (JSON)
+    external fun exportFhirBundle(): String
+    // iCalendar text with completed and planned sessions for the next `weeks` weeks, counting
+    // this one. Event UIDs are stable, so importing a newer export updates earlier events.
+    external fun exportCalendar(utcOffsetMinutes: Int, weeks: Int): String
+
+    // Clinician share bundles. exportShareBundle takes local dates (YYYY-MM-DD, inclusive)
+    // and returns "" on success, otherwise "invalid_range" or "error". verifyShareBundle
+    // returns a ShareVerification; pass "" as the fingerprint to accept any signer.
+    external fun exportShareBundle(path: String, from: String, to: String): String
+    external fun getShareKeyFingerprint(): String
+    external fun verifyShareBundle(path: String, expectedFingerprint: String): String
+
+    // CSV import. mappingJson maps columns by header name or 1-based position, e.g.
+    // {"date": "Day", "pressure": 3, "reps": "Reps", "pressure_unit": "kpa"}; "" uses the
+    // "date" and "pressure" headers. Both return a CsvImportResult; only importCsv writes.
+    external fun previewCsvImport(csv: String, mappingJson: String): String
+    external fun importCsv(csv: String, mappingJson: String): String
+
+    // Sync functions (see blast_emst_core/SYNC_PROTOCOL.md). The app sends getSyncPush's
+    // JSON to the server and passes pull responses to applySyncPull.
+    external fun configureSync(serverUrl: String, space: String): Boolean
+    external fun getSyncConfig(): String
+    external fun getSyncPush(): String
+    external fun ackSyncPush(throughSeq: Long): Boolean
+    external fun applySyncPull(responseJson: String): String
+
+    // Reminder functions
+    external fun nextReminder(utcOffsetMinutes: Int): String
+    external fun getReminderSchedule(): String
+    external fun setReminderSchedule(scheduleJson: String): Boolean
+    external fun getUpcomingReminders(utcOffsetMinutes: Int, count: Int): String
 
     // Settings functions
     external fun getSetting(key: String, defaultValue: String): String
@@ -9,7 +9,30 @@ data class Session(
     val id: Long,
     val start_time: String,
     val end_time: String?, // The question mark makes this field nullable
-    val pressure_setting: Int,
+    val pressure_setting: Int, // Always cmH2O
+    val pressure: DisplayPressure? = null,
     val notes: String,
-    val rep_count: Int
-)
\ No newline at end of file
+    val rep_count: Int,
+    val deleted_at: String? = null,
+    val score: SessionScore? = null // Set once the session has ended
+)
+
+// The session pressure converted to the user's preferred display unit.
+@Serializable
+data class DisplayPressure(
+    val value: Double,
+    val unit: String,
+    val text: String
+)
+
+// How well a completed session followed the prescription. Every component is 0-100.
+@Serializable
+data class SessionScore(
+    val total: Int,
+    val reps_score: Int,
+    val sets_score: Int,
+    val rest_score: Int,
+    val duration_score: Int,
+    val reps_completed: Int,
+    val sets_completed: Int
+)
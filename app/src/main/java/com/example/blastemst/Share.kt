@@ -0,0 +1,50 @@
+package com.example.blastemst
+
+import kotlinx.serialization.Serializable
+
+// Returned by RustBridge.verifyShareBundle. error is null on success, otherwise one of
+// "not_a_share_bundle", "unsupported_version", "bad_signature", "untrusted_key",
+// "corrupted" or "error".
+@Serializable
+data class ShareVerification(
+    val bundle: VerifiedShare? = null,
+    val error: String? = null
+)
+
+@Serializable
+data class VerifiedShare(
+    val public_key: String,
+    val fingerprint: String, // Eight groups of four hex digits, e.g. "3f2a 91c0 ..."
+    val contents: ShareContents
+)
+
+@Serializable
+data class ShareContents(
+    val version: Int,
+    val created_at: String,
+    val from: String,
+    val to: String,
+    val profile: UserProfile,
+    val targets: TrainingTargets = TrainingTargets(),
+    val sessions: List<Session> = emptyList(),
+    val stats: ShareStats
+)
+
+@Serializable
+data class ShareStats(
+    val completed_sessions: Int = 0,
+    val total_reps: Long = 0,
+    val training_days: Int = 0,
+    val sessions_per_week: Double = 0.0,
+    val first_pressure: Int? = null, // cmH2O
+    val latest_pressure: Int? = null,
+    val max_pressure: Int? = null,
+    val average_score: Double? = null
+)
+
+// per_day of 0 means only a weekly count is prescribed
+@Serializable
+data class TrainingTargets(
+    val per_day: Int = 0,
+    val per_week: Int = 5
+)
@@ -0,0 +1,19 @@
+package com.example.blastemst
+
+import kotlinx.serialization.Serializable
+
+// One entry of RustBridge.listSnapshots, newest first
+@Serializable
+data class Snapshot(
+    val id: String, // Pass to restoreSnapshot
+    val created_at: String,
+    val reason: String, // "session", "daily", "manual" or "pre_restore"
+    val size_bytes: Long
+)
+
+// schedule is "after_session", "daily" or "off"; keep is 1 to 50
+@Serializable
+data class SnapshotSettings(
+    val schedule: String = "after_session",
+    val keep: Int = 5
+)
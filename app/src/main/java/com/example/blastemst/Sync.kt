@@ -0,0 +1,30 @@
+package com.example.blastemst
+
+import kotlinx.serialization.Serializable
+import kotlinx.serialization.json.JsonElement
+
+@Serializable
+data class SyncConfig(
+    val device_id: String = "",
+    val server_url: String? = null,
+    val space: String? = null,
+    val cursor: Long = 0,
+    val pending_changes: Long = 0
+)
+
+// The parts of a getSyncPush body the app needs; the body itself is sent as-is.
+// An empty records list means everything has been pushed.
+@Serializable
+data class SyncPush(
+    val device_id: String = "",
+    val records: List<JsonElement> = emptyList(),
+    val through_seq: Long = 0
+)
+
+@Serializable
+data class SyncPullResult(
+    val applied: Int = 0,
+    val kept_local: Int = 0,
+    val skipped: Int = 0,
+    val cursor: Long = 0
+)
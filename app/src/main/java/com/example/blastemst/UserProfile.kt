@@ -9,6 +9,23 @@ data class UserProfile(
     val id: Long,
     val first_name: String,
     val last_name: String,
-    val dob: String,
-    val speech_therapist: String
+    val dob: String? = null,
+    val speech_therapist: String,
+    val therapist_phone: String = "",
+    val therapist_email: String = "",
+    val diagnosis: String? = null,
+    val diagnosis_notes: String = "",
+    val trainer_device: String = "",
+    val baseline_mep: Double? = null,
+    val treatment_start_date: String? = null,
+    // A date of birth from an older version that isn't YYYY-MM-DD; read-only
+    val dob_legacy: String? = null
+)
+
+// A validation error returned by RustBridge.updateProfile for a single profile field.
+@SuppressLint("UnsafeOptInUsageError")
+@Serializable
+data class ProfileFieldError(
+    val field: String,
+    val message: String
 )
\ No newline at end of file
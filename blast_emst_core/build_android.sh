@@ -8,6 +8,7 @@ SCRIPT_DIR=$(cd -- "$(dirname -- "${BASH_SOURCE[0]}")" &> /dev/null && pwd)
 ANDROID_PROJECT_DIR="$SCRIPT_DIR/.."
 
 echo "--- Building Rust core for Android ---"
+# Set CARGO_FEATURES=sqlcipher to build with database encryption.
 
 # MODIFIED COMMAND:
 # We now explicitly list all four targets using the "-t" flag.
@@ -18,7 +19,7 @@ cargo ndk \
     -t x86 \
     -t x86_64 \
     -o "$SCRIPT_DIR/jniLibs" \
-    build --release
+    build --lib --release ${CARGO_FEATURES:+--features "$CARGO_FEATURES"}
 
 echo "--- Copying native libraries (.so files) to Android project ---"
 # The final destination for the .so files
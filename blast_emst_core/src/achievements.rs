@@ -0,0 +1,161 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+// --- Achievements ---
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Achievement {
+    FirstSession,
+    HundredReps,
+    FourWeekStreak,
+    ProgramCompleted,
+    PressureIncreased,
+}
+
+// Profile setting for how many weeks on target make up the training program. Standard
+// EMST protocols run for five weeks.
+pub const PROGRAM_WEEKS_KEY: &str = "program_weeks";
+pub const DEFAULT_PROGRAM_WEEKS: u32 = 5;
+
+const HUNDRED_REPS: i64 = 100;
+const STREAK_WEEKS: u32 = 4;
+
+impl Achievement {
+    pub const ALL: [Achievement; 5] = [
+        Achievement::FirstSession,
+        Achievement::HundredReps,
+        Achievement::FourWeekStreak,
+        Achievement::ProgramCompleted,
+        Achievement::PressureIncreased,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Achievement::FirstSession => "first_session",
+            Achievement::HundredReps => "hundred_reps",
+            Achievement::FourWeekStreak => "four_week_streak",
+            Achievement::ProgramCompleted => "program_completed",
+            Achievement::PressureIncreased => "pressure_increased",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Achievement> {
+        Achievement::ALL.into_iter().find(|achievement| achievement.as_str() == value)
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Achievement::FirstSession => "First Breath",
+            Achievement::HundredReps => "Century",
+            Achievement::FourWeekStreak => "Four-Week Streak",
+            Achievement::ProgramCompleted => "Program Complete",
+            Achievement::PressureIncreased => "Stepping Up",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Achievement::FirstSession => "Completed your first training session",
+            Achievement::HundredReps => "Logged 100 repetitions",
+            Achievement::FourWeekStreak => "Met your weekly goal four weeks in a row",
+            Achievement::ProgramCompleted => "Met your weekly goal for every week of the program",
+            Achievement::PressureIncreased => "Trained at a higher pressure than your first session",
+        }
+    }
+}
+
+// Everything the milestones depend on, gathered from the database after a session ends.
+#[derive(Debug, Clone, Default)]
+pub struct AchievementFacts {
+    pub completed_sessions: i64,
+    pub total_reps: i64,
+    pub week_streak: u32,
+    pub weeks_on_target: u32,
+    pub program_weeks: u32,
+    pub first_pressure: Option<i32>,
+    pub max_pressure: Option<i32>,
+}
+
+// Every milestone the facts satisfy, whether or not it was unlocked before.
+pub fn earned(facts: &AchievementFacts) -> Vec<Achievement> {
+    Achievement::ALL
+        .into_iter()
+        .filter(|achievement| match achievement {
+            Achievement::FirstSession => facts.completed_sessions >= 1,
+            Achievement::HundredReps => facts.total_reps >= HUNDRED_REPS,
+            Achievement::FourWeekStreak => facts.week_streak >= STREAK_WEEKS,
+            Achievement::ProgramCompleted => facts.program_weeks > 0 && facts.weeks_on_target >= facts.program_weeks,
+            Achievement::PressureIncreased => match (facts.first_pressure, facts.max_pressure) {
+                (Some(first), Some(max)) => max > first,
+                _ => false,
+            },
+        })
+        .collect()
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AchievementStatus {
+    pub id: Achievement,
+    pub title: &'static str,
+    pub description: &'static str,
+    pub unlocked_at: Option<DateTime<Utc>>,
+}
+
+impl AchievementStatus {
+    pub fn new(achievement: Achievement, unlocked_at: Option<DateTime<Utc>>) -> Self {
+        AchievementStatus {
+            id: achievement,
+            title: achievement.title(),
+            description: achievement.description(),
+            unlocked_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nothing_is_earned_before_the_first_session() {
+        assert!(earned(&AchievementFacts { program_weeks: DEFAULT_PROGRAM_WEEKS, ..Default::default() }).is_empty());
+    }
+
+    #[test]
+    fn milestones_unlock_exactly_at_their_thresholds() {
+        let below = AchievementFacts {
+            completed_sessions: 1,
+            total_reps: HUNDRED_REPS - 1,
+            week_streak: STREAK_WEEKS - 1,
+            weeks_on_target: DEFAULT_PROGRAM_WEEKS - 1,
+            program_weeks: DEFAULT_PROGRAM_WEEKS,
+            first_pressure: Some(30),
+            max_pressure: Some(30),
+        };
+        assert_eq!(earned(&below), vec![Achievement::FirstSession]);
+
+        let at = AchievementFacts {
+            total_reps: HUNDRED_REPS,
+            week_streak: STREAK_WEEKS,
+            weeks_on_target: DEFAULT_PROGRAM_WEEKS,
+            max_pressure: Some(31),
+            ..below
+        };
+        assert_eq!(earned(&at), Achievement::ALL.to_vec());
+    }
+
+    #[test]
+    fn a_zero_week_program_is_never_completed() {
+        let facts = AchievementFacts { completed_sessions: 3, program_weeks: 0, ..Default::default() };
+        assert!(!earned(&facts).contains(&Achievement::ProgramCompleted));
+    }
+
+    #[test]
+    fn ids_round_trip() {
+        for achievement in Achievement::ALL {
+            assert_eq!(Achievement::parse(achievement.as_str()), Some(achievement));
+        }
+        assert_eq!(Achievement::parse("unknown"), None);
+    }
+}
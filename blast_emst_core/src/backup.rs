@@ -0,0 +1,416 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use chrono::{DateTime, Utc};
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, params_from_iter};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+use crate::{db, share, sync};
+
+// --- Encrypted backups ---
+//
+// Archive layout, all integers little-endian:
+//
+//   magic "EMSTBAK\0" | format version (u8) | Argon2id memory KiB, iterations, lanes (u32 each)
+//   | salt (16) | key check (16) | nonce (24) | header digest (16) | ciphertext
+//
+// The passphrase is stretched with Argon2id into 48 bytes: a 32-byte XChaCha20-Poly1305 key
+// and a 16-byte key check stored in the clear. The header digest is a truncated SHA-256 of
+// everything before it and catches damage to the KDF parameters, salt or key check without
+// needing the passphrase. With the header intact, a mismatching key check means the
+// passphrase is wrong and a failed decryption means the ciphertext was damaged. The header is
+// also authenticated as associated data.
+
+const MAGIC: &[u8; 8] = b"EMSTBAK\0";
+pub const FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const CHECK_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const DIGEST_LEN: usize = 16;
+// Where the header digest starts, after the fields it covers
+const DIGEST_OFFSET: usize = MAGIC.len() + 1 + 3 * 4 + SALT_LEN + CHECK_LEN + NONCE_LEN;
+const HEADER_LEN: usize = DIGEST_OFFSET + DIGEST_LEN;
+
+// OWASP's minimum recommendation for Argon2id; new archives record their own parameters
+const KDF_MEMORY_KIB: u32 = 19 * 1024;
+const KDF_ITERATIONS: u32 = 2;
+const KDF_LANES: u32 = 1;
+// Refuse archives asking for more than this, so a crafted file can't exhaust memory or
+// keep the phone busy for minutes
+const MAX_KDF_MEMORY_KIB: u32 = 256 * 1024;
+const MAX_KDF_ITERATIONS: u32 = 16;
+const MAX_KDF_LANES: u32 = 8;
+
+#[derive(Debug)]
+pub enum BackupError {
+    NotABackup,
+    UnsupportedVersion(u8),
+    WrongPassphrase,
+    Corrupted,
+    Kdf(String),
+    Format(serde_json::Error),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackupError::NotABackup => write!(f, "not a backup archive"),
+            BackupError::UnsupportedVersion(v) => write!(f, "unsupported backup format version {}", v),
+            BackupError::WrongPassphrase => write!(f, "wrong passphrase"),
+            BackupError::Corrupted => write!(f, "the backup is damaged"),
+            BackupError::Kdf(e) => write!(f, "key derivation failed: {}", e),
+            BackupError::Format(e) => write!(f, "invalid backup contents: {}", e),
+            BackupError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for BackupError {
+    fn from(e: rusqlite::Error) -> Self {
+        BackupError::Database(e)
+    }
+}
+
+impl From<serde_json::Error> for BackupError {
+    fn from(e: serde_json::Error) -> Self {
+        BackupError::Format(e)
+    }
+}
+
+impl BackupError {
+    // Stable identifier handed to the app
+    pub fn code(&self) -> &'static str {
+        match self {
+            BackupError::NotABackup => "not_a_backup",
+            BackupError::UnsupportedVersion(_) => "unsupported_version",
+            BackupError::WrongPassphrase => "wrong_passphrase",
+            BackupError::Corrupted | BackupError::Format(_) => "corrupted",
+            BackupError::Kdf(_) | BackupError::Database(_) => "error",
+        }
+    }
+}
+
+struct KdfParams {
+    memory_kib: u32,
+    iterations: u32,
+    lanes: u32,
+}
+
+impl KdfParams {
+    fn within_limits(&self) -> bool {
+        self.memory_kib <= MAX_KDF_MEMORY_KIB && self.iterations <= MAX_KDF_ITERATIONS && self.lanes <= MAX_KDF_LANES
+    }
+}
+
+fn header_digest(fields: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut digest = [0u8; DIGEST_LEN];
+    digest.copy_from_slice(&Sha256::digest(fields)[..DIGEST_LEN]);
+    digest
+}
+
+fn derive(passphrase: &str, salt: &[u8], kdf: &KdfParams) -> Result<[u8; KEY_LEN + CHECK_LEN], BackupError> {
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.lanes, Some(KEY_LEN + CHECK_LEN))
+        .map_err(|e| BackupError::Kdf(e.to_string()))?;
+    let mut output = [0u8; KEY_LEN + CHECK_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut output)
+        .map_err(|e| BackupError::Kdf(e.to_string()))?;
+    Ok(output)
+}
+
+// Encrypts `plaintext` into a complete archive.
+pub fn seal(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, BackupError> {
+    seal_with(plaintext, passphrase, &KdfParams { memory_kib: KDF_MEMORY_KIB, iterations: KDF_ITERATIONS, lanes: KDF_LANES })
+}
+
+fn seal_with(plaintext: &[u8], passphrase: &str, kdf: &KdfParams) -> Result<Vec<u8>, BackupError> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let derived = derive(passphrase, &salt, kdf)?;
+    let (key, check) = derived.split_at(KEY_LEN);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut archive = Vec::with_capacity(HEADER_LEN + plaintext.len() + 16);
+    archive.extend_from_slice(MAGIC);
+    archive.push(FORMAT_VERSION);
+    for value in [kdf.memory_kib, kdf.iterations, kdf.lanes] {
+        archive.extend_from_slice(&value.to_le_bytes());
+    }
+    archive.extend_from_slice(&salt);
+    archive.extend_from_slice(check);
+    archive.extend_from_slice(&nonce);
+    let digest = header_digest(&archive);
+    archive.extend_from_slice(&digest);
+
+    let cipher = XChaCha20Poly1305::new_from_slice(key).map_err(|e| BackupError::Kdf(e.to_string()))?;
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad: &archive })
+        .map_err(|_| BackupError::Kdf("encryption failed".to_string()))?;
+    archive.extend_from_slice(&ciphertext);
+    Ok(archive)
+}
+
+pub fn is_backup(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+// Verifies and decrypts an archive made by `seal`.
+pub fn open(archive: &[u8], passphrase: &str) -> Result<Vec<u8>, BackupError> {
+    if archive.len() < MAGIC.len() + 1 || &archive[..MAGIC.len()] != MAGIC {
+        return Err(BackupError::NotABackup);
+    }
+    let version = archive[MAGIC.len()];
+    if version != FORMAT_VERSION {
+        return Err(BackupError::UnsupportedVersion(version));
+    }
+    if archive.len() < HEADER_LEN {
+        return Err(BackupError::Corrupted);
+    }
+    let (header, ciphertext) = archive.split_at(HEADER_LEN);
+    if header[DIGEST_OFFSET..] != header_digest(&header[..DIGEST_OFFSET]) {
+        return Err(BackupError::Corrupted);
+    }
+    let mut offset = MAGIC.len() + 1;
+    let kdf = KdfParams {
+        memory_kib: read_u32(header, offset),
+        iterations: read_u32(header, offset + 4),
+        lanes: read_u32(header, offset + 8),
+    };
+    offset += 12;
+    if !kdf.within_limits() {
+        return Err(BackupError::Corrupted);
+    }
+    let salt = &header[offset..offset + SALT_LEN];
+    offset += SALT_LEN;
+    let stored_check = &header[offset..offset + CHECK_LEN];
+    offset += CHECK_LEN;
+    let nonce = XNonce::from_slice(&header[offset..offset + NONCE_LEN]);
+
+    let derived = derive(passphrase, salt, &kdf).map_err(|_| BackupError::Corrupted)?;
+    let (key, check) = derived.split_at(KEY_LEN);
+    if check != stored_check {
+        return Err(BackupError::WrongPassphrase);
+    }
+    let cipher = XChaCha20Poly1305::new_from_slice(key).map_err(|_| BackupError::Corrupted)?;
+    cipher
+        .decrypt(nonce, Payload { msg: ciphertext, aad: header })
+        .map_err(|_| BackupError::Corrupted)
+}
+
+// --- Database contents ---
+
+// Version of the JSON layout inside the archive
+pub const CONTENTS_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BackupContents {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    // Table name to rows, each row a map of column name to value
+    pub tables: Map<String, Value>,
+}
+
+fn user_tables(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name"
+    )?;
+    stmt.query_map([], |row| row.get(0))?.collect()
+}
+
+fn to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Value::from(f),
+        ValueRef::Text(t) => Value::from(String::from_utf8_lossy(t).into_owned()),
+        // The schema has no BLOB columns
+        ValueRef::Blob(_) => Value::Null,
+    }
+}
+
+fn to_sql(value: &Value) -> rusqlite::types::Value {
+    use rusqlite::types::Value as Sql;
+    match value {
+        Value::Null => Sql::Null,
+        Value::Bool(b) => Sql::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Sql::Integer(i),
+            None => Sql::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => Sql::Text(s.clone()),
+        other => Sql::Text(other.to_string()),
+    }
+}
+
+// Every row of every table, for all profiles. The sync change log and share signing keys
+// belong to this device and stay out.
+pub fn dump(conn: &Connection) -> Result<BackupContents, BackupError> {
+    let mut tables = Map::new();
+    for table in user_tables(conn)? {
+        if table == sync::CHANGE_LOG_TABLE || table == share::KEY_TABLE {
+            continue;
+        }
+        let mut stmt = conn.prepare(&format!("SELECT * FROM \"{}\"", table))?;
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let mut rows = stmt.query([])?;
+        let mut dumped = Vec::new();
+        while let Some(row) = rows.next()? {
+            let mut object = Map::new();
+            for (i, column) in columns.iter().enumerate() {
+                object.insert(column.clone(), to_json(row.get_ref(i)?));
+            }
+            dumped.push(Value::Object(object));
+        }
+        tables.insert(table, Value::Array(dumped));
+    }
+    Ok(BackupContents { version: CONTENTS_VERSION, exported_at: Utc::now(), tables })
+}
+
+// Replaces the contents of every table with the backup's. Columns the backup predates keep
+// their defaults and tables or columns this build doesn't know are skipped. Everything
+// happens in one transaction, so a failed restore leaves the database as it was.
+pub fn restore(conn: &Connection, contents: &BackupContents) -> Result<(), BackupError> {
+    if contents.version > CONTENTS_VERSION {
+        return Err(BackupError::UnsupportedVersion(contents.version.min(u8::MAX as u32) as u8));
+    }
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("PRAGMA defer_foreign_keys = ON", [])?;
+    let tables = user_tables(&tx)?;
+    let device_id = db::get_app_setting(&tx, sync::DEVICE_ID_KEY)?;
+    // Clear everything first; deleting a session later would cascade to restored reps
+    for table in &tables {
+        tx.execute(&format!("DELETE FROM \"{}\"", table), [])?;
+    }
+    for table in tables {
+        let Some(Value::Array(rows)) = contents.tables.get(&table) else {
+            continue;
+        };
+        let known: Vec<String> = tx
+            .prepare(&format!("SELECT * FROM \"{}\" LIMIT 0", table))?
+            .column_names()
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        for row in rows {
+            let Value::Object(object) = row else {
+                return Err(BackupError::Corrupted);
+            };
+            let columns: Vec<&String> = object.keys().filter(|column| known.contains(column)).collect();
+            if columns.is_empty() {
+                continue;
+            }
+            let names: Vec<String> = columns.iter().map(|c| format!("\"{}\"", c)).collect();
+            let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+            tx.execute(
+                &format!("INSERT INTO \"{}\" ({}) VALUES ({})", table, names.join(", "), placeholders.join(", ")),
+                params_from_iter(columns.iter().map(|c| to_sql(&object[c.as_str()]))),
+            )?;
+        }
+    }
+    sync::reset_after_restore(&tx, device_id.as_deref())?;
+    // Keys belonged to the profiles that were just replaced
+    share::forget_keys(&tx)?;
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn export_backup(conn: &Connection, passphrase: &str) -> Result<Vec<u8>, BackupError> {
+    let contents = serde_json::to_vec(&dump(conn)?)?;
+    seal(&contents, passphrase)
+}
+
+pub fn import_backup(conn: &Connection, archive: &[u8], passphrase: &str) -> Result<(), BackupError> {
+    let plaintext = open(archive, passphrase)?;
+    let contents: BackupContents = serde_json::from_slice(&plaintext)?;
+    restore(conn, &contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAINTEXT: &[u8] = b"{\"version\":1}";
+    const KDF_OFFSET: usize = MAGIC.len() + 1;
+    const SALT_OFFSET: usize = KDF_OFFSET + 12;
+    const CHECK_OFFSET: usize = SALT_OFFSET + SALT_LEN;
+
+    // Argon2's minimum cost, so the tests don't spend their time deriving keys
+    fn cheap() -> KdfParams {
+        KdfParams { memory_kib: 8, iterations: 1, lanes: 1 }
+    }
+
+    fn sealed() -> Vec<u8> {
+        seal_with(PLAINTEXT, "correct horse", &cheap()).unwrap()
+    }
+
+    fn flipped(mut archive: Vec<u8>, offset: usize) -> Vec<u8> {
+        archive[offset] ^= 0x01;
+        archive
+    }
+
+    // Rewrites a header field and its digest, as a crafted file would
+    fn with_u32(mut archive: Vec<u8>, offset: usize, value: u32) -> Vec<u8> {
+        archive[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        let digest = header_digest(&archive[..DIGEST_OFFSET]);
+        archive[DIGEST_OFFSET..HEADER_LEN].copy_from_slice(&digest);
+        archive
+    }
+
+    #[test]
+    fn round_trips_with_the_right_passphrase() {
+        let archive = sealed();
+        assert!(is_backup(&archive));
+        assert_eq!(archive[MAGIC.len()], FORMAT_VERSION);
+        assert_eq!(open(&archive, "correct horse").unwrap(), PLAINTEXT);
+    }
+
+    #[test]
+    fn other_format_versions_are_unsupported() {
+        let mut archive = sealed();
+        archive[MAGIC.len()] = FORMAT_VERSION + 1;
+        assert!(matches!(open(&archive, "correct horse"), Err(BackupError::UnsupportedVersion(2))));
+    }
+
+    #[test]
+    fn wrong_passphrase_is_reported_as_such() {
+        assert!(matches!(open(&sealed(), "battery staple"), Err(BackupError::WrongPassphrase)));
+    }
+
+    #[test]
+    fn damaged_ciphertext_is_corrupted() {
+        let archive = sealed();
+        let last = archive.len() - 1;
+        assert!(matches!(open(&flipped(archive.clone(), HEADER_LEN), "correct horse"), Err(BackupError::Corrupted)));
+        assert!(matches!(open(&flipped(archive.clone(), last), "correct horse"), Err(BackupError::Corrupted)));
+        assert!(matches!(open(&archive[..HEADER_LEN - 1], "correct horse"), Err(BackupError::Corrupted)));
+    }
+
+    #[test]
+    fn damaged_header_is_corrupted_rather_than_a_wrong_passphrase() {
+        for offset in [KDF_OFFSET + 4, SALT_OFFSET, CHECK_OFFSET, CHECK_OFFSET + CHECK_LEN, DIGEST_OFFSET] {
+            let result = open(&flipped(sealed(), offset), "correct horse");
+            assert!(matches!(result, Err(BackupError::Corrupted)), "byte {} gave {:?}", offset, result);
+        }
+        assert!(matches!(open(&flipped(sealed(), 0), "correct horse"), Err(BackupError::NotABackup)));
+    }
+
+    #[test]
+    fn kdf_parameters_past_the_limits_are_refused() {
+        for (offset, value) in [(KDF_OFFSET, MAX_KDF_MEMORY_KIB + 1), (KDF_OFFSET + 4, MAX_KDF_ITERATIONS + 1), (KDF_OFFSET + 8, MAX_KDF_LANES + 1)] {
+            let archive = with_u32(sealed(), offset, value);
+            assert!(matches!(open(&archive, "correct horse"), Err(BackupError::Corrupted)));
+        }
+        assert!(matches!(open(&with_u32(sealed(), KDF_OFFSET + 8, 0), "correct horse"), Err(BackupError::Corrupted)));
+    }
+}
@@ -0,0 +1,97 @@
+// Combined adherence for a clinician's patients, from the share bundles and backups their
+// apps export.
+//
+//   clinician_dashboard [--weeks N] [--html OUT] [--trust FINGERPRINT]... [--passphrase P] FILE...
+//
+// Without --html the report is printed to the terminal. --trust may be repeated; when given,
+// share bundles signed by any other key are refused. The passphrase is used for every
+// backup among the files and can also be given as EMST_BACKUP_PASSPHRASE.
+
+use blast_emst_core::dashboard;
+use chrono::Local;
+use std::path::PathBuf;
+use std::process;
+
+const DEFAULT_WEEKS: u32 = 8;
+
+struct Options {
+    files: Vec<PathBuf>,
+    weeks: u32,
+    html: Option<PathBuf>,
+    trusted: Vec<String>,
+    passphrase: Option<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        files: Vec::new(),
+        weeks: DEFAULT_WEEKS,
+        html: None,
+        trusted: Vec::new(),
+        passphrase: std::env::var("EMST_BACKUP_PASSPHRASE").ok().filter(|p| !p.is_empty()),
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--weeks" => {
+                options.weeks = value()?.parse().ok().filter(|w| (1..=52).contains(w)).ok_or("--weeks must be 1 to 52")?
+            }
+            "--html" => options.html = Some(PathBuf::from(value()?)),
+            "--trust" => options.trusted.push(value()?),
+            "--passphrase" => options.passphrase = Some(value()?),
+            _ if arg.starts_with("--") => return Err(format!("unknown argument {}", arg)),
+            _ => options.files.push(PathBuf::from(arg)),
+        }
+    }
+    if options.files.is_empty() {
+        return Err("no files given".to_string());
+    }
+    Ok(options)
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!(
+            "{}\nusage: clinician_dashboard [--weeks N] [--html OUT] [--trust FINGERPRINT]... [--passphrase P] FILE...",
+            e
+        );
+        process::exit(2);
+    });
+
+    let mut patients = Vec::new();
+    let mut failed = false;
+    for path in &options.files {
+        let source = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let loaded = std::fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| {
+                dashboard::load(&source, &bytes, options.passphrase.as_deref(), &options.trusted).map_err(|e| e.to_string())
+            });
+        match loaded {
+            Ok(found) => patients.extend(found),
+            Err(e) => {
+                eprintln!("skipping {}: {}", path.display(), e);
+                failed = true;
+            }
+        }
+    }
+
+    let now = Local::now();
+    let mut summaries: Vec<_> = patients.iter().map(|patient| dashboard::summarize(patient, now, options.weeks)).collect();
+    summaries.sort_by_key(|summary| summary.name.to_lowercase());
+
+    match &options.html {
+        Some(out) => {
+            if let Err(e) = std::fs::write(out, dashboard::render_html(&summaries, now)) {
+                eprintln!("cannot write {}: {}", out.display(), e);
+                process::exit(1);
+            }
+            eprintln!("wrote {} patients to {}", summaries.len(), out.display());
+        }
+        None => print!("{}", dashboard::render_terminal(&summaries)),
+    }
+    if failed {
+        process::exit(1);
+    }
+}
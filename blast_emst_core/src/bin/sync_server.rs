@@ -0,0 +1,85 @@
+// Reference server for the sync protocol in SYNC_PROTOCOL.md.
+//
+//   sync_server [--addr 0.0.0.0:8080] [--db sync.sqlite] [--token SECRET]
+//
+// The token can also be given as SYNC_SERVER_TOKEN. Serve it behind a TLS-terminating
+// reverse proxy; the server itself only speaks plain HTTP.
+
+use blast_emst_core::sync_server::{Request, SyncServer, SyncStore};
+use std::io::Read;
+use std::path::PathBuf;
+use std::process;
+
+// Pushes are at most a few hundred small records
+const MAX_BODY_BYTES: u64 = 8 * 1024 * 1024;
+
+struct Options {
+    addr: String,
+    db: PathBuf,
+    token: Option<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        addr: "0.0.0.0:8080".to_string(),
+        db: PathBuf::from("sync.sqlite"),
+        token: std::env::var("SYNC_SERVER_TOKEN").ok().filter(|token| !token.is_empty()),
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--addr" => options.addr = value()?,
+            "--db" => options.db = PathBuf::from(value()?),
+            "--token" => options.token = Some(value()?),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\nusage: sync_server [--addr HOST:PORT] [--db PATH] [--token SECRET]", e);
+        process::exit(2);
+    });
+    let store = SyncStore::open(&options.db).unwrap_or_else(|e| {
+        eprintln!("cannot open {}: {}", options.db.display(), e);
+        process::exit(1);
+    });
+    if options.token.is_none() {
+        eprintln!("warning: no token set, anyone who can reach the server can read and write every space");
+    }
+    let server = SyncServer::new(store, options.token);
+    let http = tiny_http::Server::http(&options.addr).unwrap_or_else(|e| {
+        eprintln!("cannot listen on {}: {}", options.addr, e);
+        process::exit(1);
+    });
+    eprintln!("sync server listening on {}", options.addr);
+
+    let content_type = tiny_http::Header::from_bytes("Content-Type", "application/json").unwrap();
+    for mut request in http.incoming_requests() {
+        let mut body = String::new();
+        if request.as_reader().take(MAX_BODY_BYTES).read_to_string(&mut body).is_err() {
+            let _ = request.respond(tiny_http::Response::empty(400));
+            continue;
+        }
+        let authorization = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Authorization"))
+            .map(|header| header.value.to_string());
+        let method = request.method().to_string();
+        let response = server.handle(&Request {
+            method: &method,
+            url: request.url(),
+            authorization: authorization.as_deref(),
+            body: &body,
+        });
+        let _ = request.respond(
+            tiny_http::Response::from_string(response.body)
+                .with_status_code(response.status)
+                .with_header(content_type.clone()),
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::scoring::{self, Prescription};
+
+// --- Rep cadence ---
+
+// One pause between two consecutive breaths
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RepInterval {
+    // Index of the breath that ended the pause, counting from 0
+    pub rep_index: usize,
+    pub seconds: i64,
+    // Whether the pause separates two sets rather than two breaths of the same set
+    pub set_boundary: bool,
+    // A pause within a set shorter than the prescribed rep rest
+    pub rushed: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SetCadence {
+    pub set_number: usize,
+    pub reps: usize,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub mean_interval_seconds: Option<f64>,
+    pub rushed_reps: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct IntervalStats {
+    pub count: usize,
+    pub mean_seconds: Option<f64>,
+    pub median_seconds: Option<f64>,
+    pub min_seconds: Option<i64>,
+    pub max_seconds: Option<i64>,
+    pub std_dev_seconds: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SessionCadence {
+    pub session_id: i64,
+    pub reps: usize,
+    pub intervals: Vec<RepInterval>,
+    // Statistics over the pauses within sets; rests between sets are left out
+    pub within_set: IntervalStats,
+    pub between_sets: IntervalStats,
+    pub sets: Vec<SetCadence>,
+    pub rushed_reps: usize,
+    pub rep_rest_seconds: u32,
+    pub set_gap_seconds: u32,
+}
+
+fn stats(seconds: &[i64]) -> IntervalStats {
+    if seconds.is_empty() {
+        return IntervalStats::default();
+    }
+    let count = seconds.len();
+    let mean = seconds.iter().sum::<i64>() as f64 / count as f64;
+    let mut sorted = seconds.to_vec();
+    sorted.sort_unstable();
+    let median = if count.is_multiple_of(2) {
+        (sorted[count / 2 - 1] + sorted[count / 2]) as f64 / 2.0
+    } else {
+        sorted[count / 2] as f64
+    };
+    let variance = seconds.iter().map(|s| (*s as f64 - mean).powi(2)).sum::<f64>() / count as f64;
+    IntervalStats {
+        count,
+        mean_seconds: Some(mean),
+        median_seconds: Some(median),
+        min_seconds: sorted.first().copied(),
+        max_seconds: sorted.last().copied(),
+        std_dev_seconds: Some(variance.sqrt()),
+    }
+}
+
+// Analyzes the rep timestamps of a session (oldest first). Sets are split the same way
+// the session score splits them.
+pub fn analyze(session_id: i64, rep_times: &[DateTime<Utc>], prescription: &Prescription) -> SessionCadence {
+    let rep_rest = prescription.rep_rest_seconds as i64;
+    let sets = scoring::split_into_sets(rep_times, prescription);
+
+    let mut intervals = Vec::new();
+    let mut set_cadence = Vec::new();
+    let mut rep_index = 0;
+    for (i, set) in sets.iter().enumerate() {
+        if i > 0 {
+            let previous_end = *sets[i - 1].last().unwrap_or(&set[0]);
+            intervals.push(RepInterval {
+                rep_index,
+                seconds: (set[0] - previous_end).num_seconds(),
+                set_boundary: true,
+                rushed: false,
+            });
+        }
+        let pauses: Vec<i64> = set.windows(2).map(|pair| (pair[1] - pair[0]).num_seconds()).collect();
+        for (j, seconds) in pauses.iter().enumerate() {
+            intervals.push(RepInterval {
+                rep_index: rep_index + j + 1,
+                seconds: *seconds,
+                set_boundary: false,
+                rushed: *seconds < rep_rest,
+            });
+        }
+        set_cadence.push(SetCadence {
+            set_number: i + 1,
+            reps: set.len(),
+            start: set[0],
+            end: *set.last().unwrap_or(&set[0]),
+            mean_interval_seconds: stats(&pauses).mean_seconds,
+            rushed_reps: pauses.iter().filter(|seconds| **seconds < rep_rest).count(),
+        });
+        rep_index += set.len();
+    }
+
+    let within: Vec<i64> = intervals.iter().filter(|interval| !interval.set_boundary).map(|interval| interval.seconds).collect();
+    let between: Vec<i64> = intervals.iter().filter(|interval| interval.set_boundary).map(|interval| interval.seconds).collect();
+    SessionCadence {
+        session_id,
+        reps: rep_times.len(),
+        rushed_reps: intervals.iter().filter(|interval| interval.rushed).count(),
+        within_set: stats(&within),
+        between_sets: stats(&between),
+        intervals,
+        sets: set_cadence,
+        rep_rest_seconds: prescription.rep_rest_seconds,
+        set_gap_seconds: prescription.set_gap_seconds(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    // Rep times separated by the given pauses in seconds
+    fn times(pauses: &[i64]) -> Vec<DateTime<Utc>> {
+        let mut times = vec![Utc.with_ymd_and_hms(2024, 5, 15, 9, 0, 0).unwrap()];
+        for pause in pauses {
+            times.push(*times.last().unwrap() + Duration::seconds(*pause));
+        }
+        times
+    }
+
+    #[test]
+    fn no_reps_or_a_single_rep_has_no_intervals() {
+        let prescription = Prescription::default();
+        let empty = analyze(1, &[], &prescription);
+        assert_eq!((empty.reps, empty.intervals.len(), empty.sets.len()), (0, 0, 0));
+        assert_eq!(empty.within_set, IntervalStats::default());
+
+        let single = analyze(1, &times(&[]), &prescription);
+        assert_eq!((single.reps, single.intervals.len(), single.sets.len()), (1, 0, 1));
+        assert_eq!(single.sets[0].mean_interval_seconds, None);
+    }
+
+    #[test]
+    fn rushed_means_strictly_shorter_than_the_rep_rest() {
+        let prescription = Prescription::default();
+        let rest = prescription.rep_rest_seconds as i64;
+        let cadence = analyze(1, &times(&[rest, rest - 1, rest + 1]), &prescription);
+        let rushed: Vec<bool> = cadence.intervals.iter().map(|interval| interval.rushed).collect();
+        assert_eq!(rushed, vec![false, true, false]);
+        assert_eq!((cadence.rushed_reps, cadence.sets[0].rushed_reps), (1, 1));
+    }
+
+    #[test]
+    fn set_boundaries_are_kept_out_of_within_set_stats() {
+        let prescription = Prescription::default();
+        let gap = prescription.set_gap_seconds() as i64;
+        let cadence = analyze(1, &times(&[10, 20, gap, 12]), &prescription);
+        assert_eq!(cadence.sets.iter().map(|set| set.reps).collect::<Vec<_>>(), vec![3, 2]);
+        let boundary = &cadence.intervals[2];
+        assert_eq!((boundary.rep_index, boundary.seconds, boundary.set_boundary, boundary.rushed), (3, gap, true, false));
+        assert_eq!(cadence.within_set.count, 3);
+        assert_eq!(cadence.within_set.median_seconds, Some(12.0));
+        assert_eq!((cadence.within_set.min_seconds, cadence.within_set.max_seconds), (Some(10), Some(20)));
+        assert_eq!(cadence.between_sets.mean_seconds, Some(gap as f64));
+    }
+
+    #[test]
+    fn even_counts_take_the_middle_pair_for_the_median() {
+        let result = stats(&[20, 10, 40, 30]);
+        assert_eq!((result.median_seconds, result.mean_seconds), (Some(25.0), Some(25.0)));
+        assert_eq!(stats(&[15, 15]).std_dev_seconds, Some(0.0));
+    }
+}
@@ -0,0 +1,327 @@
+use chrono::{DateTime, Datelike, Days, Duration, NaiveDate, SecondsFormat, TimeZone, Utc};
+
+use crate::db::Session;
+use crate::reminders::ReminderSchedule;
+use crate::stats::{self, TrainingTargets};
+
+// --- iCalendar export ---
+//
+// Completed sessions become timed events, and the sessions still to come become tentative
+// ones: at the reminder schedule's times when the user has set any, otherwise spread over
+// the week to meet the weekly goal. Every event's UID is derived from what it describes
+// (the session's sync uuid, or the profile and planned day) so importing a newer export
+// updates the events already in the calendar instead of adding copies.
+
+const PRODID: &str = "-//Blast EMST//Training Calendar//EN";
+const UID_DOMAIN: &str = "blast-emst";
+// How long a planned session at a scheduled time is shown for
+const PLANNED_MINUTES: i64 = 15;
+// RFC 5545 limits content lines to 75 octets, excluding the line break
+const MAX_LINE_OCTETS: usize = 75;
+// How far ahead sessions are planned, in weeks. Requests outside 1..=MAX_WEEKS are clamped.
+pub const MAX_WEEKS: u32 = 52;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventTime {
+    Instant(DateTime<Utc>),
+    // All-day events, as planned sessions without a scheduled time
+    Date(NaiveDate),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub uid: String,
+    pub start: EventTime,
+    // Exclusive, so an all-day event ends the day after it starts
+    pub end: EventTime,
+    pub summary: String,
+    pub description: String,
+    pub tentative: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedSession {
+    pub date: NaiveDate,
+    // Set when the session comes from a reminder schedule rule
+    pub at: Option<DateTime<Utc>>,
+    pub sessions: u32,
+}
+
+// Sessions planned from now through the end of the `weeks`th week, counting the current
+// one. `history` holds end times of completed sessions and needs to cover the current week.
+pub fn plan<Tz: TimeZone>(
+    now: &DateTime<Tz>,
+    history: &[DateTime<Utc>],
+    targets: &TrainingTargets,
+    schedule: &ReminderSchedule,
+    weeks: u32,
+) -> Vec<PlannedSession> {
+    let weeks = weeks.clamp(1, MAX_WEEKS);
+    if schedule.rules.is_empty() {
+        plan_from_goal(now, history, targets, schedule, weeks)
+    } else {
+        plan_from_schedule(now, schedule, weeks)
+    }
+}
+
+fn plan_from_schedule<Tz: TimeZone>(now: &DateTime<Tz>, schedule: &ReminderSchedule, weeks: u32) -> Vec<PlannedSession> {
+    let this_week = stats::week_start(now.date_naive());
+    let days = (weeks as u64 * 7).saturating_sub((now.date_naive() - this_week).num_days() as u64);
+    schedule
+        .reminders_within(now, days)
+        .into_iter()
+        .map(|at| PlannedSession { date: at.with_timezone(&now.timezone()).date_naive(), at: Some(at), sessions: 1 })
+        .collect()
+}
+
+fn plan_from_goal<Tz: TimeZone>(
+    now: &DateTime<Tz>,
+    history: &[DateTime<Utc>],
+    targets: &TrainingTargets,
+    schedule: &ReminderSchedule,
+    weeks: u32,
+) -> Vec<PlannedSession> {
+    let today = now.date_naive();
+    let this_week = stats::week_start(today);
+    let per_day = targets.per_day.max(1);
+    // Today stays open until its sessions are done
+    let first_open = if stats::daily_progress(now, history, targets).completed >= per_day { today + Days::new(1) } else { today };
+    let mut planned = Vec::new();
+    for week in 0..weeks as u64 {
+        let start = this_week + Days::new(7 * week);
+        let remaining = if week == 0 {
+            targets.weekly_target().saturating_sub(stats::weekly_adherence(now, history, targets).counted)
+        } else {
+            targets.weekly_target()
+        };
+        let open: Vec<NaiveDate> = (0..7)
+            .map(|offset| start + Days::new(offset))
+            .filter(|date| *date >= first_open && !schedule.rest_days.contains(&date.weekday()))
+            .collect();
+        planned.extend(spread(remaining, per_day, &open));
+    }
+    planned
+}
+
+// Spreads `remaining` sessions evenly over the open days, at most `per_day` on each unless
+// there are too few days left to fit them.
+fn spread(remaining: u32, per_day: u32, open: &[NaiveDate]) -> Vec<PlannedSession> {
+    let days = remaining.div_ceil(per_day).min(open.len() as u32) as usize;
+    if days == 0 {
+        return Vec::new();
+    }
+    let (base, extra) = (remaining / days as u32, remaining % days as u32);
+    (0..days)
+        .map(|i| PlannedSession {
+            date: open[i * open.len() / days],
+            at: None,
+            sessions: base + u32::from((i as u32) < extra),
+        })
+        .collect()
+}
+
+pub fn completed_event(uuid: &str, session: &Session) -> Option<Event> {
+    let end = session.end_time?;
+    let mut description = format!("{} reps at {}", session.rep_count, session.pressure.text);
+    if let Some(score) = &session.score {
+        description.push_str(&format!("\nScore: {}/100", score.total));
+    }
+    if !session.notes.trim().is_empty() {
+        description.push_str(&format!("\n{}", session.notes.trim()));
+    }
+    Some(Event {
+        uid: format!("session-{}@{}", uuid, UID_DOMAIN),
+        start: EventTime::Instant(session.start_time),
+        end: EventTime::Instant(end.max(session.start_time)),
+        summary: "EMST session".to_string(),
+        description,
+        tentative: false,
+    })
+}
+
+// `pressure` is the latest session's, shown as the one to train at.
+pub fn planned_event(profile_id: i64, planned: &PlannedSession, targets: &TrainingTargets, pressure: Option<&str>) -> Event {
+    let (uid, start, end) = match planned.at {
+        Some(at) => (
+            format!("planned-{}-{}@{}", profile_id, at.format("%Y%m%dT%H%M%SZ"), UID_DOMAIN),
+            EventTime::Instant(at),
+            EventTime::Instant(at + Duration::minutes(PLANNED_MINUTES)),
+        ),
+        None => (
+            format!("planned-{}-{}@{}", profile_id, planned.date.format("%Y%m%d"), UID_DOMAIN),
+            EventTime::Date(planned.date),
+            EventTime::Date(planned.date + Days::new(1)),
+        ),
+    };
+    let summary = match planned.sessions {
+        1 => "Planned EMST session".to_string(),
+        n => format!("Planned EMST sessions ({})", n),
+    };
+    let mut description = format!("Weekly goal: {} sessions", targets.weekly_target());
+    if let Some(pressure) = pressure {
+        description.push_str(&format!("\nPressure: {}", pressure));
+    }
+    Event { uid, start, end, summary, description, tentative: true }
+}
+
+pub fn render(name: &str, events: &[Event], now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODID),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", escape(&calendar_name(name))),
+    ];
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", event.uid));
+        lines.push(format!("DTSTAMP:{}", utc(now)));
+        lines.push(property("DTSTART", &event.start));
+        lines.push(property("DTEND", &event.end));
+        lines.push(format!("SUMMARY:{}", escape(&event.summary)));
+        lines.push(format!("DESCRIPTION:{}", escape(&event.description)));
+        lines.push(format!("STATUS:{}", if event.tentative { "TENTATIVE" } else { "CONFIRMED" }));
+        if matches!(event.start, EventTime::Date(_)) {
+            lines.push("TRANSP:TRANSPARENT".to_string());
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold(line) + "\r\n").collect()
+}
+
+fn calendar_name(name: &str) -> String {
+    match name.trim() {
+        "" => "EMST training".to_string(),
+        name => format!("EMST training – {}", name),
+    }
+}
+
+fn utc(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true).replace(['-', ':'], "")
+}
+
+fn property(name: &str, time: &EventTime) -> String {
+    match time {
+        EventTime::Instant(at) => format!("{}:{}", name, utc(*at)),
+        EventTime::Date(date) => format!("{};VALUE=DATE:{}", name, date.format("%Y%m%d")),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\n', '\r'], "\\n")
+}
+
+// Splits long lines into continuation lines starting with a space, without breaking a
+// UTF-8 character apart.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reminders::ReminderRule;
+    use crate::units::{DisplayPressure, Pressure, PressureUnit};
+    use chrono::{FixedOffset, NaiveTime, Weekday};
+
+    // Wednesday 15 May 2024, 10:00 in UTC+2
+    fn wednesday_morning() -> DateTime<FixedOffset> {
+        FixedOffset::east_opt(2 * 3600).unwrap().with_ymd_and_hms(2024, 5, 15, 10, 0, 0).unwrap()
+    }
+
+    fn may(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, day).unwrap()
+    }
+
+    fn session(notes: &str) -> Session {
+        let start = Utc.with_ymd_and_hms(2024, 5, 14, 7, 0, 0).unwrap();
+        Session {
+            id: 7,
+            start_time: start,
+            end_time: Some(start + Duration::minutes(12)),
+            pressure_setting: 30,
+            pressure: DisplayPressure::new(Pressure::from_cmh2o(30.0), PressureUnit::CmH2O),
+            notes: notes.to_string(),
+            rep_count: 25,
+            deleted_at: None,
+            score: None,
+        }
+    }
+
+    #[test]
+    fn completed_sessions_keep_their_uid_across_exports() {
+        let event = completed_event("0b1c", &session("felt easy; more, tomorrow")).unwrap();
+        let first = render("Ann", std::slice::from_ref(&event), Utc::now());
+        let second = render("Ann", &[event], Utc::now() + Duration::days(3));
+        let uid = |ics: &str| ics.lines().find(|line| line.starts_with("UID:")).map(str::to_string);
+        assert_eq!(uid(&first), Some("UID:session-0b1c@blast-emst".to_string()));
+        assert_eq!(uid(&first), uid(&second));
+        assert!(first.contains("DTSTART:20240514T070000Z\r\nDTEND:20240514T071200Z\r\n"));
+        assert!(first.contains("DESCRIPTION:25 reps at 30 cmH2O\\nfelt easy\\; more\\, tomorrow\r\n"));
+    }
+
+    #[test]
+    fn long_lines_are_folded_on_character_boundaries() {
+        let event = completed_event("0b1c", &session(&"é".repeat(60))).unwrap();
+        let ics = render("Ann", &[event], Utc::now());
+        assert!(ics.split("\r\n").all(|line| line.len() <= MAX_LINE_OCTETS));
+        let unfolded = ics.replace("\r\n ", "");
+        assert!(unfolded.contains(&"é".repeat(60)));
+    }
+
+    #[test]
+    fn goal_spreads_the_rest_of_the_week_and_later_weeks() {
+        // One session done on Monday; four more needed from Wednesday, Sunday is a rest day
+        let history = [Utc.with_ymd_and_hms(2024, 5, 13, 7, 0, 0).unwrap()];
+        let targets = TrainingTargets { per_day: 0, per_week: 5 };
+        let schedule = ReminderSchedule { rest_days: vec![Weekday::Sun], ..Default::default() };
+        let planned = plan(&wednesday_morning(), &history, &targets, &schedule, 2);
+        let this_week: Vec<_> = planned.iter().filter(|p| p.date < may(20)).map(|p| (p.date, p.sessions)).collect();
+        assert_eq!(this_week, vec![(may(15), 1), (may(16), 1), (may(17), 1), (may(18), 1)]);
+        let next_week: Vec<_> = planned.iter().filter(|p| p.date >= may(20)).map(|p| p.date).collect();
+        assert_eq!(next_week, vec![may(20), may(21), may(22), may(23), may(24)]);
+        assert!(planned.iter().all(|p| p.at.is_none()));
+    }
+
+    #[test]
+    fn schedule_times_are_used_when_set() {
+        let schedule = ReminderSchedule {
+            rules: vec![ReminderRule { time: NaiveTime::from_hms_opt(8, 0, 0).unwrap(), weekdays: vec![Weekday::Mon, Weekday::Wed] }],
+            ..Default::default()
+        };
+        let planned = plan(&wednesday_morning(), &[], &TrainingTargets::default(), &schedule, 2);
+        // This morning's slot has passed; next Monday and Wednesday remain
+        let dates: Vec<_> = planned.iter().map(|p| p.date).collect();
+        assert_eq!(dates, vec![may(20), may(22)]);
+        let event = planned_event(3, &planned[0], &TrainingTargets::default(), Some("30 cmH2O"));
+        assert_eq!(event.uid, "planned-3-20240520T060000Z@blast-emst");
+        assert_eq!(event.end, EventTime::Instant(planned[0].at.unwrap() + Duration::minutes(PLANNED_MINUTES)));
+    }
+
+    #[test]
+    fn planning_is_clamped_to_a_year() {
+        let targets = TrainingTargets { per_day: 1, per_week: 0 };
+        let schedule = ReminderSchedule::default();
+        let planned = plan(&wednesday_morning(), &[], &targets, &schedule, u32::MAX);
+        assert_eq!(planned.last().map(|p| p.date), Some(stats::week_start(may(15)) + Days::new(7 * MAX_WEEKS as u64 - 1)));
+        // Asking for no weeks still plans the rest of this one
+        let planned = plan(&wednesday_morning(), &[], &targets, &schedule, 0);
+        assert_eq!(planned.iter().map(|p| p.date).collect::<Vec<_>>(), (15..=19).map(may).collect::<Vec<_>>());
+    }
+}
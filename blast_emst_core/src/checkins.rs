@@ -0,0 +1,249 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::profile::FieldError;
+
+// --- Wellbeing check-ins ---
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckinKind {
+    // Before the first breath of a session
+    Pre,
+    // After the session has ended
+    Post,
+}
+
+impl CheckinKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckinKind::Pre => "pre",
+            CheckinKind::Post => "post",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<CheckinKind> {
+        match value {
+            "pre" => Some(CheckinKind::Pre),
+            "post" => Some(CheckinKind::Post),
+            _ => None,
+        }
+    }
+}
+
+// Fatigue and breathlessness are rated 0-10; perceived exertion uses the Borg RPE scale
+// (6 = no exertion, 20 = maximal) and is usually only asked after the session.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Checkin {
+    pub session_id: i64,
+    pub kind: CheckinKind,
+    pub fatigue: u8,
+    pub breathlessness: u8,
+    pub dizziness: bool,
+    #[serde(default)]
+    pub perceived_exertion: Option<u8>,
+    #[serde(default)]
+    pub notes: String,
+    // Filled in when the check-in is saved
+    #[serde(default)]
+    pub flags: Vec<AdverseFlag>,
+    #[serde(default)]
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdverseFlag {
+    Dizziness,
+    SevereBreathlessness,
+    SevereFatigue,
+    VeryHardExertion,
+}
+
+impl AdverseFlag {
+    pub fn message(&self) -> &'static str {
+        match self {
+            AdverseFlag::Dizziness => "You reported feeling dizzy",
+            AdverseFlag::SevereBreathlessness => "You reported severe breathlessness",
+            AdverseFlag::SevereFatigue => "You reported severe fatigue",
+            AdverseFlag::VeryHardExertion => "You rated the session as very hard",
+        }
+    }
+}
+
+const MAX_RATING: u8 = 10;
+const MIN_BORG: u8 = 6;
+const MAX_BORG: u8 = 20;
+const SEVERE_BREATHLESSNESS: u8 = 7;
+const SEVERE_FATIGUE: u8 = 8;
+// "Very hard" on the Borg scale; EMST should be effortful but not near maximal
+const VERY_HARD_EXERTION: u8 = 17;
+
+#[derive(Debug)]
+pub enum CheckinError {
+    Invalid(Vec<FieldError>),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for CheckinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckinError::Invalid(errors) => {
+                let fields: Vec<&str> = errors.iter().map(|e| e.field).collect();
+                write!(f, "invalid check-in fields: {}", fields.join(", "))
+            }
+            CheckinError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for CheckinError {
+    fn from(e: rusqlite::Error) -> Self {
+        CheckinError::Database(e)
+    }
+}
+
+impl CheckinError {
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        match self {
+            CheckinError::Invalid(errors) => errors.clone(),
+            CheckinError::Database(e) => vec![FieldError { field: "checkin", message: e.to_string() }],
+        }
+    }
+}
+
+pub fn validate_checkin(checkin: &Checkin) -> Result<(), CheckinError> {
+    let mut errors = Vec::new();
+    for (field, value) in [("fatigue", checkin.fatigue), ("breathlessness", checkin.breathlessness)] {
+        if value > MAX_RATING {
+            errors.push(FieldError { field, message: format!("Must be between 0 and {}", MAX_RATING) });
+        }
+    }
+    if let Some(exertion) = checkin.perceived_exertion
+        && !(MIN_BORG..=MAX_BORG).contains(&exertion)
+    {
+        errors.push(FieldError {
+            field: "perceived_exertion",
+            message: format!("Must be between {} and {}", MIN_BORG, MAX_BORG),
+        });
+    }
+    if errors.is_empty() { Ok(()) } else { Err(CheckinError::Invalid(errors)) }
+}
+
+pub fn adverse_flags(checkin: &Checkin) -> Vec<AdverseFlag> {
+    let mut flags = Vec::new();
+    if checkin.dizziness {
+        flags.push(AdverseFlag::Dizziness);
+    }
+    if checkin.breathlessness >= SEVERE_BREATHLESSNESS {
+        flags.push(AdverseFlag::SevereBreathlessness);
+    }
+    if checkin.fatigue >= SEVERE_FATIGUE {
+        flags.push(AdverseFlag::SevereFatigue);
+    }
+    if checkin.perceived_exertion.is_some_and(|exertion| exertion >= VERY_HARD_EXERTION) {
+        flags.push(AdverseFlag::VeryHardExertion);
+    }
+    flags
+}
+
+// Shown before the next session when the latest check-in raised a flag.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SafetyWarning {
+    pub session_id: i64,
+    pub kind: CheckinKind,
+    pub recorded_at: Option<DateTime<Utc>>,
+    pub flags: Vec<AdverseFlag>,
+    pub messages: Vec<&'static str>,
+    pub advice: &'static str,
+}
+
+const SAFETY_ADVICE: &str = "Rest before training again and contact your therapist if the symptoms persist.";
+
+pub fn safety_warning(latest: &Checkin) -> Option<SafetyWarning> {
+    if latest.flags.is_empty() {
+        return None;
+    }
+    Some(SafetyWarning {
+        session_id: latest.session_id,
+        kind: latest.kind,
+        recorded_at: latest.recorded_at,
+        flags: latest.flags.clone(),
+        messages: latest.flags.iter().map(|flag| flag.message()).collect(),
+        advice: SAFETY_ADVICE,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkin() -> Checkin {
+        Checkin {
+            session_id: 1,
+            kind: CheckinKind::Post,
+            fatigue: 0,
+            breathlessness: 0,
+            dizziness: false,
+            perceived_exertion: Some(MIN_BORG),
+            notes: String::new(),
+            flags: Vec::new(),
+            recorded_at: None,
+        }
+    }
+
+    fn invalid_fields(checkin: &Checkin) -> Vec<&'static str> {
+        match validate_checkin(checkin) {
+            Ok(()) => Vec::new(),
+            Err(e) => e.field_errors().into_iter().map(|error| error.field).collect(),
+        }
+    }
+
+    #[test]
+    fn ratings_are_checked_at_their_limits() {
+        let at_limits = Checkin { fatigue: MAX_RATING, breathlessness: MAX_RATING, perceived_exertion: Some(MAX_BORG), ..checkin() };
+        assert!(invalid_fields(&at_limits).is_empty());
+        assert!(invalid_fields(&Checkin { perceived_exertion: None, ..checkin() }).is_empty());
+
+        let past_limits = Checkin {
+            fatigue: MAX_RATING + 1,
+            breathlessness: MAX_RATING + 1,
+            perceived_exertion: Some(MIN_BORG - 1),
+            ..checkin()
+        };
+        assert_eq!(invalid_fields(&past_limits), vec!["fatigue", "breathlessness", "perceived_exertion"]);
+        assert_eq!(invalid_fields(&Checkin { perceived_exertion: Some(MAX_BORG + 1), ..checkin() }), vec!["perceived_exertion"]);
+    }
+
+    #[test]
+    fn flags_start_exactly_at_the_severe_thresholds() {
+        assert!(adverse_flags(&Checkin {
+            fatigue: SEVERE_FATIGUE - 1,
+            breathlessness: SEVERE_BREATHLESSNESS - 1,
+            perceived_exertion: Some(VERY_HARD_EXERTION - 1),
+            ..checkin()
+        })
+        .is_empty());
+        let flags = adverse_flags(&Checkin {
+            fatigue: SEVERE_FATIGUE,
+            breathlessness: SEVERE_BREATHLESSNESS,
+            dizziness: true,
+            perceived_exertion: Some(VERY_HARD_EXERTION),
+            ..checkin()
+        });
+        assert_eq!(
+            flags,
+            vec![AdverseFlag::Dizziness, AdverseFlag::SevereBreathlessness, AdverseFlag::SevereFatigue, AdverseFlag::VeryHardExertion]
+        );
+    }
+
+    #[test]
+    fn a_warning_needs_a_flag() {
+        assert_eq!(safety_warning(&checkin()), None);
+        let flagged = Checkin { flags: vec![AdverseFlag::Dizziness], ..checkin() };
+        let warning = safety_warning(&flagged).unwrap();
+        assert_eq!(warning.messages, vec![AdverseFlag::Dizziness.message()]);
+        assert_eq!(warning.advice, SAFETY_ADVICE);
+    }
+}
@@ -0,0 +1,409 @@
+use chrono::{DateTime, Days, Local, NaiveDate, Utc};
+use std::fmt::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::backup::{self, BackupError};
+use crate::db::{self, Session, UserProfile};
+use crate::share::{self, ShareError};
+use crate::stats::{self, TrainingTargets, WeeklyAdherence};
+use crate::units::{Pressure, PressureUnit};
+
+// --- Clinician dashboard ---
+//
+// Summaries for the `clinician_dashboard` binary. Patients come from signed share bundles
+// or, given the passphrase, encrypted backups (every profile in a backup is a patient).
+// Adherence is computed with the same stats code the app uses, in the clinician's
+// timezone. A share bundle only holds the sessions of its date range, so weeks outside it
+// are shown as having no data rather than as missed.
+
+pub struct Patient {
+    // File the patient was read from
+    pub source: String,
+    pub profile: UserProfile,
+    pub targets: TrainingTargets,
+    // Completed sessions, newest first
+    pub sessions: Vec<Session>,
+    // Fingerprint of the signing key, for share bundles
+    pub signer: Option<String>,
+    // The days a share bundle covers; a backup holds the whole history
+    pub covers: Option<RangeInclusive<NaiveDate>>,
+    // The unit the patient reads pressures in
+    pub unit: PressureUnit,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Share(ShareError),
+    Backup(BackupError),
+    // A backup was given but no passphrase to open it
+    NeedsPassphrase,
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Share(e) => write!(f, "{}", e),
+            LoadError::Backup(e) => write!(f, "{}", e),
+            LoadError::NeedsPassphrase => write!(f, "this is a backup; pass --passphrase to read it"),
+            LoadError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for LoadError {
+    fn from(e: rusqlite::Error) -> Self {
+        LoadError::Database(e)
+    }
+}
+
+// Reads the patients in a share bundle or backup. `trusted` lists the signer fingerprints
+// the clinician has confirmed; when it is empty any correctly signed bundle is accepted.
+pub fn load(source: &str, bytes: &[u8], passphrase: Option<&str>, trusted: &[String]) -> Result<Vec<Patient>, LoadError> {
+    match share::verify(bytes, None) {
+        Ok(verified) => {
+            let compact = |f: &str| f.split_whitespace().collect::<String>().to_ascii_lowercase();
+            if !trusted.is_empty() && !trusted.iter().any(|f| compact(f) == compact(&verified.fingerprint)) {
+                return Err(LoadError::Share(ShareError::UntrustedKey));
+            }
+            let contents = verified.contents;
+            Ok(vec![Patient {
+                source: source.to_string(),
+                profile: contents.profile,
+                targets: contents.targets,
+                sessions: contents.sessions,
+                signer: Some(verified.fingerprint),
+                covers: Some(contents.from..=contents.to),
+                unit: contents.stats.pressure_unit,
+            }])
+        }
+        Err(ShareError::NotAShareBundle) => load_backup(source, bytes, passphrase),
+        Err(e) => Err(LoadError::Share(e)),
+    }
+}
+
+fn load_backup(source: &str, bytes: &[u8], passphrase: Option<&str>) -> Result<Vec<Patient>, LoadError> {
+    if !backup::is_backup(bytes) {
+        return Err(LoadError::Backup(BackupError::NotABackup));
+    }
+    let Some(passphrase) = passphrase else {
+        return Err(LoadError::NeedsPassphrase);
+    };
+    let conn = db::initialize_database(":memory:")?;
+    backup::import_backup(&conn, bytes, passphrase).map_err(LoadError::Backup)?;
+    let mut patients = Vec::new();
+    for profile in db::list_profiles(&conn)? {
+        db::switch_profile(&conn, profile.id)?;
+        let sessions = db::get_all_sessions(&conn)?.into_iter().filter(|s| s.end_time.is_some()).collect();
+        patients.push(Patient {
+            source: source.to_string(),
+            targets: db::get_training_targets(&conn)?,
+            unit: db::get_pressure_unit(&conn)?,
+            profile,
+            sessions,
+            signer: None,
+            covers: None,
+        });
+    }
+    Ok(patients)
+}
+
+// --- Summaries ---
+
+pub struct DashboardWeek {
+    pub week_start: NaiveDate,
+    // None when the patient's file doesn't cover the whole week
+    pub adherence: Option<WeeklyAdherence>,
+}
+
+pub struct PatientSummary {
+    pub name: String,
+    pub source: String,
+    pub signer: Option<String>,
+    pub targets: TrainingTargets,
+    pub completed_sessions: usize,
+    pub total_reps: i64,
+    pub last_session: Option<NaiveDate>,
+    pub days_since_last: Option<i64>,
+    // Oldest first, ending with the current week
+    pub weeks: Vec<DashboardWeek>,
+    pub week_streak: u32,
+    pub weeks_on_target: u32,
+    // In cmH2O, shown in `unit`
+    pub first_pressure: Option<i32>,
+    pub latest_pressure: Option<i32>,
+    pub unit: PressureUnit,
+    pub average_score: Option<f64>,
+}
+
+impl PatientSummary {
+    // Mean adherence over the finished weeks shown that have data, so a week in progress
+    // doesn't drag it down
+    pub fn average_adherence(&self) -> Option<f64> {
+        let finished: Vec<f64> = self.weeks[..self.weeks.len().saturating_sub(1)]
+            .iter()
+            .filter_map(|week| week.adherence.as_ref())
+            .map(|adherence| adherence.percent)
+            .collect();
+        (!finished.is_empty()).then(|| finished.iter().sum::<f64>() / finished.len() as f64)
+    }
+}
+
+fn display_name(profile: &UserProfile, source: &str) -> String {
+    let name = format!("{} {}", profile.first_name.trim(), profile.last_name.trim());
+    let name = name.trim();
+    if name.is_empty() { source.to_string() } else { name.to_string() }
+}
+
+// Whether `covers` holds every day of the week starting at `week_start` up to `today`.
+fn covers_week(covers: &Option<RangeInclusive<NaiveDate>>, week_start: NaiveDate, today: NaiveDate) -> bool {
+    let week_end = (week_start + Days::new(6)).min(today);
+    covers.as_ref().is_none_or(|covers| covers.contains(&week_start) && covers.contains(&week_end))
+}
+
+pub fn summarize(patient: &Patient, now: DateTime<Local>, weeks: u32) -> PatientSummary {
+    let history: Vec<DateTime<Utc>> = patient.sessions.iter().filter_map(|session| session.end_time).collect();
+    let last_session = history.iter().max().map(|at| at.with_timezone(&Local).date_naive());
+    let scores: Vec<f64> = patient.sessions.iter().filter_map(|s| s.score.as_ref()).map(|s| s.total as f64).collect();
+    PatientSummary {
+        name: display_name(&patient.profile, &patient.source),
+        source: patient.source.clone(),
+        signer: patient.signer.clone(),
+        targets: patient.targets,
+        completed_sessions: patient.sessions.len(),
+        total_reps: patient.sessions.iter().map(|session| session.rep_count).sum(),
+        last_session,
+        days_since_last: last_session.map(|date| (now.date_naive() - date).num_days()),
+        weeks: stats::recent_weeks(&now, &history, &patient.targets, weeks)
+            .into_iter()
+            .map(|adherence| DashboardWeek {
+                week_start: adherence.week_start,
+                adherence: covers_week(&patient.covers, adherence.week_start, now.date_naive()).then_some(adherence),
+            })
+            .collect(),
+        week_streak: stats::week_streak(&now, &history, &patient.targets),
+        weeks_on_target: stats::weeks_on_target(&now, &history, &patient.targets),
+        first_pressure: patient.sessions.iter().min_by_key(|s| s.start_time).map(|s| s.pressure_setting),
+        latest_pressure: patient.sessions.iter().max_by_key(|s| s.start_time).map(|s| s.pressure_setting),
+        unit: patient.unit,
+        average_score: (!scores.is_empty()).then(|| scores.iter().sum::<f64>() / scores.len() as f64),
+    }
+}
+
+fn or_dash<T: fmt::Display>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+fn pressure_change(summary: &PatientSummary) -> String {
+    let format = |cmh2o: i32| Pressure::from_cmh2o(cmh2o as f64).format(summary.unit);
+    match (summary.first_pressure, summary.latest_pressure) {
+        (Some(first), Some(latest)) if first != latest => {
+            let first = Pressure::from_cmh2o(first as f64).value_in(summary.unit);
+            format!("{:.*} -> {}", summary.unit.decimals(), first, format(latest))
+        }
+        (_, Some(latest)) => format(latest),
+        _ => "-".to_string(),
+    }
+}
+
+// --- Terminal output ---
+
+pub fn render_terminal(summaries: &[PatientSummary]) -> String {
+    let mut out = String::new();
+    let name_width = summaries.iter().map(|s| s.name.chars().count()).max().unwrap_or(0).max(7);
+    let week_starts: Vec<NaiveDate> = summaries.first().map(|s| s.weeks.iter().map(|w| w.week_start).collect()).unwrap_or_default();
+
+    let _ = writeln!(out, "Weekly adherence (% of target; the last column is the current week, - where there is no data)\n");
+    let _ = write!(out, "{:<width$}", "Patient", width = name_width);
+    for start in &week_starts {
+        let _ = write!(out, " {:>5}", start.format("%m-%d").to_string());
+    }
+    let _ = writeln!(out, " {:>5}  {:>6}  Last session", "Avg", "Streak");
+    for summary in summaries {
+        let _ = write!(out, "{:<width$}", summary.name, width = name_width);
+        for week in &summary.weeks {
+            let _ = write!(out, " {:>5}", or_dash(week.adherence.as_ref().map(|a| format!("{:.0}", a.percent))));
+        }
+        let average = summary.average_adherence().map(|a| format!("{:.0}", a));
+        let last = match (summary.last_session, summary.days_since_last) {
+            (Some(date), Some(days)) => format!("{} ({} days ago)", date, days),
+            _ => "never".to_string(),
+        };
+        let _ = writeln!(out, " {:>5}  {:>6}  {}", or_dash(average), summary.week_streak, last);
+    }
+
+    for summary in summaries {
+        let _ = writeln!(out, "\n== {} ==", summary.name);
+        let _ = writeln!(out, "  Source:            {}", summary.source);
+        if let Some(signer) = &summary.signer {
+            let _ = writeln!(out, "  Signed by:         {}", signer);
+        }
+        let _ = writeln!(out, "  Target:            {} sessions/week", summary.targets.weekly_target());
+        let _ = writeln!(out, "  Sessions:          {} ({} reps)", summary.completed_sessions, summary.total_reps);
+        let _ = writeln!(out, "  Weeks on target:   {} (current streak {})", summary.weeks_on_target, summary.week_streak);
+        let _ = writeln!(out, "  Pressure:          {}", pressure_change(summary));
+        let _ = writeln!(out, "  Average score:     {}", or_dash(summary.average_score.map(|s| format!("{:.0}", s))));
+    }
+    out
+}
+
+// --- HTML output ---
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn adherence_class(percent: f64) -> &'static str {
+    if percent >= 100.0 {
+        "full"
+    } else if percent >= 50.0 {
+        "partial"
+    } else {
+        "low"
+    }
+}
+
+pub fn render_html(summaries: &[PatientSummary], generated_at: DateTime<Local>) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>EMST adherence</title>\n<style>\n\
+         body {{ font-family: sans-serif; margin: 2em; }}\n\
+         table {{ border-collapse: collapse; }}\n\
+         th, td {{ padding: 4px 8px; border: 1px solid #ccc; text-align: right; }}\n\
+         th:first-child, td:first-child {{ text-align: left; }}\n\
+         .full {{ background: #c8e6c9; }} .partial {{ background: #fff3c4; }} .low {{ background: #ffcdd2; }} .nodata {{ color: #999; }}\n\
+         dl {{ display: grid; grid-template-columns: max-content auto; gap: 2px 12px; }}\n\
+         dt {{ font-weight: bold; }}\n\
+         </style></head><body>"
+    );
+    let _ = writeln!(out, "<h1>EMST adherence</h1>\n<p>Generated {}</p>", generated_at.format("%Y-%m-%d %H:%M"));
+
+    let _ = write!(out, "<table>\n<tr><th>Patient</th>");
+    if let Some(first) = summaries.first() {
+        for week in &first.weeks {
+            let _ = write!(out, "<th>{}</th>", week.week_start.format("%m-%d"));
+        }
+    }
+    let _ = writeln!(out, "<th>Avg</th><th>Streak</th><th>Last session</th></tr>");
+    for summary in summaries {
+        let _ = write!(out, "<tr><td>{}</td>", escape(&summary.name));
+        for week in &summary.weeks {
+            match &week.adherence {
+                Some(adherence) => {
+                    let _ = write!(out, "<td class=\"{}\">{:.0}%</td>", adherence_class(adherence.percent), adherence.percent);
+                }
+                None => {
+                    let _ = write!(out, "<td class=\"nodata\">-</td>");
+                }
+            }
+        }
+        let average = summary.average_adherence().map(|a| format!("{:.0}%", a));
+        let _ = writeln!(
+            out,
+            "<td>{}</td><td>{}</td><td>{}</td></tr>",
+            or_dash(average),
+            summary.week_streak,
+            or_dash(summary.last_session)
+        );
+    }
+    let _ = writeln!(out, "</table>");
+
+    for summary in summaries {
+        let _ = writeln!(out, "<h2>{}</h2>\n<dl>", escape(&summary.name));
+        let mut row = |term: &str, value: String| {
+            let _ = writeln!(out, "<dt>{}</dt><dd>{}</dd>", term, escape(&value));
+        };
+        row("Source", summary.source.clone());
+        if let Some(signer) = &summary.signer {
+            row("Signed by", signer.clone());
+        }
+        row("Target", format!("{} sessions/week", summary.targets.weekly_target()));
+        row("Sessions", format!("{} ({} reps)", summary.completed_sessions, summary.total_reps));
+        row("Weeks on target", format!("{} (current streak {})", summary.weeks_on_target, summary.week_streak));
+        row("Pressure", pressure_change(summary));
+        row("Average score", or_dash(summary.average_score.map(|s| format!("{:.0}", s))));
+        let _ = writeln!(out, "</dl>");
+    }
+    let _ = writeln!(out, "</body></html>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patient_database() -> rusqlite::Connection {
+        let conn = db::initialize_database(":memory:").unwrap();
+        let session = db::start_new_session(&conn, 30, "").unwrap();
+        db::add_rep(&conn, session).unwrap();
+        db::end_session(&conn, session, "", &Local::now()).unwrap();
+        conn
+    }
+
+    #[test]
+    fn weeks_outside_a_share_bundle_have_no_data() {
+        let conn = patient_database();
+        let now = Local::now();
+        let this_week = stats::week_start(now.date_naive());
+        // The two weeks before this one, and this week so far
+        let bundle = share::export_share_bundle(&conn, this_week - Days::new(14), now.date_naive()).unwrap();
+        let patients = load("patient.emstshare", &bundle, None, &[]).unwrap();
+        assert_eq!(patients.len(), 1);
+
+        let summary = summarize(&patients[0], now, 8);
+        let covered: Vec<bool> = summary.weeks.iter().map(|week| week.adherence.is_some()).collect();
+        assert_eq!(covered, [vec![false; 5], vec![true; 3]].concat());
+        assert_eq!(summary.weeks[7].adherence.as_ref().unwrap().completed, 1);
+        // Only the two finished weeks in the bundle count, and they had no sessions
+        assert_eq!(summary.average_adherence(), Some(0.0));
+        assert!(render_terminal(&[summary]).contains("    -"));
+    }
+
+    #[test]
+    fn a_backup_needs_the_passphrase() {
+        let conn = patient_database();
+        let archive = backup::export_backup(&conn, "correct horse").unwrap();
+        assert!(matches!(load("patient.emstbak", &archive, None, &[]), Err(LoadError::NeedsPassphrase)));
+        assert!(matches!(
+            load("patient.emstbak", &archive, Some("wrong"), &[]),
+            Err(LoadError::Backup(BackupError::WrongPassphrase))
+        ));
+
+        let patients = load("patient.emstbak", &archive, Some("correct horse"), &[]).unwrap();
+        assert_eq!(patients.len(), 1);
+        assert!(patients[0].covers.is_none() && patients[0].signer.is_none());
+        let summary = summarize(&patients[0], Local::now(), 4);
+        assert!(summary.weeks.iter().all(|week| week.adherence.is_some()));
+    }
+
+    #[test]
+    fn bundles_from_an_untrusted_key_are_refused() {
+        let conn = patient_database();
+        let today = Local::now().date_naive();
+        let bundle = share::export_share_bundle(&conn, today, today).unwrap();
+        let fingerprint = share::key_fingerprint(&conn).unwrap();
+
+        let other = vec!["0000 0000 0000 0000 0000 0000 0000 0000".to_string()];
+        assert!(matches!(load("patient.emstshare", &bundle, None, &other), Err(LoadError::Share(ShareError::UntrustedKey))));
+        // Fingerprints match whatever their spacing and case
+        let trusted = vec![fingerprint.replace(' ', "").to_uppercase()];
+        assert_eq!(load("patient.emstshare", &bundle, None, &trusted).unwrap()[0].signer, Some(fingerprint));
+    }
+
+    #[test]
+    fn pressures_are_shown_in_the_patients_unit() {
+        let conn = patient_database();
+        db::set_setting(&conn, crate::units::PRESSURE_UNIT_KEY, PressureUnit::MmHg.as_str()).unwrap();
+        let session = db::start_new_session(&conn, 40, "").unwrap();
+        db::end_session(&conn, session, "", &Local::now()).unwrap();
+        let today = Local::now().date_naive();
+        let bundle = share::export_share_bundle(&conn, today, today).unwrap();
+        let archive = backup::export_backup(&conn, "pw").unwrap();
+
+        for patient in [load("patient.emstshare", &bundle, None, &[]), load("patient.emstbak", &archive, Some("pw"), &[])] {
+            let summary = summarize(&patient.unwrap()[0], Local::now(), 4);
+            assert_eq!(pressure_change(&summary), "22.1 -> 29.4 mmHg");
+        }
+    }
+}
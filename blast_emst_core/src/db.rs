@@ -1,16 +1,51 @@
 use rusqlite::{Connection, Result, Row, params, OptionalExtension};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
 use serde::{Serialize, Deserialize};
+use log::warn;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::achievements::{self, Achievement, AchievementFacts, AchievementStatus, DEFAULT_PROGRAM_WEEKS, PROGRAM_WEEKS_KEY};
+use crate::encryption::{self, DatabaseKey, EncryptionError};
+use crate::calendar;
+use crate::fhir;
+use crate::devices::{self, PressureError};
+use crate::profile::{self, FieldError, Indication, ProfileError, ProfileInput};
+use crate::checkins::{self, Checkin, CheckinError, CheckinKind, SafetyWarning};
+use crate::cadence::{self, SessionCadence};
+use crate::scoring::{self, Prescription, ScoreSummary, SessionScore, REPS_PER_SET_KEY, REP_REST_SECONDS_KEY, SET_REST_SECONDS_KEY};
+use crate::questionnaires::{self, Instrument, QuestionnaireError, QuestionnaireResponse, QuestionnaireTrend};
+use crate::reminders::{ReminderSchedule, REMINDER_SCHEDULE_KEY};
+use crate::share;
+use crate::snapshots;
+use crate::sync;
+use crate::stats::{self, TrainingTargets, DEFAULT_SESSIONS_PER_WEEK, GOAL_SESSIONS_PER_DAY_KEY, GOAL_SESSIONS_PER_WEEK_KEY};
+use crate::units::{DisplayPressure, Pressure, PressureUnit, PRESSURE_UNIT_KEY};
 
 // --- Data Models for our Entities ---
 
 #[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
 pub struct UserProfile {
     pub id: i64,
     pub first_name: String,
     pub last_name: String,
-    pub dob: String,
+    #[serde(deserialize_with = "profile::deserialize_optional_date")]
+    pub dob: Option<NaiveDate>,
     pub speech_therapist: String,
+    pub therapist_phone: String,
+    pub therapist_email: String,
+    pub diagnosis: Option<Indication>,
+    pub diagnosis_notes: String,
+    // Id of the user's trainer in the device catalog, or empty if not chosen yet
+    pub trainer_device: String,
+    // Maximum expiratory pressure measured before training started, in cmH2O
+    pub baseline_mep: Option<f64>,
+    #[serde(deserialize_with = "profile::deserialize_optional_date")]
+    pub treatment_start_date: Option<NaiveDate>,
+    // A date of birth entered as free text before dates were validated, kept until a valid
+    // one replaces it so the app can show it for re-entry
+    pub dob_legacy: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -18,9 +53,21 @@ pub struct Session {
     pub id: i64,
     pub start_time: DateTime<Utc>,
     pub end_time: Option<DateTime<Utc>>,
+    // Always in cmH2O; `pressure` carries the same value in the user's display unit
     pub pressure_setting: i32,
+    pub pressure: DisplayPressure,
     pub notes: String,
     pub rep_count: i64,
+    pub deleted_at: Option<DateTime<Utc>>,
+    // Set once the session has ended
+    pub score: Option<SessionScore>,
+}
+
+impl Session {
+    fn with_pressure_unit(mut self, unit: PressureUnit) -> Self {
+        self.pressure = DisplayPressure::new(Pressure::from_cmh2o(self.pressure_setting as f64), unit);
+        self
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -53,13 +100,28 @@ impl<'stmt> TryFrom<&Row<'stmt>> for Session {
             None => None,
         };
 
+        let deleted_at_opt_str: Option<String> = row.get(6)?;
+        let deleted_at = match deleted_at_opt_str {
+            Some(s) => Some(
+                DateTime::parse_from_rfc3339(&s)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(6, rusqlite::types::Type::Text, Box::new(e)))?
+            ),
+            None => None,
+        };
+
+        let pressure_setting: i32 = row.get(3)?;
+
         Ok(Session {
             id: row.get(0)?,
             start_time,
             end_time,
-            pressure_setting: row.get(3)?,
+            pressure_setting,
+            pressure: DisplayPressure::new(Pressure::from_cmh2o(pressure_setting as f64), PressureUnit::CmH2O),
             notes: row.get(4)?,
             rep_count: row.get(5)?,
+            deleted_at,
+            score: None,
         })
     }
 }
@@ -67,7 +129,23 @@ impl<'stmt> TryFrom<&Row<'stmt>> for Session {
 
 // --- Database Initialization Function ---
 pub fn initialize_database(db_path: &str) -> Result<Connection> {
-    let conn = Connection::open(db_path)?;
+    prepare_database(Connection::open(db_path)?)
+}
+
+// Opens the database encrypted with `key`, encrypting an existing plaintext database first.
+pub fn initialize_encrypted_database(db_path: &str, key: &DatabaseKey) -> std::result::Result<Connection, EncryptionError> {
+    let conn = encryption::open_encrypted(Path::new(db_path), key)?;
+    Ok(prepare_database(conn)?)
+}
+
+fn prepare_database(conn: Connection) -> Result<Connection> {
+    migrate(&conn)?;
+    Ok(conn)
+}
+
+// Creates and migrates the schema on an open connection. Also run after a snapshot from an
+// older version of the app is restored.
+pub fn migrate(conn: &Connection) -> Result<()> {
     conn.execute("PRAGMA foreign_keys = ON;", [])?; // Good practice to enable foreign key constraints
 
     // Create the user_profile table
@@ -91,17 +169,50 @@ pub fn initialize_database(db_path: &str) -> Result<Connection> {
         (),
     )?;
 
+    // Create the profile_settings table. Settings that belong to a patient (goals, pressure,
+    // reminders, ...) live here, keyed by profile; app_settings keeps device-wide values.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS profile_settings (
+            profile_id INTEGER NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (profile_id, key)
+        )",
+        (),
+    )?;
+
     // Create the sessions table
     conn.execute(
         "CREATE TABLE IF NOT EXISTS sessions (
             id INTEGER PRIMARY KEY,
+            profile_id INTEGER NOT NULL DEFAULT 1,
             start_time DATETIME NOT NULL,
             end_time DATETIME,
             pressure_setting INTEGER NOT NULL,
-            notes TEXT
+            notes TEXT,
+            deleted_at DATETIME
         )",
         (),
     )?;
+    // Databases created before soft deletion existed are missing this column
+    add_column_if_missing(conn, "sessions", "deleted_at", "DATETIME")?;
+    // Databases created before multiple profiles existed belong entirely to the first profile
+    add_column_if_missing(conn, "sessions", "profile_id", "INTEGER NOT NULL DEFAULT 1")?;
+    // Clinical profile fields
+    add_column_if_missing(conn, "user_profile", "therapist_phone", "TEXT NOT NULL DEFAULT ''")?;
+    add_column_if_missing(conn, "user_profile", "therapist_email", "TEXT NOT NULL DEFAULT ''")?;
+    add_column_if_missing(conn, "user_profile", "diagnosis", "TEXT")?;
+    add_column_if_missing(conn, "user_profile", "diagnosis_notes", "TEXT NOT NULL DEFAULT ''")?;
+    add_column_if_missing(conn, "user_profile", "trainer_device", "TEXT NOT NULL DEFAULT ''")?;
+    add_column_if_missing(conn, "user_profile", "baseline_mep", "REAL")?;
+    add_column_if_missing(conn, "user_profile", "treatment_start_date", "DATE")?;
+    // Dates of birth that aren't YYYY-MM-DD are set aside rather than read as unset, so the
+    // next save of the profile can't erase them
+    add_column_if_missing(conn, "user_profile", "dob_legacy", "TEXT")?;
+    conn.execute(
+        "UPDATE user_profile SET dob_legacy = dob, dob = '' WHERE dob != '' AND date(dob) IS NOT dob",
+        (),
+    )?;
 
     // Create the reps table
     conn.execute(
@@ -114,117 +225,405 @@ pub fn initialize_database(db_path: &str) -> Result<Connection> {
         (),
     )?;
 
-    Ok(conn)
+    // Create the session_scores table, holding the quality score of each completed session
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS session_scores (
+            session_id INTEGER PRIMARY KEY,
+            total INTEGER NOT NULL,
+            reps_score INTEGER NOT NULL,
+            sets_score INTEGER NOT NULL,
+            rest_score INTEGER NOT NULL,
+            duration_score INTEGER NOT NULL,
+            reps_completed INTEGER NOT NULL,
+            sets_completed INTEGER NOT NULL,
+            FOREIGN KEY (session_id) REFERENCES sessions (id) ON DELETE CASCADE
+        )",
+        (),
+    )?;
+
+    // Create the session_checkins table, holding the pre- and post-session check-ins
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS session_checkins (
+            session_id INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            fatigue INTEGER NOT NULL,
+            breathlessness INTEGER NOT NULL,
+            dizziness INTEGER NOT NULL,
+            perceived_exertion INTEGER,
+            notes TEXT NOT NULL DEFAULT '',
+            flags TEXT NOT NULL DEFAULT '[]',
+            recorded_at DATETIME NOT NULL,
+            PRIMARY KEY (session_id, kind),
+            FOREIGN KEY (session_id) REFERENCES sessions (id) ON DELETE CASCADE
+        )",
+        (),
+    )?;
+
+    // Create the achievements table, one row per milestone a profile has unlocked
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS achievements (
+            profile_id INTEGER NOT NULL,
+            achievement TEXT NOT NULL,
+            unlocked_at DATETIME NOT NULL,
+            PRIMARY KEY (profile_id, achievement)
+        )",
+        (),
+    )?;
+
+    // Create the questionnaire_responses table, one row per completed questionnaire
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS questionnaire_responses (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            profile_id INTEGER NOT NULL,
+            instrument TEXT NOT NULL,
+            completed_on TEXT NOT NULL,
+            answers TEXT NOT NULL,
+            total INTEGER NOT NULL,
+            recorded_at DATETIME NOT NULL
+        )",
+        (),
+    )?;
+
+    // Sessions and reps carry a UUID shared across synced devices
+    add_column_if_missing(conn, "sessions", "uuid", "TEXT")?;
+    add_column_if_missing(conn, "reps", "uuid", "TEXT")?;
+    sync::prepare_schema(conn)?;
+    // Share signing keys have their own table, kept out of backups and snapshots
+    share::prepare_schema(conn)?;
+
+    // Settings written before profiles existed were stored device-wide. Hand them to the
+    // first profile so the original user keeps their configuration.
+    conn.execute(
+        "INSERT OR IGNORE INTO profile_settings (profile_id, key, value)
+         SELECT 1, key, value FROM app_settings WHERE key NOT IN (?1, ?2, ?3, ?4, ?5)",
+        DEVICE_SETTINGS,
+    )?;
+    conn.execute(
+        "DELETE FROM app_settings WHERE key NOT IN (?1, ?2, ?3, ?4, ?5)",
+        DEVICE_SETTINGS,
+    )?;
+
+    Ok(())
+}
+
+// Adds a column to an existing table if it isn't there yet. CREATE TABLE IF NOT EXISTS
+// won't touch tables created by older versions of the app, so new columns go through here.
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), ())?;
+    }
+    Ok(())
 }
 
 // --- Database Functions ---
 
-pub fn start_new_session(conn: &Connection, pressure_setting: i32, notes: &str) -> Result<i64> {
+#[derive(Debug)]
+pub enum SessionError {
+    Pressure(PressureError),
+    Database(rusqlite::Error),
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SessionError::Pressure(e) => write!(f, "{}", e),
+            SessionError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for SessionError {
+    fn from(e: rusqlite::Error) -> Self {
+        SessionError::Database(e)
+    }
+}
+
+impl From<PressureError> for SessionError {
+    fn from(e: PressureError) -> Self {
+        SessionError::Pressure(e)
+    }
+}
+
+// The pressure is checked against the active profile's trainer and rounded to the nearest
+// setting the device supports before the session is stored.
+pub fn start_new_session(conn: &Connection, pressure_setting: i32, notes: &str) -> std::result::Result<i64, SessionError> {
+    let profile = get_profile(conn)?;
+    let device = devices::find_device(&profile.trainer_device);
+    let pressure_setting = devices::normalize_pressure(device, pressure_setting)?;
     let now = Utc::now().to_rfc3339();
     conn.execute(
-        "INSERT INTO sessions (start_time, pressure_setting, notes) VALUES (?1, ?2, ?3)",
-        (now, pressure_setting, notes),
+        "INSERT INTO sessions (uuid, profile_id, start_time, pressure_setting, notes) VALUES (?1, ?2, ?3, ?4, ?5)",
+        (sync::new_uuid(), profile.id, now, pressure_setting, notes),
     )?;
     Ok(conn.last_insert_rowid())
 }
 
-pub fn get_profile(conn: &Connection) -> Result<UserProfile> {
-    let query_result = conn.query_row(
-        "SELECT id, first_name, last_name, dob, speech_therapist FROM user_profile WHERE id = 1",
-        [],
-        |row| {
-            Ok(UserProfile {
-                id: row.get(0)?,
-                first_name: row.get(1)?,
-                last_name: row.get(2)?,
-                dob: row.get(3)?,
-                speech_therapist: row.get(4)?,
-            })
-        },
-    );
+// The pressure a new session should start at: the user's saved setting if there is one,
+// otherwise the usual starting point for their trainer.
+pub fn get_default_pressure(conn: &Connection) -> Result<i32> {
+    if let Some(pressure) = get_setting(conn, "default_pressure")?.and_then(|value| value.parse::<i32>().ok()) {
+        return Ok(pressure);
+    }
+    let profile = get_profile(conn)?;
+    Ok(devices::find_device(&profile.trainer_device)
+        .map(|device| device.default_pressure)
+        .unwrap_or(devices::FALLBACK_DEFAULT_PRESSURE))
+}
 
-    match query_result {
-        Ok(profile) => Ok(profile),
-        Err(rusqlite::Error::QueryReturnedNoRows) => {
-            let default_profile = UserProfile {
-                id: 1,
-                ..Default::default()
-            };
-            conn.execute(
-                "INSERT INTO user_profile (id, first_name, last_name, dob, speech_therapist) VALUES (?1, ?2, ?3, ?4, ?5)",
-                (
-                    &default_profile.id,
-                    &default_profile.first_name,
-                    &default_profile.last_name,
-                    &default_profile.dob,
-                    &default_profile.speech_therapist,
-                ),
-            )?;
-            Ok(default_profile)
+// The active profile id is a device-wide setting. If it is missing or points at a profile
+// that no longer exists, fall back to the oldest remaining profile, creating a blank one
+// on a fresh install.
+pub const ACTIVE_PROFILE_ID_KEY: &str = "active_profile_id";
+
+pub fn active_profile_id(conn: &Connection) -> Result<i64> {
+    if let Some(id) = get_app_setting(conn, ACTIVE_PROFILE_ID_KEY)?.and_then(|value| value.parse::<i64>().ok())
+        && profile_exists(conn, id)?
+    {
+        return Ok(id);
+    }
+
+    let oldest: Option<i64> = conn.query_row("SELECT MIN(id) FROM user_profile", [], |row| row.get(0))?;
+    let id = match oldest {
+        Some(id) => id,
+        None => {
+            conn.execute("INSERT INTO user_profile (id) VALUES (1)", ())?;
+            1
         }
-        Err(e) => Err(e),
+    };
+    set_app_setting(conn, ACTIVE_PROFILE_ID_KEY, &id.to_string())?;
+    Ok(id)
+}
+
+fn profile_exists(conn: &Connection, profile_id: i64) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM user_profile WHERE id = ?1)",
+        [profile_id],
+        |row| row.get(0),
+    )
+}
+
+const PROFILE_COLUMNS: &str = "id, first_name, last_name, dob, speech_therapist, therapist_phone, therapist_email,
+     diagnosis, diagnosis_notes, trainer_device, baseline_mep, treatment_start_date, dob_legacy";
+
+// A date that can't be read comes back as unset rather than failing the whole profile.
+// Free-text dates of birth from before validation existed are in dob_legacy by now.
+fn profile_from_row(row: &Row) -> Result<UserProfile> {
+    let dob: String = row.get(3)?;
+    let diagnosis: Option<String> = row.get(7)?;
+    let treatment_start_date: Option<String> = row.get(11)?;
+    Ok(UserProfile {
+        id: row.get(0)?,
+        first_name: row.get(1)?,
+        last_name: row.get(2)?,
+        dob: profile::parse_date(&dob),
+        speech_therapist: row.get(4)?,
+        therapist_phone: row.get(5)?,
+        therapist_email: row.get(6)?,
+        diagnosis: diagnosis.as_deref().and_then(Indication::parse),
+        diagnosis_notes: row.get(8)?,
+        trainer_device: row.get(9)?,
+        baseline_mep: row.get(10)?,
+        treatment_start_date: treatment_start_date.as_deref().and_then(profile::parse_date),
+        dob_legacy: row.get(12)?,
+    })
+}
+
+fn format_date(date: Option<NaiveDate>) -> Option<String> {
+    date.map(|d| d.format("%Y-%m-%d").to_string())
+}
+
+// Returns the active profile.
+pub fn get_profile(conn: &Connection) -> Result<UserProfile> {
+    let profile_id = active_profile_id(conn)?;
+    conn.query_row(
+        &format!("SELECT {} FROM user_profile WHERE id = ?1", PROFILE_COLUMNS),
+        [profile_id],
+        profile_from_row,
+    )
+}
+
+pub fn list_profiles(conn: &Connection) -> Result<Vec<UserProfile>> {
+    // Make sure a fresh install lists its default profile too
+    active_profile_id(conn)?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM user_profile ORDER BY id", PROFILE_COLUMNS))?;
+    let profiles = stmt.query_map([], profile_from_row)?.collect::<Result<Vec<_>>>()?;
+    Ok(profiles)
+}
+
+// Creates a new profile from everything but the id, which is assigned by the database.
+// The active profile is left unchanged; call switch_profile to start using it.
+pub fn create_profile(conn: &Connection, input: &ProfileInput) -> std::result::Result<i64, ProfileError> {
+    let profile = profile::validate_profile(input, Utc::now().date_naive())?;
+    conn.execute("INSERT INTO user_profile DEFAULT VALUES", ())?;
+    let id = conn.last_insert_rowid();
+    save_profile_fields(conn, id, &profile)?;
+    Ok(id)
+}
+
+pub fn switch_profile(conn: &Connection, profile_id: i64) -> Result<()> {
+    if !profile_exists(conn, profile_id)? {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+    set_app_setting(conn, ACTIVE_PROFILE_ID_KEY, &profile_id.to_string())
+}
+
+// Deletes a profile together with its sessions, reps and settings. Deleting the active
+// profile makes the oldest remaining profile active on the next lookup.
+pub fn delete_profile(conn: &Connection, profile_id: i64) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM sessions WHERE profile_id = ?1", [profile_id])?;
+    tx.execute("DELETE FROM profile_settings WHERE profile_id = ?1", [profile_id])?;
+    tx.execute("DELETE FROM achievements WHERE profile_id = ?1", [profile_id])?;
+    tx.execute("DELETE FROM questionnaire_responses WHERE profile_id = ?1", [profile_id])?;
+    tx.execute("DELETE FROM sync_changes WHERE profile_id = ?1", [profile_id])?;
+    tx.execute(&format!("DELETE FROM {} WHERE profile_id = ?1", share::KEY_TABLE), [profile_id])?;
+    let rows_affected = tx.execute("DELETE FROM user_profile WHERE id = ?1", [profile_id])?;
+    if rows_affected == 0 {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
     }
+    tx.commit()
 }
 
-pub fn update_profile(conn: &Connection, profile: &UserProfile) -> Result<()> {
+// Validates the profile and saves it as the active profile. Invalid profiles are rejected as
+// a whole with the list of offending fields, leaving the stored profile untouched.
+pub fn update_profile(conn: &Connection, input: &ProfileInput) -> std::result::Result<(), ProfileError> {
+    let profile = profile::validate_profile(input, Utc::now().date_naive())?;
+    save_profile_fields(conn, active_profile_id(conn)?, &profile)?;
+    Ok(())
+}
+
+fn save_profile_fields(conn: &Connection, profile_id: i64, profile: &UserProfile) -> Result<()> {
     conn.execute(
-        "UPDATE user_profile SET first_name = ?2, last_name = ?3, dob = ?4, speech_therapist = ?5
+        "UPDATE user_profile SET first_name = ?2, last_name = ?3, dob = ?4, speech_therapist = ?5,
+             therapist_phone = ?6, therapist_email = ?7, diagnosis = ?8, diagnosis_notes = ?9,
+             trainer_device = ?10, baseline_mep = ?11, treatment_start_date = ?12,
+             dob_legacy = CASE WHEN ?4 = '' THEN dob_legacy END
          WHERE id = ?1",
-        (
-            &profile.id,
-            &profile.first_name,
-            &profile.last_name,
-            &profile.dob,
-            &profile.speech_therapist,
-        ),
+        params![
+            profile_id,
+            profile.first_name,
+            profile.last_name,
+            format_date(profile.dob).unwrap_or_default(),
+            profile.speech_therapist,
+            profile.therapist_phone,
+            profile.therapist_email,
+            profile.diagnosis.map(|d| d.as_str()),
+            profile.diagnosis_notes,
+            profile.trainer_device,
+            profile.baseline_mep,
+            format_date(profile.treatment_start_date),
+        ],
     )?;
     Ok(())
 }
 
+// A session row that couldn't be read, left out of a listing rather than failing it.
+// integrity::check reports the same rows with a repair.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SkippedSession {
+    pub session_id: i64,
+    pub problem: String,
+}
+
+fn read_sessions(rows: &mut rusqlite::Rows, unit: PressureUnit) -> Result<(Vec<Session>, Vec<SkippedSession>)> {
+    let mut sessions = Vec::new();
+    let mut skipped = Vec::new();
+    while let Some(row) = rows.next()? {
+        match Session::try_from(row) {
+            Ok(session) => sessions.push(session.with_pressure_unit(unit)),
+            Err(e) => skipped.push(SkippedSession { session_id: row.get(0)?, problem: e.to_string() }),
+        }
+    }
+    Ok((sessions, skipped))
+}
+
+fn log_skipped(skipped: &[SkippedSession]) {
+    for session in skipped {
+        warn!("Skipping unreadable session {}: {}", session.session_id, session.problem);
+    }
+}
+
 pub fn get_all_sessions(conn: &Connection) -> Result<Vec<Session>> {
+    let (sessions, skipped) = get_all_sessions_checked(conn)?;
+    log_skipped(&skipped);
+    Ok(sessions)
+}
+
+// The active profile's sessions, plus the rows that had to be left out.
+pub fn get_all_sessions_checked(conn: &Connection) -> Result<(Vec<Session>, Vec<SkippedSession>)> {
     // This SQL query now joins the sessions and reps tables to get the count for each session
     let mut stmt = conn.prepare(
-        "SELECT s.id, s.start_time, s.end_time, s.pressure_setting, s.notes, COUNT(r.id) as rep_count
+        "SELECT s.id, s.start_time, s.end_time, s.pressure_setting, s.notes, COUNT(r.id) as rep_count, s.deleted_at
          FROM sessions s
          LEFT JOIN reps r ON s.id = r.session_id
+         WHERE s.profile_id = ?1 AND s.deleted_at IS NULL
          GROUP BY s.id
          ORDER BY s.start_time DESC"
     )?;
-    let mut rows = stmt.query([])?;
-    let mut sessions = Vec::new();
-    while let Some(row) = rows.next()? {
-        sessions.push(Session::try_from(row)?);
-    }
-    Ok(sessions)
+    let mut rows = stmt.query([active_profile_id(conn)?])?;
+    let (mut sessions, skipped) = read_sessions(&mut rows, get_pressure_unit(conn)?)?;
+    attach_scores(conn, &mut sessions)?;
+    Ok((sessions, skipped))
 }
 
 pub fn get_active_session(conn: &Connection) -> Result<Option<Session>> {
     let mut stmt = conn.prepare(
-        "SELECT s.id, s.start_time, s.end_time, s.pressure_setting, s.notes, COUNT(r.id) as rep_count
+        "SELECT s.id, s.start_time, s.end_time, s.pressure_setting, s.notes, COUNT(r.id) as rep_count, s.deleted_at
          FROM sessions s
          LEFT JOIN reps r ON s.id = r.session_id
-         WHERE s.end_time IS NULL
+         WHERE s.profile_id = ?1 AND s.end_time IS NULL AND s.deleted_at IS NULL
          GROUP BY s.id  -- Important to group by all non-aggregated columns from sessions if there could be multiple active sessions (though LIMIT 1 should prevent this)
          ORDER BY s.start_time DESC
          LIMIT 1"
     )?;
-    let mut rows = stmt.query([])?;
+    let mut rows = stmt.query([active_profile_id(conn)?])?;
     if let Some(row) = rows.next()? {
-        Ok(Some(Session::try_from(row)?))
+        Ok(Some(Session::try_from(row)?.with_pressure_unit(get_pressure_unit(conn)?)))
     } else {
         Ok(None)
     }
 }
 
 pub fn add_rep(conn: &Connection, session_id: i64) -> Result<()> {
-    let now = Utc::now().to_rfc3339();
-    conn.execute(
-        "INSERT INTO reps (session_id, rep_timestamp) VALUES (?1, ?2)",
-        (session_id, now),
+    add_rep_at(conn, session_id, Utc::now())
+}
+
+// Logs a rep with the time it was actually completed, as reported by guided sessions. Only
+// the active profile's sessions take reps.
+pub fn add_rep_at(conn: &Connection, session_id: i64, at: DateTime<Utc>) -> Result<()> {
+    let rows_affected = conn.execute(
+        "INSERT INTO reps (uuid, session_id, rep_timestamp)
+         SELECT ?1, id, ?3 FROM sessions WHERE id = ?2 AND profile_id = ?4",
+        params![sync::new_uuid(), session_id, at.to_rfc3339(), active_profile_id(conn)?],
     )?;
+    if rows_affected == 0 {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
     Ok(())
 }
 
+// The reps of a session, oldest first.
+pub fn get_reps(conn: &Connection, session_id: i64) -> Result<Vec<Rep>> {
+    let mut stmt = conn.prepare("SELECT id, session_id, rep_timestamp FROM reps WHERE session_id = ?1 ORDER BY rep_timestamp")?;
+    let rows = stmt
+        .query_map([session_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?)))?
+        .collect::<Result<Vec<_>>>()?;
+    rows.into_iter()
+        .map(|(id, session_id, timestamp)| {
+            let rep_timestamp = DateTime::parse_from_rfc3339(&timestamp)
+                .map(|dt| dt.with_timezone(&Utc))
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e)))?;
+            Ok(Rep { id, session_id, rep_timestamp })
+        })
+        .collect()
+}
+
 pub fn get_total_reps(conn: &Connection, session_id: i64) -> Result<i64> {
     conn.query_row(
         "SELECT COUNT(*) FROM reps WHERE session_id = ?1",
@@ -233,20 +632,408 @@ pub fn get_total_reps(conn: &Connection, session_id: i64) -> Result<i64> {
     )
 }
 
-pub fn end_session(conn: &Connection, session_id: i64, notes: &str) -> Result<()> { // Add notes parameter
-    let now = Utc::now().to_rfc3339();
+// Ends the session and returns any achievements it unlocked so the UI can celebrate them.
+// Ends a session at `now`, given in the user's timezone so streaks unlocked by it are
+// counted in their weeks.
+pub fn end_session<Tz: TimeZone>(conn: &Connection, session_id: i64, notes: &str, now: &DateTime<Tz>) -> Result<Vec<AchievementStatus>> { // Add notes parameter
+    let rows_affected = conn.execute(
+        "UPDATE sessions SET end_time = ?1, notes = ?2 WHERE id = ?3 AND profile_id = ?4", // Add notes to SET clause
+        params![now.with_timezone(&Utc).to_rfc3339(), notes, session_id, active_profile_id(conn)?], // Add notes to params
+    )?;
+    if rows_affected == 0 {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+    score_completed_session(conn, session_id)?;
+    unlock_new_achievements(conn, now)
+}
+
+// The active profile's prescription, built from its training settings.
+pub fn get_prescription(conn: &Connection) -> Result<Prescription> {
+    let defaults = Prescription::default();
+    let read = |key: &str, default: u32| -> Result<u32> {
+        Ok(get_setting(conn, key)?
+            .and_then(|value| value.parse::<u32>().ok())
+            .filter(|value| *value > 0)
+            .unwrap_or(default))
+    };
+    Ok(Prescription {
+        reps: read("default_reps", defaults.reps)?,
+        reps_per_set: read(REPS_PER_SET_KEY, defaults.reps_per_set)?,
+        rep_rest_seconds: read(REP_REST_SECONDS_KEY, defaults.rep_rest_seconds)?,
+        set_rest_seconds: read(SET_REST_SECONDS_KEY, defaults.set_rest_seconds)?,
+    })
+}
+
+// Scores a session that has ended against the current prescription and stores the result.
+pub fn score_completed_session(conn: &Connection, session_id: i64) -> Result<SessionScore> {
+    let (start_time, end_time): (String, Option<String>) = conn.query_row(
+        "SELECT start_time, end_time FROM sessions WHERE id = ?1",
+        [session_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let parse = |s: &str, column: usize| {
+        DateTime::parse_from_rfc3339(s)
+            .map(|dt| dt.with_timezone(&Utc))
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, Box::new(e)))
+    };
+    let start_time = parse(&start_time, 0)?;
+    let end_time = parse(&end_time.ok_or(rusqlite::Error::QueryReturnedNoRows)?, 1)?;
+    let rep_times: Vec<DateTime<Utc>> = get_reps(conn, session_id)?.into_iter().map(|rep| rep.rep_timestamp).collect();
+
+    let score = scoring::score_session(start_time, end_time, &rep_times, &get_prescription(conn)?);
     conn.execute(
-        "UPDATE sessions SET end_time = ?1, notes = ?2 WHERE id = ?3", // Add notes to SET clause
-        params![now, notes, session_id], // Add notes to params
+        "INSERT OR REPLACE INTO session_scores
+             (session_id, total, reps_score, sets_score, rest_score, duration_score, reps_completed, sets_completed)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            session_id,
+            score.total,
+            score.reps_score,
+            score.sets_score,
+            score.rest_score,
+            score.duration_score,
+            score.reps_completed,
+            score.sets_completed,
+        ],
+    )?;
+    Ok(score)
+}
+
+fn attach_scores(conn: &Connection, sessions: &mut [Session]) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT total, reps_score, sets_score, rest_score, duration_score, reps_completed, sets_completed
+         FROM session_scores WHERE session_id = ?1"
     )?;
+    for session in sessions.iter_mut() {
+        session.score = stmt
+            .query_row([session.id], |row| {
+                Ok(SessionScore {
+                    total: row.get(0)?,
+                    reps_score: row.get(1)?,
+                    sets_score: row.get(2)?,
+                    rest_score: row.get(3)?,
+                    duration_score: row.get(4)?,
+                    reps_completed: row.get(5)?,
+                    sets_completed: row.get(6)?,
+                })
+            })
+            .optional()?;
+    }
     Ok(())
 }
 
-// Gets a setting's value for a given key. It returns an Option<String>
+// Rep cadence of a session, measured against the active profile's prescription.
+pub fn get_session_cadence(conn: &Connection, session_id: i64) -> Result<SessionCadence> {
+    let rep_times: Vec<DateTime<Utc>> = get_reps(conn, session_id)?.into_iter().map(|rep| rep.rep_timestamp).collect();
+    Ok(cadence::analyze(session_id, &rep_times, &get_prescription(conn)?))
+}
+
+// Score averages over the active profile's completed sessions.
+pub fn get_score_summary(conn: &Connection) -> Result<ScoreSummary> {
+    conn.query_row(
+        "SELECT COUNT(*), AVG(sc.total), AVG(sc.reps_score), AVG(sc.sets_score), AVG(sc.rest_score),
+                AVG(sc.duration_score), MAX(sc.total)
+         FROM session_scores sc JOIN sessions s ON s.id = sc.session_id
+         WHERE s.profile_id = ?1 AND s.deleted_at IS NULL",
+        [active_profile_id(conn)?],
+        |row| {
+            Ok(ScoreSummary {
+                scored_sessions: row.get(0)?,
+                average_total: row.get(1)?,
+                average_reps_score: row.get(2)?,
+                average_sets_score: row.get(3)?,
+                average_rest_score: row.get(4)?,
+                average_duration_score: row.get(5)?,
+                best_total: row.get(6)?,
+            })
+        },
+    )
+}
+
+// Streaks are counted in the timezone of `now`, which is also the time the facts hold for.
+fn gather_achievement_facts<Tz: TimeZone>(conn: &Connection, now: &DateTime<Tz>) -> Result<AchievementFacts> {
+    let profile_id = active_profile_id(conn)?;
+    let (completed_sessions, max_pressure): (i64, Option<i32>) = conn.query_row(
+        "SELECT COUNT(*), MAX(pressure_setting) FROM sessions
+         WHERE profile_id = ?1 AND end_time IS NOT NULL AND deleted_at IS NULL",
+        [profile_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let first_pressure: Option<i32> = conn.query_row(
+        "SELECT pressure_setting FROM sessions
+         WHERE profile_id = ?1 AND end_time IS NOT NULL AND deleted_at IS NULL
+         ORDER BY start_time LIMIT 1",
+        [profile_id],
+        |row| row.get(0),
+    ).optional()?;
+    let total_reps: i64 = conn.query_row(
+        "SELECT COUNT(r.id) FROM reps r JOIN sessions s ON s.id = r.session_id
+         WHERE s.profile_id = ?1 AND s.end_time IS NOT NULL AND s.deleted_at IS NULL",
+        [profile_id],
+        |row| row.get(0),
+    )?;
+
+    let history = get_recent_session_end_times(conn, DateTime::UNIX_EPOCH)?;
+    let targets = get_training_targets(conn)?;
+    Ok(AchievementFacts {
+        completed_sessions,
+        total_reps,
+        week_streak: stats::week_streak(now, &history, &targets),
+        weeks_on_target: stats::weeks_on_target(now, &history, &targets),
+        program_weeks: get_setting(conn, PROGRAM_WEEKS_KEY)?
+            .and_then(|value| value.parse::<u32>().ok())
+            .unwrap_or(DEFAULT_PROGRAM_WEEKS),
+        first_pressure,
+        max_pressure,
+    })
+}
+
+// Records any milestones the active profile has newly reached and returns just those.
+pub fn unlock_new_achievements<Tz: TimeZone>(conn: &Connection, now: &DateTime<Tz>) -> Result<Vec<AchievementStatus>> {
+    let profile_id = active_profile_id(conn)?;
+    let facts = gather_achievement_facts(conn, now)?;
+    let now = now.with_timezone(&Utc);
+    let mut unlocked = Vec::new();
+    for achievement in achievements::earned(&facts) {
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO achievements (profile_id, achievement, unlocked_at) VALUES (?1, ?2, ?3)",
+            params![profile_id, achievement.as_str(), now.to_rfc3339()],
+        )?;
+        if inserted > 0 {
+            unlocked.push(AchievementStatus::new(achievement, Some(now)));
+        }
+    }
+    Ok(unlocked)
+}
+
+// Every achievement with its unlock time, or None if the active profile hasn't reached it.
+pub fn get_achievements(conn: &Connection) -> Result<Vec<AchievementStatus>> {
+    let mut stmt = conn.prepare("SELECT achievement, unlocked_at FROM achievements WHERE profile_id = ?1")?;
+    let unlocked = stmt
+        .query_map([active_profile_id(conn)?], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Achievement::ALL
+        .into_iter()
+        .map(|achievement| {
+            let unlocked_at = unlocked
+                .iter()
+                .find(|(id, _)| Achievement::parse(id) == Some(achievement))
+                .and_then(|(_, at)| DateTime::parse_from_rfc3339(at).ok())
+                .map(|dt| dt.with_timezone(&Utc));
+            AchievementStatus::new(achievement, unlocked_at)
+        })
+        .collect())
+}
+
+// Validates and stores a check-in, replacing an earlier one of the same kind for the session.
+// Returns the check-in with its adverse-symptom flags filled in.
+pub fn save_checkin(conn: &Connection, checkin: &Checkin) -> std::result::Result<Checkin, CheckinError> {
+    checkins::validate_checkin(checkin)?;
+    // Check-ins can only be attached to the active profile's own sessions
+    let owned = conn
+        .query_row(
+            "SELECT 1 FROM sessions WHERE id = ?1 AND profile_id = ?2 AND deleted_at IS NULL",
+            params![checkin.session_id, active_profile_id(conn)?],
+            |_| Ok(()),
+        )
+        .optional()?;
+    if owned.is_none() {
+        return Err(CheckinError::Invalid(vec![FieldError {
+            field: "session_id",
+            message: "Session not found".to_string(),
+        }]));
+    }
+    let mut saved = checkin.clone();
+    saved.flags = checkins::adverse_flags(checkin);
+    saved.recorded_at = Some(Utc::now());
+    conn.execute(
+        "INSERT OR REPLACE INTO session_checkins
+             (session_id, kind, fatigue, breathlessness, dizziness, perceived_exertion, notes, flags, recorded_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            saved.session_id,
+            saved.kind.as_str(),
+            saved.fatigue,
+            saved.breathlessness,
+            saved.dizziness,
+            saved.perceived_exertion,
+            saved.notes,
+            serde_json::to_string(&saved.flags).unwrap_or_else(|_| "[]".to_string()),
+            saved.recorded_at.map(|at| at.to_rfc3339()),
+        ],
+    )?;
+    Ok(saved)
+}
+
+const CHECKIN_COLUMNS: &str =
+    "c.session_id, c.kind, c.fatigue, c.breathlessness, c.dizziness, c.perceived_exertion, c.notes, c.flags, c.recorded_at";
+
+fn checkin_from_row(row: &Row) -> Result<Checkin> {
+    let kind: String = row.get(1)?;
+    let flags: String = row.get(7)?;
+    let recorded_at: String = row.get(8)?;
+    Ok(Checkin {
+        session_id: row.get(0)?,
+        kind: CheckinKind::parse(&kind).unwrap_or(CheckinKind::Pre),
+        fatigue: row.get(2)?,
+        breathlessness: row.get(3)?,
+        dizziness: row.get(4)?,
+        perceived_exertion: row.get(5)?,
+        notes: row.get(6)?,
+        flags: serde_json::from_str(&flags).unwrap_or_default(),
+        recorded_at: DateTime::parse_from_rfc3339(&recorded_at).ok().map(|dt| dt.with_timezone(&Utc)),
+    })
+}
+
+// The check-ins of a session, pre before post.
+pub fn get_session_checkins(conn: &Connection, session_id: i64) -> Result<Vec<Checkin>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM session_checkins c WHERE c.session_id = ?1 ORDER BY c.kind = 'post'",
+        CHECKIN_COLUMNS
+    ))?;
+    stmt.query_map([session_id], checkin_from_row)?.collect()
+}
+
+// A warning to show before the next session if the active profile's most recent check-in
+// reported adverse symptoms. Check-ins of trashed sessions are ignored.
+pub fn get_safety_warning(conn: &Connection) -> Result<Option<SafetyWarning>> {
+    let latest = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM session_checkins c JOIN sessions s ON s.id = c.session_id
+                 WHERE s.profile_id = ?1 AND s.deleted_at IS NULL
+                 ORDER BY c.recorded_at DESC LIMIT 1",
+                CHECKIN_COLUMNS
+            ),
+            [active_profile_id(conn)?],
+            checkin_from_row,
+        )
+        .optional()?;
+    Ok(latest.as_ref().and_then(checkins::safety_warning))
+}
+
+// Sync UUIDs of a profile's sessions by id, which exports use for ids that stay the same
+// from one export to the next.
+fn get_session_uuids(conn: &Connection, profile_id: i64) -> Result<HashMap<i64, String>> {
+    let mut stmt = conn.prepare("SELECT id, uuid FROM sessions WHERE profile_id = ?1 AND uuid IS NOT NULL")?;
+    stmt.query_map([profile_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+        .collect()
+}
+
+// The active profile and its sessions as a FHIR R4 Bundle, in the profile's pressure unit.
+pub fn get_fhir_bundle(conn: &Connection) -> Result<serde_json::Value> {
+    let profile = get_profile(conn)?;
+    let sessions = get_all_sessions(conn)?;
+    let patient_key = format!("{}:{}", sync::device_id(conn)?, profile.id);
+    let uuids = get_session_uuids(conn, profile.id)?;
+    Ok(fhir::bundle(&profile, &patient_key, &sessions, &uuids, get_pressure_unit(conn)?, Utc::now()))
+}
+
+// The active profile's completed sessions and the next `weeks` weeks of planned ones, counting
+// the current week, as an iCalendar file. `now` carries the user's timezone.
+pub fn get_calendar<Tz: TimeZone>(conn: &Connection, now: &DateTime<Tz>, weeks: u32) -> Result<String> {
+    let profile = get_profile(conn)?;
+    let sessions = get_all_sessions(conn)?;
+    let uuids = get_session_uuids(conn, profile.id)?;
+    let mut events: Vec<calendar::Event> = sessions
+        .iter()
+        .rev()
+        .filter_map(|session| calendar::completed_event(uuids.get(&session.id)?, session))
+        .collect();
+
+    let now_utc = now.with_timezone(&Utc);
+    let targets = get_training_targets(conn)?;
+    let history = get_recent_session_end_times(conn, now_utc - Duration::days(8))?;
+    let latest_pressure = sessions.iter().find(|session| session.end_time.is_some()).map(|session| session.pressure.text.as_str());
+    events.extend(
+        calendar::plan(now, &history, &targets, &get_reminder_schedule(conn)?, weeks)
+            .iter()
+            .map(|planned| calendar::planned_event(profile.id, planned, &targets, latest_pressure)),
+    );
+    let name = format!("{} {}", profile.first_name, profile.last_name);
+    Ok(calendar::render(&name, &events, now_utc))
+}
+
+// Scores and stores a completed questionnaire for the active profile.
+pub fn save_questionnaire_response(
+    conn: &Connection,
+    instrument: Instrument,
+    completed_on: NaiveDate,
+    answers: &[u8],
+) -> std::result::Result<QuestionnaireResponse, QuestionnaireError> {
+    let total = questionnaires::score(instrument, answers)?;
+    let recorded_at = Utc::now();
+    conn.execute(
+        "INSERT INTO questionnaire_responses (profile_id, instrument, completed_on, answers, total, recorded_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            active_profile_id(conn)?,
+            instrument.as_str(),
+            format_date(Some(completed_on)),
+            serde_json::to_string(answers).unwrap_or_default(),
+            total,
+            recorded_at.to_rfc3339(),
+        ],
+    )?;
+    Ok(QuestionnaireResponse {
+        id: conn.last_insert_rowid(),
+        instrument,
+        completed_on,
+        answers: answers.to_vec(),
+        total,
+        abnormal: total >= instrument.definition().abnormal_threshold,
+        recorded_at,
+    })
+}
+
+// The active profile's responses, oldest first, optionally for one instrument only.
+pub fn list_questionnaire_responses(conn: &Connection, instrument: Option<Instrument>) -> Result<Vec<QuestionnaireResponse>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, instrument, completed_on, answers, total, recorded_at FROM questionnaire_responses
+         WHERE profile_id = ?1 AND (?2 IS NULL OR instrument = ?2)
+         ORDER BY completed_on, recorded_at"
+    )?;
+    let rows = stmt
+        .query_map(params![active_profile_id(conn)?, instrument.map(|i| i.as_str())], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, u32>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(id, instrument, completed_on, answers, total, recorded_at)| {
+            // Rows for instruments this build doesn't know about are skipped
+            let instrument = Instrument::parse(&instrument)?;
+            Some(QuestionnaireResponse {
+                id,
+                instrument,
+                completed_on: profile::parse_date(&completed_on)?,
+                answers: serde_json::from_str(&answers).unwrap_or_default(),
+                total,
+                abnormal: total >= instrument.definition().abnormal_threshold,
+                recorded_at: DateTime::parse_from_rfc3339(&recorded_at).ok()?.with_timezone(&Utc),
+            })
+        })
+        .collect())
+}
+
+pub fn get_questionnaire_trend(conn: &Connection, instrument: Instrument) -> Result<QuestionnaireTrend> {
+    let responses = list_questionnaire_responses(conn, Some(instrument))?;
+    Ok(questionnaires::trend(instrument, &responses))
+}
+
+// Gets a setting's value for a given key in the active profile. It returns an Option<String>
 // which will be Some(value) if the key exists, and None if it doesn't.
 pub fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>> {
-    let mut stmt = conn.prepare("SELECT value FROM app_settings WHERE key = ?1")?;
-    let mut rows = stmt.query([key])?;
+    let profile_id = active_profile_id(conn)?;
+    let mut stmt = conn.prepare("SELECT value FROM profile_settings WHERE profile_id = ?1 AND key = ?2")?;
+    let mut rows = stmt.query(params![profile_id, key])?;
 
     if let Some(row) = rows.next()? {
         Ok(Some(row.get(0)?))
@@ -255,9 +1042,66 @@ pub fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>> {
     }
 }
 
-// Sets a setting's value for a given key.
+// Sets a setting's value for a given key in the active profile.
 // Uses "INSERT OR REPLACE" to create the setting if it's new or update it if it exists.
 pub fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<()> {
+    let profile_id = active_profile_id(conn)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO profile_settings (profile_id, key, value) VALUES (?1, ?2, ?3)",
+        params![profile_id, key, value],
+    )?;
+    Ok(())
+}
+
+// The active profile's preferred pressure display unit, cmH2O unless set otherwise.
+pub fn get_pressure_unit(conn: &Connection) -> Result<PressureUnit> {
+    Ok(get_setting(conn, PRESSURE_UNIT_KEY)?
+        .and_then(|value| PressureUnit::parse(&value))
+        .unwrap_or_default())
+}
+
+// The active profile's daily and weekly session targets.
+pub fn get_training_targets(conn: &Connection) -> Result<TrainingTargets> {
+    let per_week = get_setting(conn, GOAL_SESSIONS_PER_WEEK_KEY)?
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_SESSIONS_PER_WEEK);
+    let per_day = get_setting(conn, GOAL_SESSIONS_PER_DAY_KEY)?
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(0);
+    Ok(TrainingTargets { per_day, per_week })
+}
+
+// The active profile's reminder schedule. A missing or unreadable schedule is treated as
+// empty so reminders fall back to the goal-based engine alone.
+pub fn get_reminder_schedule(conn: &Connection) -> Result<ReminderSchedule> {
+    Ok(get_setting(conn, REMINDER_SCHEDULE_KEY)?
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default())
+}
+
+pub fn set_reminder_schedule(conn: &Connection, schedule: &ReminderSchedule) -> Result<()> {
+    let json = serde_json::to_string(schedule)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    set_setting(conn, REMINDER_SCHEDULE_KEY, &json)
+}
+
+// The keys that belong in app_settings. Anything else found there predates profiles and is
+// handed to the first profile by migrate.
+const DEVICE_SETTINGS: (&str, &str, &str, &str, &str) = (
+    ACTIVE_PROFILE_ID_KEY,
+    TRASH_RETENTION_DAYS_KEY,
+    sync::DEVICE_ID_KEY,
+    snapshots::SCHEDULE_KEY,
+    snapshots::KEEP_KEY,
+);
+
+// Device-wide settings that don't belong to any one profile, such as which profile is active.
+pub fn get_app_setting(conn: &Connection, key: &str) -> Result<Option<String>> {
+    conn.query_row("SELECT value FROM app_settings WHERE key = ?1", [key], |row| row.get(0))
+        .optional()
+}
+
+pub fn set_app_setting(conn: &Connection, key: &str, value: &str) -> Result<()> {
     conn.execute(
         "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
         (key, value),
@@ -268,26 +1112,319 @@ pub fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<()> {
 pub fn get_session_count_for_current_week(conn: &Connection) -> Result<i32> {
     conn.query_row(
         // Use end_time and ensure it's not NULL
-        "SELECT COUNT(*) FROM sessions WHERE end_time IS NOT NULL AND strftime('%Y-%W', end_time) = strftime('%Y-%W', 'now', 'localtime')",
-        [],
+        "SELECT COUNT(*) FROM sessions WHERE profile_id = ?1 AND end_time IS NOT NULL AND deleted_at IS NULL AND strftime('%Y-%W', end_time) = strftime('%Y-%W', 'now', 'localtime')",
+        [active_profile_id(conn)?],
         |row| row.get(0),
     ).map_err(|e| e.into())
 }
 
 pub fn get_last_session_end_time(conn: &Connection) -> Result<Option<String>> {
     conn.query_row(
-        "SELECT MAX(end_time) FROM sessions WHERE end_time IS NOT NULL",
-        [],
+        "SELECT MAX(end_time) FROM sessions WHERE profile_id = ?1 AND end_time IS NOT NULL AND deleted_at IS NULL",
+        [active_profile_id(conn)?],
         |row| row.get(0),
     )
     .optional() // This makes it return Ok(None) if no rows are found, instead of an Error
 }
 
+// End times of completed sessions since `since`, plus the most recent one even if it is
+// older. That is all the history the reminder engine needs.
+pub fn get_recent_session_end_times(conn: &Connection, since: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>> {
+    let mut stmt = conn.prepare(
+        "SELECT end_time FROM sessions
+         WHERE profile_id = ?1 AND end_time IS NOT NULL AND deleted_at IS NULL
+           AND (end_time >= ?2 OR end_time = (
+               SELECT MAX(end_time) FROM sessions
+               WHERE profile_id = ?1 AND end_time IS NOT NULL AND deleted_at IS NULL))
+         ORDER BY end_time"
+    )?;
+    let end_times = stmt
+        .query_map(params![active_profile_id(conn)?, since.to_rfc3339()], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(end_times
+        .iter()
+        .filter_map(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|dt| dt.with_timezone(&Utc))
+        .collect())
+}
+
+// (session id, end time, pressure) for every completed session, oldest first.
+pub fn get_pressure_history(conn: &Connection) -> Result<Vec<(i64, DateTime<Utc>, i32)>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, end_time, pressure_setting FROM sessions
+         WHERE profile_id = ?1 AND end_time IS NOT NULL AND deleted_at IS NULL
+         ORDER BY end_time"
+    )?;
+    let rows = stmt
+        .query_map([active_profile_id(conn)?], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, i32>(2)?))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(id, end_time, pressure)| {
+            let end_time = DateTime::parse_from_rfc3339(&end_time).ok()?.with_timezone(&Utc);
+            Some((id, end_time, pressure))
+        })
+        .collect())
+}
+
+// Moves a session to the trash. The session and its reps stay in the database until
+// they are restored or purged, but are hidden from every other query.
 pub fn delete_session(conn: &Connection, session_id: i64) -> Result<()> {
-    let rows_affected = conn.execute("DELETE FROM sessions WHERE id = ?1", [session_id])?;
+    let now = Utc::now().to_rfc3339();
+    let rows_affected = conn.execute(
+        "UPDATE sessions SET deleted_at = ?1 WHERE id = ?2 AND profile_id = ?3 AND deleted_at IS NULL",
+        params![now, session_id, active_profile_id(conn)?],
+    )?;
+    if rows_affected == 0 {
+        Err(rusqlite::Error::QueryReturnedNoRows)
+    } else {
+        Ok(())
+    }
+}
+
+pub fn list_deleted_sessions(conn: &Connection) -> Result<Vec<Session>> {
+    let mut stmt = conn.prepare(
+        "SELECT s.id, s.start_time, s.end_time, s.pressure_setting, s.notes, COUNT(r.id) as rep_count, s.deleted_at
+         FROM sessions s
+         LEFT JOIN reps r ON s.id = r.session_id
+         WHERE s.profile_id = ?1 AND s.deleted_at IS NOT NULL
+         GROUP BY s.id
+         ORDER BY s.deleted_at DESC"
+    )?;
+    let mut rows = stmt.query([active_profile_id(conn)?])?;
+    let (mut sessions, skipped) = read_sessions(&mut rows, get_pressure_unit(conn)?)?;
+    log_skipped(&skipped);
+    attach_scores(conn, &mut sessions)?;
+    Ok(sessions)
+}
+
+pub fn restore_session(conn: &Connection, session_id: i64) -> Result<()> {
+    let rows_affected = conn.execute(
+        "UPDATE sessions SET deleted_at = NULL WHERE id = ?1 AND profile_id = ?2 AND deleted_at IS NOT NULL",
+        params![session_id, active_profile_id(conn)?],
+    )?;
     if rows_affected == 0 {
         Err(rusqlite::Error::QueryReturnedNoRows)
     } else {
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// How long a deleted session stays in the trash before it is permanently removed.
+// This is a device-wide setting in app_settings since purging covers every profile.
+pub const TRASH_RETENTION_DAYS_KEY: &str = "trash_retention_days";
+pub const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
+
+pub fn get_trash_retention_days(conn: &Connection) -> Result<i64> {
+    Ok(get_app_setting(conn, TRASH_RETENTION_DAYS_KEY)?
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|days| *days >= 0)
+        .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS))
+}
+
+// 0 empties the trash at the next start.
+pub fn set_trash_retention_days(conn: &Connection, days: i64) -> Result<()> {
+    set_app_setting(conn, TRASH_RETENTION_DAYS_KEY, &days.max(0).to_string())
+}
+
+// Permanently deletes sessions that have been in the trash longer than the configured
+// retention period. Their reps are removed by the ON DELETE CASCADE on the reps table.
+// Returns the number of sessions purged.
+pub fn purge_expired_sessions(conn: &Connection) -> Result<usize> {
+    let retention_days = get_trash_retention_days(conn)?;
+    let cutoff = (Utc::now() - Duration::days(retention_days)).to_rfc3339();
+    conn.execute(
+        "DELETE FROM sessions WHERE deleted_at IS NOT NULL AND deleted_at < ?1",
+        [cutoff],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkins::AdverseFlag;
+
+    #[test]
+    fn trash_operations_only_touch_the_active_profile() {
+        let conn = initialize_database(":memory:").unwrap();
+        let first = start_new_session(&conn, 30, "").unwrap();
+        end_session(&conn, first, "", &Utc::now()).unwrap();
+        let other = create_profile(&conn, &ProfileInput { first_name: "B".into(), ..Default::default() }).unwrap();
+        switch_profile(&conn, other).unwrap();
+
+        assert!(delete_session(&conn, first).is_err());
+        switch_profile(&conn, 1).unwrap();
+        delete_session(&conn, first).unwrap();
+        switch_profile(&conn, other).unwrap();
+        assert!(restore_session(&conn, first).is_err());
+        switch_profile(&conn, 1).unwrap();
+        restore_session(&conn, first).unwrap();
+        assert_eq!(get_all_sessions(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn trash_retention_is_shared_by_all_profiles() {
+        let conn = initialize_database(":memory:").unwrap();
+        assert_eq!(get_trash_retention_days(&conn).unwrap(), DEFAULT_TRASH_RETENTION_DAYS);
+        set_trash_retention_days(&conn, 7).unwrap();
+        let other = create_profile(&conn, &ProfileInput::default()).unwrap();
+        switch_profile(&conn, other).unwrap();
+        assert_eq!(get_trash_retention_days(&conn).unwrap(), 7);
+        set_trash_retention_days(&conn, -3).unwrap();
+        assert_eq!(get_trash_retention_days(&conn).unwrap(), 0);
+    }
+
+    #[test]
+    fn streak_achievements_are_counted_at_the_given_time() {
+        let conn = initialize_database(":memory:").unwrap();
+        set_setting(&conn, stats::GOAL_SESSIONS_PER_WEEK_KEY, "1").unwrap();
+        let monday = Utc.with_ymd_and_hms(2024, 6, 3, 9, 0, 0).unwrap();
+        for week in 1..=4 {
+            let session = start_new_session(&conn, 30, "").unwrap();
+            conn.execute(
+                "UPDATE sessions SET end_time = ?1 WHERE id = ?2",
+                params![(monday - Duration::weeks(week)).to_rfc3339(), session],
+            )
+            .unwrap();
+        }
+
+        let unlocked = unlock_new_achievements(&conn, &monday).unwrap();
+        let ids: Vec<Achievement> = unlocked.iter().map(|status| status.id).collect();
+        assert_eq!(ids, vec![Achievement::FirstSession, Achievement::FourWeekStreak]);
+        assert!(unlocked.iter().all(|status| status.unlocked_at == Some(monday)));
+
+        // Seen from two weeks later the streak is broken, but unlocks are never taken back
+        let later = unlock_new_achievements(&conn, &(monday + Duration::weeks(2))).unwrap();
+        assert!(later.is_empty());
+    }
+
+    #[test]
+    fn checkins_only_attach_to_the_active_profiles_sessions() {
+        let conn = initialize_database(":memory:").unwrap();
+        let session = start_new_session(&conn, 30, "").unwrap();
+        let checkin = Checkin {
+            session_id: session,
+            kind: CheckinKind::Pre,
+            fatigue: 2,
+            breathlessness: 8,
+            dizziness: false,
+            perceived_exertion: None,
+            notes: String::new(),
+            flags: Vec::new(),
+            recorded_at: None,
+        };
+        let saved = save_checkin(&conn, &checkin).unwrap();
+        assert_eq!(saved.flags, vec![AdverseFlag::SevereBreathlessness]);
+
+        let other = create_profile(&conn, &ProfileInput::default()).unwrap();
+        switch_profile(&conn, other).unwrap();
+        let error = save_checkin(&conn, &checkin).unwrap_err();
+        assert_eq!(error.field_errors()[0].field, "session_id");
+        assert!(save_checkin(&conn, &Checkin { session_id: 999, ..checkin }).is_err());
+    }
+
+    #[test]
+    fn purge_removes_only_sessions_past_the_retention_period() {
+        let conn = initialize_database(":memory:").unwrap();
+        let deleted_days_ago = |days: i64| {
+            let session = start_new_session(&conn, 30, "").unwrap();
+            add_rep(&conn, session).unwrap();
+            end_session(&conn, session, "", &Utc::now()).unwrap();
+            let deleted_at = (Utc::now() - Duration::days(days)).to_rfc3339();
+            conn.execute("UPDATE sessions SET deleted_at = ?1 WHERE id = ?2", params![deleted_at, session]).unwrap();
+            session
+        };
+        let expired = deleted_days_ago(DEFAULT_TRASH_RETENTION_DAYS + 1);
+        let recent = deleted_days_ago(2);
+        let kept = start_new_session(&conn, 30, "").unwrap();
+        let exists = |id: i64| -> bool {
+            conn.query_row("SELECT COUNT(*) FROM sessions WHERE id = ?1", [id], |row| row.get::<_, i64>(0)).unwrap() == 1
+        };
+
+        assert_eq!(purge_expired_sessions(&conn).unwrap(), 1);
+        assert!(!exists(expired) && exists(recent) && exists(kept));
+        let orphans: i64 = conn
+            .query_row("SELECT COUNT(*) FROM reps WHERE session_id = ?1", [expired], |row| row.get(0))
+            .unwrap();
+        assert_eq!(orphans, 0);
+
+        // With no retention everything in the trash goes, and nothing else
+        set_trash_retention_days(&conn, 0).unwrap();
+        assert_eq!(purge_expired_sessions(&conn).unwrap(), 1);
+        assert!(!exists(recent) && exists(kept));
+    }
+
+    #[test]
+    fn sessions_and_profile_updates_stay_with_the_active_profile() {
+        let conn = initialize_database(":memory:").unwrap();
+        let session = start_new_session(&conn, 30, "").unwrap();
+        let other = create_profile(&conn, &ProfileInput { first_name: "B".into(), ..Default::default() }).unwrap();
+        switch_profile(&conn, other).unwrap();
+
+        assert!(add_rep(&conn, session).is_err());
+        assert!(end_session(&conn, session, "", &Utc::now()).is_err());
+        // The update lands on the active profile, never on another
+        update_profile(&conn, &ProfileInput { first_name: "Renamed".into(), ..Default::default() }).unwrap();
+        assert_eq!(get_profile(&conn).unwrap().first_name, "Renamed");
+
+        switch_profile(&conn, 1).unwrap();
+        assert_eq!(get_profile(&conn).unwrap().first_name, "");
+        assert_eq!(get_total_reps(&conn, session).unwrap(), 0);
+        add_rep(&conn, session).unwrap();
+        end_session(&conn, session, "", &Utc::now()).unwrap();
+        assert_eq!(get_total_reps(&conn, session).unwrap(), 1);
+    }
+
+    #[test]
+    fn free_text_dates_of_birth_survive_profile_edits() {
+        let conn = initialize_database(":memory:").unwrap();
+        let id = active_profile_id(&conn).unwrap();
+        conn.execute("UPDATE user_profile SET dob = '10/05/1950' WHERE id = ?1", [id]).unwrap();
+        migrate(&conn).unwrap();
+        let profile = get_profile(&conn).unwrap();
+        assert_eq!((profile.dob, profile.dob_legacy.as_deref()), (None, Some("10/05/1950")));
+
+        update_profile(&conn, &ProfileInput { first_name: "Ann".into(), ..Default::default() }).unwrap();
+        assert_eq!(get_profile(&conn).unwrap().dob_legacy.as_deref(), Some("10/05/1950"));
+
+        let dob = Some("1950-05-10".to_string());
+        update_profile(&conn, &ProfileInput { dob, ..Default::default() }).unwrap();
+        let profile = get_profile(&conn).unwrap();
+        assert_eq!((profile.dob, profile.dob_legacy), (NaiveDate::from_ymd_opt(1950, 5, 10), None));
+    }
+
+    #[test]
+    fn calendar_is_planned_and_stamped_at_the_given_time() {
+        let conn = initialize_database(":memory:").unwrap();
+        set_setting(&conn, stats::GOAL_SESSIONS_PER_WEEK_KEY, "1").unwrap();
+        let session = start_new_session(&conn, 30, "").unwrap();
+        end_session(&conn, session, "", &Utc::now()).unwrap();
+        let tuesday = Utc.with_ymd_and_hms(2024, 5, 7, 18, 0, 0).unwrap();
+        conn.execute(
+            "UPDATE sessions SET start_time = ?1, end_time = ?1 WHERE id = ?2",
+            params![tuesday.to_rfc3339(), session],
+        )
+        .unwrap();
+
+        // Wednesday evening in UTC+2: this week's session is done, next week's is still to come
+        let now = chrono::FixedOffset::east_opt(2 * 3600).unwrap().with_ymd_and_hms(2024, 5, 8, 20, 0, 0).unwrap();
+        let ics = get_calendar(&conn, &now, 2).unwrap();
+        assert!(ics.contains("DTSTAMP:20240508T180000Z"));
+        assert_eq!(ics.matches("STATUS:CONFIRMED").count(), 1);
+        assert_eq!(ics.matches("STATUS:TENTATIVE").count(), 1);
+        assert!(ics.contains("DTSTART;VALUE=DATE:20240513"));
+    }
+
+    #[test]
+    fn sessions_end_at_the_given_time() {
+        let conn = initialize_database(":memory:").unwrap();
+        let session = start_new_session(&conn, 30, "").unwrap();
+        let now = chrono::FixedOffset::west_opt(5 * 3600).unwrap().with_ymd_and_hms(2024, 6, 2, 22, 30, 0).unwrap();
+        let unlocked = end_session(&conn, session, "", &now).unwrap();
+        assert_eq!(unlocked[0].id, Achievement::FirstSession);
+        assert_eq!(unlocked[0].unlocked_at, Some(now.with_timezone(&Utc)));
+        assert_eq!(get_all_sessions(&conn).unwrap()[0].end_time, Some(now.with_timezone(&Utc)));
+    }
+}
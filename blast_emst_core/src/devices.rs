@@ -0,0 +1,156 @@
+use serde::Serialize;
+use std::fmt;
+
+// --- Trainer device catalog ---
+
+// A spring-loaded pressure threshold trainer. All pressures are in cmH2O.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct TrainerDevice {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub manufacturer: &'static str,
+    pub min_pressure: i32,
+    pub max_pressure: i32,
+    pub step: i32,
+    // Where a new user on this device usually starts before the therapist adjusts it
+    pub default_pressure: i32,
+}
+
+pub const CATALOG: [TrainerDevice; 3] = [
+    TrainerDevice {
+        id: "emst150",
+        name: "EMST150",
+        manufacturer: "Aspire Products",
+        min_pressure: 30,
+        max_pressure: 150,
+        step: 5,
+        default_pressure: 30,
+    },
+    TrainerDevice {
+        id: "emst75",
+        name: "EMST75 Lite",
+        manufacturer: "Aspire Products",
+        min_pressure: 5,
+        max_pressure: 75,
+        step: 5,
+        default_pressure: 20,
+    },
+    TrainerDevice {
+        id: "threshold_pep",
+        name: "Threshold PEP",
+        manufacturer: "Philips Respironics",
+        min_pressure: 4,
+        max_pressure: 20,
+        step: 1,
+        default_pressure: 10,
+    },
+];
+
+// Used when the profile doesn't name a device, matching the app's historical default
+pub const FALLBACK_DEFAULT_PRESSURE: i32 = 30;
+
+pub fn find_device(id: &str) -> Option<&'static TrainerDevice> {
+    CATALOG.iter().find(|device| device.id == id)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PressureError {
+    NotPositive(i32),
+    OutOfRange { device: &'static str, pressure: i32, min: i32, max: i32 },
+}
+
+impl fmt::Display for PressureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PressureError::NotPositive(pressure) => write!(f, "pressure {} cmH2O must be positive", pressure),
+            PressureError::OutOfRange { device, pressure, min, max } => write!(
+                f,
+                "pressure {} cmH2O is outside the {} range of {}-{} cmH2O",
+                pressure, device, min, max
+            ),
+        }
+    }
+}
+
+impl TrainerDevice {
+    // Snaps a requested pressure to the nearest setting the device can actually be dialled to,
+    // rejecting anything outside the device's range. The range check uses the pressure as
+    // entered so both ends of the dial behave the same way.
+    pub fn normalize_pressure(&self, pressure: i32) -> Result<i32, PressureError> {
+        let out_of_range = || PressureError::OutOfRange {
+            device: self.name,
+            pressure,
+            min: self.min_pressure,
+            max: self.max_pressure,
+        };
+        if pressure < self.min_pressure || pressure > self.max_pressure {
+            return Err(out_of_range());
+        }
+        // Round half up to the nearest step, then back off a step if that lands past the top
+        let offset = pressure.checked_sub(self.min_pressure).ok_or_else(out_of_range)?;
+        let steps = offset.checked_add(self.step / 2).ok_or_else(out_of_range)? / self.step;
+        let rounded = self.min_pressure + steps * self.step;
+        Ok(if rounded > self.max_pressure { rounded - self.step } else { rounded })
+    }
+}
+
+// Validates a pressure for the given device, or just checks it is positive when the user
+// hasn't picked a device.
+pub fn normalize_pressure(device: Option<&TrainerDevice>, pressure: i32) -> Result<i32, PressureError> {
+    match device {
+        Some(device) => device.normalize_pressure(pressure),
+        None if pressure > 0 => Ok(pressure),
+        None => Err(PressureError::NotPositive(pressure)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emst150() -> &'static TrainerDevice {
+        find_device("emst150").unwrap()
+    }
+
+    #[test]
+    fn snaps_to_the_nearest_step() {
+        assert_eq!(emst150().normalize_pressure(30), Ok(30));
+        assert_eq!(emst150().normalize_pressure(32), Ok(30));
+        assert_eq!(emst150().normalize_pressure(33), Ok(35));
+        assert_eq!(find_device("threshold_pep").unwrap().normalize_pressure(13), Ok(13));
+    }
+
+    #[test]
+    fn both_ends_of_the_range_are_checked_before_rounding() {
+        let device = emst150();
+        assert_eq!(device.normalize_pressure(150), Ok(150));
+        assert_eq!(device.normalize_pressure(148), Ok(150));
+        for pressure in [28, 29, 151, 153] {
+            assert!(
+                matches!(device.normalize_pressure(pressure), Err(PressureError::OutOfRange { .. })),
+                "{} should be out of range",
+                pressure
+            );
+        }
+    }
+
+    #[test]
+    fn extreme_values_are_rejected_without_overflowing() {
+        for device in &CATALOG {
+            assert!(device.normalize_pressure(i32::MIN).is_err());
+            assert!(device.normalize_pressure(i32::MAX).is_err());
+        }
+    }
+
+    #[test]
+    fn off_grid_maximum_is_never_exceeded() {
+        let device = TrainerDevice { max_pressure: 33, ..*emst150() };
+        assert_eq!(device.normalize_pressure(33), Ok(30));
+    }
+
+    #[test]
+    fn without_a_device_only_positive_pressures_pass() {
+        assert_eq!(normalize_pressure(None, 1), Ok(1));
+        assert_eq!(normalize_pressure(None, 0), Err(PressureError::NotPositive(0)));
+    }
+}
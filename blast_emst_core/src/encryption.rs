@@ -0,0 +1,232 @@
+use rusqlite::{Connection, DatabaseName, OptionalExtension};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+// --- At-rest encryption ---
+//
+// Encryption is provided by SQLCipher when the crate is built with the `sqlcipher` feature.
+// Support is detected at runtime with `PRAGMA cipher_version`, so a build without the
+// feature refuses keyed databases instead of silently storing data in plaintext.
+
+// Raw 256-bit key, handed over by the app's keystore as 64 hex digits. A raw key skips
+// SQLCipher's passphrase derivation, which the keystore makes unnecessary.
+#[derive(Clone)]
+pub struct DatabaseKey {
+    hex: String,
+}
+
+impl DatabaseKey {
+    pub fn from_hex(hex: &str) -> Option<DatabaseKey> {
+        let valid = hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit());
+        valid.then(|| DatabaseKey { hex: hex.to_ascii_lowercase() })
+    }
+
+    // The form SQLCipher expects for raw keys in PRAGMA key and ATTACH ... KEY
+    fn sql_value(&self) -> String {
+        format!("x'{}'", self.hex)
+    }
+}
+
+impl fmt::Debug for DatabaseKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DatabaseKey(..)")
+    }
+}
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    // The build has no SQLCipher support
+    Unsupported,
+    // The database is encrypted with a different key, or isn't a database at all
+    WrongKey,
+    Io(io::Error),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncryptionError::Unsupported => write!(f, "built without SQLCipher support"),
+            EncryptionError::WrongKey => write!(f, "the database key is incorrect"),
+            EncryptionError::Io(e) => write!(f, "{}", e),
+            EncryptionError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for EncryptionError {
+    fn from(e: rusqlite::Error) -> Self {
+        EncryptionError::Database(e)
+    }
+}
+
+impl From<io::Error> for EncryptionError {
+    fn from(e: io::Error) -> Self {
+        EncryptionError::Io(e)
+    }
+}
+
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+// Plaintext SQLite files start with a fixed header; SQLCipher files look like random bytes.
+// Missing and empty files are new databases, not plaintext ones.
+pub fn is_plaintext(path: &Path) -> io::Result<bool> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let mut header = [0u8; 16];
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(&header == SQLITE_HEADER),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn cipher_available(conn: &Connection) -> Result<bool, rusqlite::Error> {
+    let version: Option<String> = conn.query_row("PRAGMA cipher_version", [], |row| row.get(0)).optional()?;
+    Ok(version.is_some())
+}
+
+// Keys a freshly opened connection and checks the key can read the database.
+pub fn apply_key(conn: &Connection, key: &DatabaseKey) -> Result<(), EncryptionError> {
+    if !cipher_available(conn)? {
+        return Err(EncryptionError::Unsupported);
+    }
+    conn.pragma_update(None, "key", key.sql_value())?;
+    match conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0)) {
+        Ok(_) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::NotADatabase => {
+            Err(EncryptionError::WrongKey)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Rewrites a plaintext database encrypted with `key`. The encrypted copy is written next
+// to the original and renamed over it once complete, so an interruption leaves the
+// plaintext database intact.
+pub fn encrypt_in_place(path: &Path, key: &DatabaseKey) -> Result<(), EncryptionError> {
+    let mut staging = path.as_os_str().to_owned();
+    staging.push(".encrypting");
+    let staging = Path::new(&staging);
+    if staging.exists() {
+        fs::remove_file(staging)?;
+    }
+    {
+        let conn = Connection::open(path)?;
+        if !cipher_available(&conn)? {
+            return Err(EncryptionError::Unsupported);
+        }
+        let user_version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        conn.execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            (staging.to_string_lossy(), key.sql_value()),
+        )?;
+        conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+        conn.pragma_update(Some(DatabaseName::Attached("encrypted")), "user_version", user_version)?;
+        conn.execute("DETACH DATABASE encrypted", [])?;
+    }
+    fs::rename(staging, path)?;
+    Ok(())
+}
+
+// Opens the database at `path` with `key`, first encrypting it if it is still plaintext.
+pub fn open_encrypted(path: &Path, key: &DatabaseKey) -> Result<Connection, EncryptionError> {
+    if is_plaintext(path)? {
+        encrypt_in_place(path, key)?;
+    }
+    let conn = Connection::open(path)?;
+    apply_key(&conn, key)?;
+    Ok(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn key(digit: char) -> DatabaseKey {
+        DatabaseKey::from_hex(&digit.to_string().repeat(64)).unwrap()
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("emst-encryption-{}.db", crate::sync::new_uuid()))
+    }
+
+    fn staging_path(path: &Path) -> PathBuf {
+        let mut staging = path.as_os_str().to_owned();
+        staging.push(".encrypting");
+        PathBuf::from(staging)
+    }
+
+    // A plaintext database with one row and a schema version
+    fn plaintext_database() -> PathBuf {
+        let path = temp_path();
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch("CREATE TABLE notes (text TEXT); INSERT INTO notes VALUES ('hello'); PRAGMA user_version = 7;")
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn keys_must_be_64_hex_digits() {
+        assert!(DatabaseKey::from_hex(&"A".repeat(64)).is_some());
+        assert!(DatabaseKey::from_hex(&"a".repeat(63)).is_none());
+        assert!(DatabaseKey::from_hex(&"g".repeat(64)).is_none());
+        assert_eq!(format!("{:?}", key('a')), "DatabaseKey(..)");
+    }
+
+    #[test]
+    fn missing_and_empty_files_are_not_plaintext() {
+        let path = temp_path();
+        assert!(!is_plaintext(&path).unwrap());
+        fs::write(&path, b"").unwrap();
+        assert!(!is_plaintext(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn encrypts_in_place_through_a_staging_file() {
+        let path = plaintext_database();
+        // A copy left behind by an interrupted attempt is replaced
+        fs::write(staging_path(&path), b"half written").unwrap();
+        encrypt_in_place(&path, &key('a')).unwrap();
+        assert!(!is_plaintext(&path).unwrap());
+        assert!(!staging_path(&path).exists());
+
+        let conn = open_encrypted(&path, &key('a')).unwrap();
+        let text: String = conn.query_row("SELECT text FROM notes", [], |row| row.get(0)).unwrap();
+        let user_version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!((text.as_str(), user_version), ("hello", 7));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn a_different_key_is_reported_as_wrong() {
+        let path = plaintext_database();
+        drop(open_encrypted(&path, &key('a')).unwrap());
+        assert!(matches!(open_encrypted(&path, &key('b')), Err(EncryptionError::WrongKey)));
+        // A wrong key must not be mistaken for a plaintext database and re-encrypted
+        assert!(open_encrypted(&path, &key('a')).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(not(feature = "sqlcipher"))]
+    #[test]
+    fn builds_without_sqlcipher_refuse_keys_and_leave_data_alone() {
+        let path = plaintext_database();
+        assert!(matches!(encrypt_in_place(&path, &key('a')), Err(EncryptionError::Unsupported)));
+        assert!(matches!(open_encrypted(&path, &key('a')), Err(EncryptionError::Unsupported)));
+        let conn = Connection::open(&path).unwrap();
+        assert!(matches!(apply_key(&conn, &key('a')), Err(EncryptionError::Unsupported)));
+        assert!(is_plaintext(&path).unwrap());
+        assert!(!staging_path(&path).exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,358 @@
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use serde_json::{Map, Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use uuid::{Builder, Uuid};
+
+use crate::db::{Session, UserProfile};
+use crate::units::{Pressure, PressureUnit};
+
+// --- HL7 FHIR R4 export ---
+//
+// Builds a `collection` Bundle with the active profile as a Patient, each completed session
+// as a Procedure plus an Observation of its pressure, reps and duration, and the baseline
+// MEP as an Observation. Resources reference each other by urn:uuid full URLs and carry an
+// identifier with the app's own id. The UUIDs are derived from the session UUIDs and the
+// patient key, so exporting again gives every resource the same id and receivers can update
+// rather than duplicate.
+// Pressures are given in the profile's display unit, the same values the user sees in the app.
+
+const IDENTIFIER_SYSTEM: &str = "urn:blast-emst";
+const UCUM: &str = "http://unitsofmeasure.org";
+const OBSERVATION_CATEGORY: &str = "http://terminology.hl7.org/CodeSystem/observation-category";
+const EMST_TEXT: &str = "Expiratory muscle strength training";
+
+fn instant(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn date(date: NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+fn identifier(kind: &str, id: i64) -> Value {
+    json!([{ "system": format!("{}:{}", IDENTIFIER_SYSTEM, kind), "value": id.to_string() }])
+}
+
+fn category(code: &str, display: &str) -> Value {
+    json!([{ "coding": [{ "system": OBSERVATION_CATEGORY, "code": code, "display": display }] }])
+}
+
+fn quantity(value: f64, unit: &str, code: &str) -> Value {
+    json!({ "value": value, "unit": unit, "system": UCUM, "code": code })
+}
+
+fn ucum_code(unit: PressureUnit) -> &'static str {
+    match unit {
+        PressureUnit::CmH2O => "cm[H2O]",
+        PressureUnit::MmHg => "mm[Hg]",
+        PressureUnit::KPa => "kPa",
+    }
+}
+
+fn pressure_quantity(cmh2o: f64, unit: PressureUnit) -> Value {
+    quantity(Pressure::from_cmh2o(cmh2o).display_value(unit), unit.symbol(), ucum_code(unit))
+}
+
+// FHIR forbids empty strings, so optional text only goes in when there is some.
+fn insert_text(resource: &mut Map<String, Value>, key: &str, value: Value, text: &str) {
+    if !text.trim().is_empty() {
+        resource.insert(key.to_string(), value);
+    }
+}
+
+// A version 8 UUID hashed from the resource kind and a key that identifies its source row
+fn stable_id(kind: &str, key: &str) -> Uuid {
+    let digest = Sha256::digest(format!("{}:{}:{}", IDENTIFIER_SYSTEM, kind, key));
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    Builder::from_custom_bytes(bytes).into_uuid()
+}
+
+struct Entry {
+    full_url: String,
+    resource: Value,
+}
+
+fn entry(id: Uuid, resource: Map<String, Value>) -> Entry {
+    let mut resource = resource;
+    resource.insert("id".to_string(), Value::from(id.to_string()));
+    Entry { full_url: format!("urn:uuid:{}", id), resource: Value::Object(resource) }
+}
+
+fn patient(profile: &UserProfile, id: Uuid) -> Entry {
+    let mut resource = Map::new();
+    resource.insert("resourceType".to_string(), json!("Patient"));
+    resource.insert("identifier".to_string(), identifier("profile", profile.id));
+    let mut name = Map::new();
+    insert_text(&mut name, "family", json!(profile.last_name), &profile.last_name);
+    insert_text(&mut name, "given", json!([profile.first_name]), &profile.first_name);
+    if !name.is_empty() {
+        resource.insert("name".to_string(), json!([name]));
+    }
+    if let Some(dob) = profile.dob {
+        resource.insert("birthDate".to_string(), json!(date(dob)));
+    }
+    insert_text(
+        &mut resource,
+        "generalPractitioner",
+        json!([{ "display": profile.speech_therapist }]),
+        &profile.speech_therapist,
+    );
+    entry(id, resource)
+}
+
+fn procedure(session: &Session, id: Uuid, patient: &str, reason: Option<&str>) -> Entry {
+    let mut resource = Map::new();
+    resource.insert("resourceType".to_string(), json!("Procedure"));
+    resource.insert("identifier".to_string(), identifier("session", session.id));
+    let status = if session.end_time.is_some() { "completed" } else { "in-progress" };
+    resource.insert("status".to_string(), json!(status));
+    resource.insert("code".to_string(), json!({ "text": EMST_TEXT }));
+    resource.insert("subject".to_string(), json!({ "reference": patient }));
+    let mut period = Map::new();
+    period.insert("start".to_string(), json!(instant(session.start_time)));
+    if let Some(end) = session.end_time {
+        period.insert("end".to_string(), json!(instant(end)));
+    }
+    resource.insert("performedPeriod".to_string(), Value::Object(period));
+    if let Some(reason) = reason {
+        resource.insert("reasonCode".to_string(), json!([{ "text": reason }]));
+    }
+    insert_text(&mut resource, "note", json!([{ "text": session.notes }]), &session.notes);
+    entry(id, resource)
+}
+
+fn session_observation(session: &Session, end: DateTime<Utc>, unit: PressureUnit, id: Uuid, patient: &str, procedure: &str) -> Entry {
+    let duration = (end - session.start_time).num_seconds().max(0) as f64;
+    let mut resource = Map::new();
+    resource.insert("resourceType".to_string(), json!("Observation"));
+    resource.insert("identifier".to_string(), identifier("session-observation", session.id));
+    resource.insert("status".to_string(), json!("final"));
+    resource.insert("partOf".to_string(), json!([{ "reference": procedure }]));
+    resource.insert("category".to_string(), category("therapy", "Therapy"));
+    resource.insert("code".to_string(), json!({ "text": "EMST session" }));
+    resource.insert("subject".to_string(), json!({ "reference": patient }));
+    resource.insert("effectivePeriod".to_string(), json!({ "start": instant(session.start_time), "end": instant(end) }));
+    resource.insert(
+        "component".to_string(),
+        json!([
+            {
+                "code": { "text": "Trainer pressure setting" },
+                "valueQuantity": pressure_quantity(session.pressure_setting as f64, unit)
+            },
+            {
+                "code": { "text": "Repetitions completed" },
+                "valueQuantity": quantity(session.rep_count as f64, "repetitions", "{repetitions}")
+            },
+            {
+                "code": { "text": "Session duration" },
+                "valueQuantity": quantity(duration, "s", "s")
+            }
+        ]),
+    );
+    entry(id, resource)
+}
+
+fn mep_observation(profile: &UserProfile, mep: f64, unit: PressureUnit, id: Uuid, patient: &str) -> Entry {
+    let mut resource = Map::new();
+    resource.insert("resourceType".to_string(), json!("Observation"));
+    resource.insert("identifier".to_string(), identifier("baseline-mep", profile.id));
+    resource.insert("status".to_string(), json!("final"));
+    resource.insert("category".to_string(), category("exam", "Exam"));
+    resource.insert("code".to_string(), json!({ "text": "Maximum expiratory pressure (baseline)" }));
+    resource.insert("subject".to_string(), json!({ "reference": patient }));
+    if let Some(start) = profile.treatment_start_date {
+        resource.insert("effectiveDateTime".to_string(), json!(date(start)));
+    }
+    resource.insert("valueQuantity".to_string(), pressure_quantity(mep, unit));
+    entry(id, resource)
+}
+
+// `patient_key` identifies the profile across exports, e.g. the device id and profile id.
+// `uuids` maps session ids to their sync UUIDs.
+pub fn bundle(
+    profile: &UserProfile,
+    patient_key: &str,
+    sessions: &[Session],
+    uuids: &HashMap<i64, String>,
+    unit: PressureUnit,
+    now: DateTime<Utc>,
+) -> Value {
+    let patient_id = stable_id("patient", patient_key);
+    let patient_ref = format!("urn:uuid:{}", patient_id);
+    let reason = profile.diagnosis.map(|diagnosis| diagnosis.as_str().replace('_', " "));
+    let mut entries = vec![patient(profile, patient_id)];
+
+    if let Some(mep) = profile.baseline_mep {
+        entries.push(mep_observation(profile, mep, unit, stable_id("baseline-mep", patient_key), &patient_ref));
+    }
+    for session in sessions {
+        let session_key = match uuids.get(&session.id) {
+            Some(uuid) => uuid.clone(),
+            None => format!("{}:{}", patient_key, session.id),
+        };
+        let procedure_id = stable_id("procedure", &session_key);
+        entries.push(procedure(session, procedure_id, &patient_ref, reason.as_deref()));
+        if let Some(end) = session.end_time {
+            let procedure_ref = format!("urn:uuid:{}", procedure_id);
+            entries.push(session_observation(session, end, unit, stable_id("session-observation", &session_key), &patient_ref, &procedure_ref));
+        }
+    }
+
+    json!({
+        "resourceType": "Bundle",
+        "type": "collection",
+        "timestamp": instant(now),
+        "entry": entries
+            .into_iter()
+            .map(|entry| json!({ "fullUrl": entry.full_url, "resource": entry.resource }))
+            .collect::<Vec<_>>()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::DisplayPressure;
+    use chrono::TimeZone;
+
+    fn session(id: i64, pressure: i32) -> Session {
+        let start = Utc.with_ymd_and_hms(2024, 5, 15, 9, 0, 0).unwrap();
+        Session {
+            id,
+            start_time: start,
+            end_time: Some(start + chrono::Duration::minutes(10)),
+            pressure_setting: pressure,
+            pressure: DisplayPressure::new(Pressure::from_cmh2o(pressure as f64), PressureUnit::CmH2O),
+            notes: String::new(),
+            rep_count: 25,
+            deleted_at: None,
+            score: None,
+        }
+    }
+
+    fn pressures(bundle: &Value) -> Vec<Value> {
+        bundle["entry"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry["resource"]["resourceType"] == "Observation")
+            .map(|entry| {
+                let resource = &entry["resource"];
+                resource.get("valueQuantity").cloned().unwrap_or_else(|| resource["component"][0]["valueQuantity"].clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pressures_use_the_profile_unit_with_ucum_codes() {
+        let profile = UserProfile { id: 1, baseline_mep: Some(100.0), ..Default::default() };
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+
+        let cmh2o = pressures(&bundle(&profile, "device:1", &[session(1, 30)], &HashMap::new(), PressureUnit::CmH2O, now));
+        assert_eq!(cmh2o[0], json!({ "value": 100.0, "unit": "cmH2O", "system": UCUM, "code": "cm[H2O]" }));
+        assert_eq!(cmh2o[1]["value"], 30.0);
+
+        let kpa = pressures(&bundle(&profile, "device:1", &[session(1, 30)], &HashMap::new(), PressureUnit::KPa, now));
+        assert_eq!(kpa[0], json!({ "value": 9.81, "unit": "kPa", "system": UCUM, "code": "kPa" }));
+        assert_eq!(kpa[1]["value"], 2.94);
+
+        let mmhg = pressures(&bundle(&profile, "device:1", &[session(1, 30)], &HashMap::new(), PressureUnit::MmHg, now));
+        assert_eq!((mmhg[1]["value"].clone(), mmhg[1]["code"].clone()), (json!(22.1), json!("mm[Hg]")));
+    }
+
+    fn sample() -> (UserProfile, Vec<Session>, HashMap<i64, String>) {
+        let profile = UserProfile {
+            id: 1,
+            first_name: "Ann".to_string(),
+            dob: NaiveDate::from_ymd_opt(1950, 5, 10),
+            diagnosis: Some(crate::profile::Indication::Parkinsons),
+            baseline_mep: Some(80.0),
+            treatment_start_date: NaiveDate::from_ymd_opt(2024, 5, 1),
+            ..Default::default()
+        };
+        let mut open = session(2, 35);
+        open.end_time = None;
+        let uuids = HashMap::from([(1, "0b1c".to_string()), (2, "9f3e".to_string())]);
+        (profile, vec![session(1, 30), open], uuids)
+    }
+
+    // FHIR forbids empty strings, arrays and objects anywhere in a resource
+    fn assert_no_empty_values(value: &Value, path: &str) {
+        match value {
+            Value::String(s) => assert!(!s.is_empty(), "empty string at {}", path),
+            Value::Array(items) => {
+                assert!(!items.is_empty(), "empty array at {}", path);
+                items.iter().for_each(|item| assert_no_empty_values(item, path));
+            }
+            Value::Object(map) => {
+                assert!(!map.is_empty(), "empty object at {}", path);
+                map.iter().for_each(|(key, item)| assert_no_empty_values(item, &format!("{}.{}", path, key)));
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn bundle_matches_the_base_resource_shapes() {
+        let (profile, sessions, uuids) = sample();
+        let bundle = bundle(&profile, "device:1", &sessions, &uuids, PressureUnit::CmH2O, Utc::now());
+        assert_eq!((bundle["resourceType"].as_str(), bundle["type"].as_str()), (Some("Bundle"), Some("collection")));
+        assert_no_empty_values(&bundle, "Bundle");
+
+        let entries = bundle["entry"].as_array().unwrap();
+        let full_urls: Vec<&str> = entries.iter().map(|entry| entry["fullUrl"].as_str().unwrap()).collect();
+        let kinds: Vec<&str> = entries.iter().map(|entry| entry["resource"]["resourceType"].as_str().unwrap()).collect();
+        // The open session gets a Procedure but no Observation
+        assert_eq!(kinds, vec!["Patient", "Observation", "Procedure", "Observation", "Procedure"]);
+
+        for entry in entries {
+            let resource = &entry["resource"];
+            let id = resource["id"].as_str().unwrap();
+            assert_eq!(entry["fullUrl"], format!("urn:uuid:{}", id));
+            // Required elements (1..1) of each resource type
+            let required: &[&str] = match resource["resourceType"].as_str().unwrap() {
+                "Patient" => &[],
+                "Procedure" => &["status", "subject"],
+                "Observation" => &["status", "code"],
+                other => panic!("unexpected resource {}", other),
+            };
+            for element in required {
+                assert!(resource.get(*element).is_some(), "{} is missing {}", resource["resourceType"], element);
+            }
+            for reference in ["subject", "partOf"] {
+                let target = match &resource[reference] {
+                    Value::Array(items) => &items[0]["reference"],
+                    other => &other["reference"],
+                };
+                if let Some(target) = target.as_str() {
+                    assert!(full_urls.contains(&target), "{} points outside the bundle", target);
+                }
+            }
+        }
+        for quantity in pressures(&bundle) {
+            for element in ["value", "unit", "system", "code"] {
+                assert!(quantity.get(element).is_some(), "quantity is missing {}", element);
+            }
+        }
+        assert_eq!(entries[0]["resource"]["birthDate"], "1950-05-10");
+        assert_eq!(entries[4]["resource"]["status"], "in-progress");
+    }
+
+    #[test]
+    fn ids_are_stable_across_exports() {
+        let (profile, sessions, uuids) = sample();
+        let first = bundle(&profile, "device:1", &sessions, &uuids, PressureUnit::CmH2O, Utc::now());
+        let second = bundle(&profile, "device:1", &sessions, &uuids, PressureUnit::KPa, Utc::now());
+        let urls = |bundle: &Value| -> Vec<String> {
+            bundle["entry"].as_array().unwrap().iter().map(|entry| entry["fullUrl"].as_str().unwrap().to_string()).collect()
+        };
+        assert_eq!(urls(&first), urls(&second));
+
+        // A different profile or session gets different ids
+        let other = bundle(&profile, "device:2", &sessions, &HashMap::new(), PressureUnit::CmH2O, Utc::now());
+        assert!(urls(&first).iter().all(|url| !urls(&other).contains(url)));
+        assert_eq!(stable_id("procedure", "0b1c").get_version_num(), 8);
+    }
+}
@@ -0,0 +1,484 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::scoring::Prescription;
+
+// --- Guided session pacing ---
+//
+// A deterministic state machine walking the user through each breath: breathe in, blow
+// into the trainer, rest, and a longer rest between sets. It never reads the clock itself;
+// callers feed it the current time with every tick and user event, so the same inputs
+// always produce the same phases and cues.
+
+pub const DEFAULT_INHALE_SECONDS: u32 = 3;
+pub const DEFAULT_BLOW_SECONDS: u32 = 2;
+// Rest phases announce the last few seconds before the next breath
+pub const COUNTDOWN_SECONDS: u32 = 3;
+// The app ticks at least once a second while guiding. A longer silence means it was in the
+// background, and any phase that began and ended in it was never shown to the user.
+pub const MAX_TICK_GAP: Duration = Duration::seconds(2);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct GuidanceConfig {
+    pub reps: u32,
+    pub reps_per_set: u32,
+    pub inhale_seconds: u32,
+    pub blow_seconds: u32,
+    pub rep_rest_seconds: u32,
+    pub set_rest_seconds: u32,
+}
+
+impl GuidanceConfig {
+    pub fn from_prescription(prescription: &Prescription) -> Self {
+        GuidanceConfig {
+            reps: prescription.reps,
+            reps_per_set: prescription.reps_per_set.max(1),
+            inhale_seconds: DEFAULT_INHALE_SECONDS,
+            blow_seconds: DEFAULT_BLOW_SECONDS,
+            rep_rest_seconds: prescription.rep_rest_seconds,
+            set_rest_seconds: prescription.set_rest_seconds,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Ready,
+    Inhale,
+    Blow,
+    Rest,
+    SetRest,
+    Paused,
+    Complete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserEvent {
+    Start,
+    Pause,
+    Resume,
+    // Ends the current rest early
+    SkipRest,
+    Stop,
+}
+
+impl UserEvent {
+    pub fn parse(value: &str) -> Option<UserEvent> {
+        match value {
+            "start" => Some(UserEvent::Start),
+            "pause" => Some(UserEvent::Pause),
+            "resume" => Some(UserEvent::Resume),
+            "skip_rest" => Some(UserEvent::SkipRest),
+            "stop" => Some(UserEvent::Stop),
+            _ => None,
+        }
+    }
+}
+
+// What the app should announce or act on, in the order it happened.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Cue {
+    PhaseStarted { phase: Phase, at: DateTime<Utc>, seconds: Option<u32> },
+    Countdown { phase: Phase, seconds_left: u32 },
+    // Emitted at the end of each blow phase; the rep is logged with this timestamp
+    RepCompleted { rep: u32, set: u32, at: DateTime<Utc> },
+    SetCompleted { set: u32, at: DateTime<Utc> },
+    SessionCompleted { reps: u32, at: DateTime<Utc> },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GuidanceState {
+    pub phase: Phase,
+    // The phase to resume while paused
+    pub paused_phase: Option<Phase>,
+    pub seconds_remaining: Option<u32>,
+    // The breath in progress or coming up, counting from 1
+    pub rep: u32,
+    pub set: u32,
+    pub reps_completed: u32,
+    pub total_reps: u32,
+    pub total_sets: u32,
+}
+
+// Returned to the app after every tick or event
+#[derive(Serialize, Debug, Clone)]
+pub struct GuidanceUpdate {
+    pub session_id: i64,
+    pub state: GuidanceState,
+    pub cues: Vec<Cue>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Guide {
+    config: GuidanceConfig,
+    phase: Phase,
+    phase_started: Option<DateTime<Utc>>,
+    phase_ends: Option<DateTime<Utc>>,
+    paused: Option<(Phase, Duration)>,
+    reps_completed: u32,
+    last_countdown: Option<u32>,
+    last_tick: Option<DateTime<Utc>>,
+}
+
+fn seconds_ceil(duration: Duration) -> u32 {
+    let millis = duration.num_milliseconds().max(0);
+    ((millis + 999) / 1000) as u32
+}
+
+impl Guide {
+    pub fn new(config: GuidanceConfig) -> Self {
+        Guide {
+            config,
+            phase: Phase::Ready,
+            phase_started: None,
+            phase_ends: None,
+            paused: None,
+            reps_completed: 0,
+            last_countdown: None,
+            last_tick: None,
+        }
+    }
+
+    fn set_of(&self, rep: u32) -> u32 {
+        (rep.max(1) - 1) / self.config.reps_per_set + 1
+    }
+
+    fn total_sets(&self) -> u32 {
+        self.config.reps.div_ceil(self.config.reps_per_set)
+    }
+
+    fn phase_length(&self, phase: Phase) -> Option<Duration> {
+        let seconds = match phase {
+            Phase::Inhale => self.config.inhale_seconds,
+            Phase::Blow => self.config.blow_seconds,
+            Phase::Rest => self.config.rep_rest_seconds,
+            Phase::SetRest => self.config.set_rest_seconds,
+            Phase::Ready | Phase::Paused | Phase::Complete => return None,
+        };
+        Some(Duration::seconds(seconds as i64))
+    }
+
+    fn enter(&mut self, phase: Phase, at: DateTime<Utc>, length: Option<Duration>, cues: &mut Vec<Cue>) {
+        self.phase = phase;
+        self.phase_started = Some(at);
+        self.phase_ends = length.map(|length| at + length);
+        self.last_countdown = None;
+        cues.push(Cue::PhaseStarted { phase, at, seconds: length.map(seconds_ceil) });
+    }
+
+    fn pause(&mut self, now: DateTime<Utc>, cues: &mut Vec<Cue>) {
+        // A half-finished breath is started over; rests keep the time already taken
+        let resume = match self.phase {
+            Phase::Blow => Phase::Inhale,
+            phase => phase,
+        };
+        let remaining = match resume {
+            Phase::Rest | Phase::SetRest => self.phase_ends.map(|ends| (ends - now).max(Duration::zero())),
+            _ => self.phase_length(resume),
+        };
+        self.paused = remaining.map(|remaining| (resume, remaining));
+        self.enter(Phase::Paused, now, None, cues);
+    }
+
+    fn complete(&mut self, at: DateTime<Utc>, cues: &mut Vec<Cue>) {
+        self.paused = None;
+        self.enter(Phase::Complete, at, None, cues);
+        cues.push(Cue::SessionCompleted { reps: self.reps_completed, at });
+    }
+
+    // Moves on from the current phase, which ended at `at`.
+    fn advance(&mut self, at: DateTime<Utc>, cues: &mut Vec<Cue>) {
+        let next = match self.phase {
+            Phase::Inhale => Phase::Blow,
+            Phase::Blow => {
+                self.reps_completed += 1;
+                let rep = self.reps_completed;
+                let set = self.set_of(rep);
+                cues.push(Cue::RepCompleted { rep, set, at });
+                let last_rep = rep >= self.config.reps;
+                if last_rep || rep.is_multiple_of(self.config.reps_per_set) {
+                    cues.push(Cue::SetCompleted { set, at });
+                }
+                if last_rep {
+                    self.complete(at, cues);
+                    return;
+                }
+                if rep.is_multiple_of(self.config.reps_per_set) { Phase::SetRest } else { Phase::Rest }
+            }
+            Phase::Rest | Phase::SetRest => Phase::Inhale,
+            Phase::Ready | Phase::Paused | Phase::Complete => return,
+        };
+        self.enter(next, at, self.phase_length(next), cues);
+    }
+
+    // Runs every phase that has ended by `now`, then announces the rest countdown. After a
+    // gap longer than MAX_TICK_GAP only the phase in progress at the last tick is finished;
+    // the guide then pauses rather than logging breaths the user wasn't guided through.
+    pub fn tick(&mut self, now: DateTime<Utc>) -> Vec<Cue> {
+        let mut cues = Vec::new();
+        let last_tick = self.last_tick.filter(|last| now - *last > MAX_TICK_GAP);
+        self.last_tick = Some(now);
+        while let Some(ends) = self.phase_ends {
+            if ends > now {
+                break;
+            }
+            let unseen = last_tick.zip(self.phase_started).is_some_and(|(last, started)| started > last && ends > started);
+            if unseen {
+                self.pause(now, &mut cues);
+                return cues;
+            }
+            self.advance(ends, &mut cues);
+        }
+        if let (Phase::Rest | Phase::SetRest, Some(ends)) = (self.phase, self.phase_ends) {
+            let seconds_left = seconds_ceil(ends - now);
+            if seconds_left <= COUNTDOWN_SECONDS && self.last_countdown != Some(seconds_left) {
+                self.last_countdown = Some(seconds_left);
+                cues.push(Cue::Countdown { phase: self.phase, seconds_left });
+            }
+        }
+        cues
+    }
+
+    // Applies a user event at `now`, after catching up with any phases that ended before
+    // it. Events that make no sense in the current phase are ignored.
+    pub fn handle(&mut self, event: UserEvent, now: DateTime<Utc>) -> Vec<Cue> {
+        let mut cues = self.tick(now);
+        match (event, self.phase) {
+            (UserEvent::Start, Phase::Ready) => {
+                if self.config.reps == 0 {
+                    self.complete(now, &mut cues);
+                } else {
+                    self.enter(Phase::Inhale, now, self.phase_length(Phase::Inhale), &mut cues);
+                }
+            }
+            (UserEvent::Pause, Phase::Inhale | Phase::Blow | Phase::Rest | Phase::SetRest) => self.pause(now, &mut cues),
+            (UserEvent::Resume, Phase::Paused) => {
+                if let Some((phase, remaining)) = self.paused.take() {
+                    self.enter(phase, now, Some(remaining), &mut cues);
+                    cues.extend(self.tick(now));
+                }
+            }
+            (UserEvent::SkipRest, Phase::Rest | Phase::SetRest) => self.advance(now, &mut cues),
+            (UserEvent::Stop, phase) if phase != Phase::Complete => self.complete(now, &mut cues),
+            _ => {}
+        }
+        cues
+    }
+
+    pub fn state(&self, now: DateTime<Utc>) -> GuidanceState {
+        let rep = match self.phase {
+            Phase::Complete => self.reps_completed,
+            _ => (self.reps_completed + 1).min(self.config.reps),
+        };
+        GuidanceState {
+            phase: self.phase,
+            paused_phase: self.paused.map(|(phase, _)| phase),
+            seconds_remaining: self.phase_ends.map(|ends| seconds_ceil(ends - now)),
+            rep,
+            set: self.set_of(rep),
+            reps_completed: self.reps_completed,
+            total_reps: self.config.reps,
+            total_sets: self.total_sets(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn config() -> GuidanceConfig {
+        GuidanceConfig {
+            reps: 4,
+            reps_per_set: 2,
+            inhale_seconds: 3,
+            blow_seconds: 2,
+            rep_rest_seconds: 15,
+            set_rest_seconds: 60,
+        }
+    }
+
+    fn t0() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 15, 8, 0, 0).unwrap()
+    }
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        t0() + Duration::seconds(seconds)
+    }
+
+    fn started() -> Guide {
+        let mut guide = Guide::new(config());
+        guide.handle(UserEvent::Start, t0());
+        guide
+    }
+
+    // Ticks once a second from `from` to `to` seconds, like the app does while in view
+    fn tick_through(guide: &mut Guide, from: i64, to: i64) -> Vec<Cue> {
+        (from..=to).flat_map(|seconds| guide.tick(at(seconds))).collect()
+    }
+
+    fn reps(cues: &[Cue]) -> Vec<(u32, u32, DateTime<Utc>)> {
+        cues.iter()
+            .filter_map(|cue| match cue {
+                Cue::RepCompleted { rep, set, at } => Some((*rep, *set, *at)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn starts_ready_and_ignores_ticks() {
+        let mut guide = Guide::new(config());
+        assert!(guide.tick(at(100)).is_empty());
+        let state = guide.state(at(100));
+        assert_eq!(state.phase, Phase::Ready);
+        assert_eq!(state.rep, 1);
+        assert_eq!(state.total_sets, 2);
+        assert_eq!(state.seconds_remaining, None);
+    }
+
+    #[test]
+    fn start_begins_with_inhale() {
+        let mut guide = Guide::new(config());
+        let cues = guide.handle(UserEvent::Start, t0());
+        assert_eq!(cues, vec![Cue::PhaseStarted { phase: Phase::Inhale, at: t0(), seconds: Some(3) }]);
+        assert_eq!(guide.state(at(1)).seconds_remaining, Some(2));
+    }
+
+    #[test]
+    fn rep_is_completed_at_the_end_of_the_blow() {
+        let mut guide = started();
+        assert_eq!(guide.tick(at(3)), vec![Cue::PhaseStarted { phase: Phase::Blow, at: at(3), seconds: Some(2) }]);
+        let cues = guide.tick(at(5));
+        assert_eq!(reps(&cues), vec![(1, 1, at(5))]);
+        assert_eq!(guide.state(t0()).phase, Phase::Rest);
+        assert_eq!(guide.state(at(5)).seconds_remaining, Some(15));
+    }
+
+    #[test]
+    fn set_rest_follows_the_last_rep_of_a_set() {
+        let mut guide = started();
+        // Inhale 3 + blow 2 + rest 15 + inhale 3 + blow 2
+        let cues = tick_through(&mut guide, 1, 25);
+        assert_eq!(reps(&cues), vec![(1, 1, at(5)), (2, 1, at(25))]);
+        assert!(cues.contains(&Cue::SetCompleted { set: 1, at: at(25) }));
+        assert_eq!(guide.state(t0()).phase, Phase::SetRest);
+        assert_eq!(guide.state(at(25)).set, 2);
+    }
+
+    #[test]
+    fn runs_a_whole_session_when_ticked_regularly() {
+        let mut guide = started();
+        let cues = tick_through(&mut guide, 1, 200);
+        let completed = reps(&cues);
+        assert_eq!(completed.len(), 4);
+        // Second set starts after the 60 second set rest
+        assert_eq!(completed[2], (3, 2, at(25 + 60 + 5)));
+        assert_eq!(completed[3], (4, 2, at(25 + 60 + 5 + 20)));
+        assert!(cues.contains(&Cue::SessionCompleted { reps: 4, at: at(110) }));
+        assert_eq!(guide.state(t0()).phase, Phase::Complete);
+        assert!(guide.tick(at(2000)).is_empty());
+    }
+
+    #[test]
+    fn pauses_instead_of_catching_up_after_a_long_gap() {
+        let mut guide = started();
+        let cues = guide.tick(at(1000));
+        // The inhale in progress finishes, but the blow that followed was never shown
+        assert!(reps(&cues).is_empty());
+        assert_eq!(cues.last(), Some(&Cue::PhaseStarted { phase: Phase::Paused, at: at(1000), seconds: None }));
+        assert_eq!(guide.state(at(1000)).paused_phase, Some(Phase::Inhale));
+
+        let cues = guide.handle(UserEvent::Resume, at(1001));
+        assert_eq!(cues, vec![Cue::PhaseStarted { phase: Phase::Inhale, at: at(1001), seconds: Some(3) }]);
+    }
+
+    #[test]
+    fn a_breath_in_progress_before_a_long_gap_still_counts() {
+        let mut guide = started();
+        tick_through(&mut guide, 1, 4);
+        let cues = guide.tick(at(1000));
+        // The blow ends on time, then the rest that followed passed unseen
+        assert_eq!(reps(&cues), vec![(1, 1, at(5))]);
+        assert_eq!(guide.state(at(1000)).phase, Phase::Paused);
+
+        // The rest is already over, so resuming goes straight to the next breath
+        let cues = guide.handle(UserEvent::Resume, at(1010));
+        assert_eq!(cues.last(), Some(&Cue::PhaseStarted { phase: Phase::Inhale, at: at(1010), seconds: Some(3) }));
+        assert_eq!(guide.state(at(1010)).reps_completed, 1);
+    }
+
+    #[test]
+    fn countdown_is_announced_once_per_second() {
+        let mut guide = started();
+        tick_through(&mut guide, 1, 5);
+        assert!(guide.tick(at(10)).is_empty());
+        assert_eq!(guide.tick(at(17)), vec![Cue::Countdown { phase: Phase::Rest, seconds_left: 3 }]);
+        assert!(guide.tick(at(17) + Duration::milliseconds(500)).is_empty());
+        assert_eq!(guide.tick(at(19)), vec![Cue::Countdown { phase: Phase::Rest, seconds_left: 1 }]);
+    }
+
+    #[test]
+    fn pausing_a_rest_keeps_the_remaining_time() {
+        let mut guide = started();
+        tick_through(&mut guide, 1, 5);
+        guide.handle(UserEvent::Pause, at(10));
+        assert_eq!(guide.state(t0()).phase, Phase::Paused);
+        assert!(guide.tick(at(500)).is_empty());
+        let state = guide.state(at(500));
+        assert_eq!(state.paused_phase, Some(Phase::Rest));
+        assert_eq!(state.seconds_remaining, None);
+
+        guide.handle(UserEvent::Resume, at(600));
+        assert_eq!(guide.state(t0()).phase, Phase::Rest);
+        assert_eq!(guide.state(at(600)).seconds_remaining, Some(10));
+    }
+
+    #[test]
+    fn pausing_mid_blow_restarts_the_breath() {
+        let mut guide = started();
+        guide.handle(UserEvent::Pause, at(4));
+        let cues = guide.handle(UserEvent::Resume, at(30));
+        assert_eq!(cues, vec![Cue::PhaseStarted { phase: Phase::Inhale, at: at(30), seconds: Some(3) }]);
+        assert_eq!(guide.state(t0()).reps_completed, 0);
+    }
+
+    #[test]
+    fn skip_rest_starts_the_next_breath() {
+        let mut guide = started();
+        tick_through(&mut guide, 1, 5);
+        let cues = guide.handle(UserEvent::SkipRest, at(8));
+        assert_eq!(cues, vec![Cue::PhaseStarted { phase: Phase::Inhale, at: at(8), seconds: Some(3) }]);
+        // Skipping is only allowed during a rest
+        assert!(guide.handle(UserEvent::SkipRest, at(9)).is_empty());
+        assert_eq!(guide.state(t0()).phase, Phase::Inhale);
+    }
+
+    #[test]
+    fn stop_completes_with_the_reps_done_so_far() {
+        let mut guide = started();
+        let mut cues = tick_through(&mut guide, 1, 6);
+        cues.extend(guide.handle(UserEvent::Stop, at(7)));
+        assert_eq!(reps(&cues), vec![(1, 1, at(5))]);
+        assert_eq!(cues.last(), Some(&Cue::SessionCompleted { reps: 1, at: at(7) }));
+        assert!(guide.handle(UserEvent::Stop, at(8)).is_empty());
+    }
+
+    #[test]
+    fn zero_length_rests_go_straight_to_the_next_breath() {
+        let mut guide = Guide::new(GuidanceConfig { rep_rest_seconds: 0, set_rest_seconds: 0, ..config() });
+        guide.handle(UserEvent::Start, t0());
+        let cues = tick_through(&mut guide, 1, 10);
+        assert_eq!(reps(&cues), vec![(1, 1, at(5)), (2, 1, at(10))]);
+        assert_eq!(guide.state(t0()).phase, Phase::Inhale);
+    }
+
+    #[test]
+    fn parses_user_events() {
+        assert_eq!(UserEvent::parse("skip_rest"), Some(UserEvent::SkipRest));
+        assert_eq!(UserEvent::parse("jump"), None);
+    }
+}
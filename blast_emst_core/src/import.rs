@@ -0,0 +1,557 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use rusqlite::{Connection, params};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::db;
+use crate::devices;
+use crate::profile::FieldError;
+use crate::sync;
+use crate::units::{Pressure, PressureUnit};
+
+// --- CSV import ---
+//
+// Brings in sessions logged in spreadsheets before the app was used. Columns are mapped
+// by header name or 1-based position; the date format is detected from the whole column
+// unless the mapping names one. Every import can be previewed first: the preview parses
+// and checks every row, including for duplicates of sessions already in the app, without
+// writing anything.
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Column {
+    // 1-based, as spreadsheets number them
+    Position(usize),
+    Header(String),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ColumnMapping {
+    pub date: Column,
+    // Without a time column sessions are placed at noon, and duplicates are matched by day
+    pub time: Option<Column>,
+    pub pressure: Column,
+    pub reps: Option<Column>,
+    pub notes: Option<Column>,
+    pub has_header: bool,
+    // Detected from the first line when absent
+    pub delimiter: Option<char>,
+    // chrono format string; detected when absent
+    pub date_format: Option<String>,
+    pub pressure_unit: PressureUnit,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            date: Column::Header("date".to_string()),
+            time: None,
+            pressure: Column::Header("pressure".to_string()),
+            reps: None,
+            notes: None,
+            has_header: true,
+            delimiter: None,
+            date_format: None,
+            pressure_unit: PressureUnit::CmH2O,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Csv(csv::Error),
+    // A mapped header isn't in the file
+    MissingColumn(String),
+    // Columns can only be mapped by name when the file has a header row
+    NoHeader,
+    // No supported format reads any of the dates
+    UnknownDateFormat,
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Csv(e) => write!(f, "{}", e),
+            ImportError::MissingColumn(column) => write!(f, "column {} not found", column),
+            ImportError::NoHeader => write!(f, "columns must be mapped by position when the file has no header row"),
+            ImportError::UnknownDateFormat => write!(f, "could not recognise the date format"),
+            ImportError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<csv::Error> for ImportError {
+    fn from(e: csv::Error) -> Self {
+        ImportError::Csv(e)
+    }
+}
+
+impl From<rusqlite::Error> for ImportError {
+    fn from(e: rusqlite::Error) -> Self {
+        ImportError::Database(e)
+    }
+}
+
+impl ImportError {
+    // Stable identifier handed to the app
+    pub fn code(&self) -> &'static str {
+        match self {
+            ImportError::Csv(_) => "invalid_csv",
+            ImportError::MissingColumn(_) => "missing_column",
+            ImportError::NoHeader => "no_header",
+            ImportError::UnknownDateFormat => "unknown_date_format",
+            ImportError::Database(_) => "error",
+        }
+    }
+}
+
+// Tried in this order, so day-first wins over month-first when both read every date
+const DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d", "%d/%m/%Y", "%m/%d/%Y", "%d.%m.%Y", "%d-%m-%Y", "%m-%d-%Y", "%Y/%m/%d", "%d/%m/%y", "%m/%d/%y",
+    "%d %b %Y", "%d %B %Y", "%b %d %Y", "%B %d %Y", "%b %d, %Y", "%B %d, %Y",
+];
+const TIME_FORMATS: &[&str] = &["%H:%M", "%H:%M:%S", "%I:%M %p", "%I:%M%p", "%I %p", "%I%p"];
+
+// Two-digit years read with %Y land in the first century; no real session is that old
+const EARLIEST_YEAR: i32 = 1970;
+// Sessions without a time column
+const DEFAULT_TIME: NaiveTime = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+// Spreadsheets only record how many reps were done, so imported reps are spaced evenly
+// from the session's start. Imported sessions aren't scored, as the spacing is made up.
+const REP_SPACING_SECONDS: i64 = 10;
+const MAX_REPS: i64 = 500;
+// Rows this close to an existing session at the same pressure are taken to be the same one
+const DUPLICATE_WINDOW_MINUTES: i64 = 30;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RowStatus {
+    Ready,
+    Error,
+    Duplicate,
+    Imported,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ImportedSession {
+    pub start_time: DateTime<Utc>,
+    // Whether the time came from the file rather than the noon default
+    pub time_known: bool,
+    pub pressure_setting: i32,
+    pub reps: i64,
+    pub notes: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ImportRow {
+    // Line in the file, counting the header
+    pub line: u64,
+    pub status: RowStatus,
+    pub errors: Vec<FieldError>,
+    pub session: Option<ImportedSession>,
+    // The existing session, or the earlier row, this one duplicates
+    pub duplicate_of: Option<i64>,
+    pub duplicate_of_line: Option<u64>,
+    // Set once imported
+    pub session_id: Option<i64>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub delimiter: String,
+    pub date_format: String,
+    // Other formats that read every date differently, e.g. month-first instead of day-first.
+    // If this isn't empty the app should ask which one is meant and pass it in the mapping.
+    pub other_date_formats: Vec<String>,
+    pub rows: Vec<ImportRow>,
+    pub ready: u32,
+    pub errors: u32,
+    pub duplicates: u32,
+    pub imported: u32,
+}
+
+fn detect_delimiter(text: &str) -> u8 {
+    let first_line = text.lines().next().unwrap_or_default();
+    [b',', b';', b'\t']
+        .into_iter()
+        .max_by_key(|d| first_line.bytes().filter(|b| b == d).count())
+        .filter(|d| first_line.as_bytes().contains(d))
+        .unwrap_or(b',')
+}
+
+fn resolve(column: &Column, headers: Option<&csv::StringRecord>) -> Result<usize, ImportError> {
+    match column {
+        Column::Position(0) => Err(ImportError::MissingColumn("0".to_string())),
+        Column::Position(position) => Ok(position - 1),
+        Column::Header(name) => {
+            let headers = headers.ok_or(ImportError::NoHeader)?;
+            headers
+                .iter()
+                .position(|header| header.trim().eq_ignore_ascii_case(name.trim()))
+                .ok_or_else(|| ImportError::MissingColumn(name.clone()))
+        }
+    }
+}
+
+// A date cell may carry a time after the date, as exported by many spreadsheets.
+fn parse_date(cell: &str, format: &str) -> Option<(NaiveDate, Option<NaiveTime>)> {
+    let (date, rest) = NaiveDate::parse_and_remainder(cell.trim(), format).ok()?;
+    if date.year() < EARLIEST_YEAR {
+        return None;
+    }
+    let rest = rest.trim().trim_start_matches('T').trim();
+    if rest.is_empty() {
+        return Some((date, None));
+    }
+    Some((date, Some(parse_time(rest)?)))
+}
+
+fn parse_time(cell: &str) -> Option<NaiveTime> {
+    let cell = cell.trim();
+    TIME_FORMATS.iter().find_map(|format| NaiveTime::parse_from_str(&cell.to_ascii_uppercase(), format).ok())
+}
+
+// The first format that reads every date, or failing that the one that reads the most.
+fn detect_date_format(cells: &[&str]) -> Result<(String, Vec<String>), ImportError> {
+    let readable = |format: &str| cells.iter().filter(|cell| parse_date(cell, format).is_some()).count();
+    let complete: Vec<&str> = DATE_FORMATS.iter().copied().filter(|format| readable(format) == cells.len()).collect();
+    if let Some(chosen) = complete.first() {
+        let dates = |format: &str| cells.iter().map(|cell| parse_date(cell, format)).collect::<Vec<_>>();
+        let chosen_dates = dates(chosen);
+        let others = complete[1..]
+            .iter()
+            .filter(|format| dates(format) != chosen_dates)
+            .map(|format| format.to_string())
+            .collect();
+        return Ok((chosen.to_string(), others));
+    }
+    DATE_FORMATS
+        .iter()
+        .map(|format| (readable(format), format))
+        .filter(|(count, _)| *count > 0)
+        .max_by_key(|(count, _)| *count)
+        .map(|(_, format)| (format.to_string(), Vec::new()))
+        .ok_or(ImportError::UnknownDateFormat)
+}
+
+fn parse_pressure(cell: &str, unit: PressureUnit) -> Result<i32, String> {
+    // Accept "30", "30 cmH2O" and decimal commas such as "2,9 kPa". A unit written in the
+    // cell overrides the mapped one.
+    let cell = cell.trim();
+    let split = cell.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ',')).unwrap_or(cell.len());
+    let (number, suffix) = cell.split_at(split);
+    let not_a_pressure = || format!("\"{}\" is not a pressure", cell);
+    let unit = match suffix.trim() {
+        "" => unit,
+        suffix => PressureUnit::parse(suffix).ok_or_else(not_a_pressure)?,
+    };
+    let value: f64 = number.replace(',', ".").parse().map_err(|_| not_a_pressure())?;
+    let cmh2o = Pressure::new(value, unit).cmh2o().round() as i32;
+    // Old sessions may predate the current trainer, so only the sign is checked
+    devices::normalize_pressure(None, cmh2o).map_err(|e| e.to_string())
+}
+
+struct Cells<'a> {
+    record: &'a csv::StringRecord,
+}
+
+impl Cells<'_> {
+    fn get(&self, index: Option<usize>) -> &str {
+        index.and_then(|i| self.record.get(i)).unwrap_or("").trim()
+    }
+}
+
+struct Columns {
+    date: usize,
+    time: Option<usize>,
+    pressure: usize,
+    reps: Option<usize>,
+    notes: Option<usize>,
+}
+
+fn parse_row<Tz: TimeZone>(cells: &Cells, columns: &Columns, date_format: &str, unit: PressureUnit, tz: &Tz) -> Result<ImportedSession, Vec<FieldError>> {
+    let mut errors = Vec::new();
+    let date_cell = cells.get(Some(columns.date));
+    let date = match parse_date(date_cell, date_format) {
+        Some(date) => Some(date),
+        None => {
+            let message = if date_cell.is_empty() { "missing".to_string() } else { format!("\"{}\" doesn't match {}", date_cell, date_format) };
+            errors.push(FieldError { field: "date", message });
+            None
+        }
+    };
+    let time_cell = cells.get(columns.time);
+    let time = if time_cell.is_empty() {
+        None
+    } else {
+        match parse_time(time_cell) {
+            Some(time) => Some(time),
+            None => {
+                errors.push(FieldError { field: "time", message: format!("\"{}\" is not a time", time_cell) });
+                None
+            }
+        }
+    };
+    let pressure = parse_pressure(cells.get(Some(columns.pressure)), unit)
+        .map_err(|message| errors.push(FieldError { field: "pressure", message }))
+        .ok();
+    let reps_cell = cells.get(columns.reps);
+    let reps = if reps_cell.is_empty() {
+        Some(0)
+    } else {
+        match reps_cell.parse::<i64>() {
+            Ok(reps) if (0..=MAX_REPS).contains(&reps) => Some(reps),
+            _ => {
+                errors.push(FieldError { field: "reps", message: format!("\"{}\" is not a number of reps from 0 to {}", reps_cell, MAX_REPS) });
+                None
+            }
+        }
+    };
+
+    let (Some((date, date_time)), Some(pressure), Some(reps)) = (date, pressure, reps) else {
+        return Err(errors);
+    };
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let explicit_time = time.or(date_time);
+    let local = date.and_time(explicit_time.unwrap_or(DEFAULT_TIME));
+    let Some(start) = tz.from_local_datetime(&local).earliest() else {
+        return Err(vec![FieldError { field: "time", message: "doesn't exist in the local timezone".to_string() }]);
+    };
+    Ok(ImportedSession {
+        start_time: start.with_timezone(&Utc),
+        time_known: explicit_time.is_some(),
+        pressure_setting: pressure,
+        reps,
+        notes: cells.get(columns.notes).to_string(),
+    })
+}
+
+fn same_session<Tz: TimeZone>(tz: &Tz, a: &ImportedSession, start: DateTime<Utc>, pressure: i32) -> bool {
+    if a.pressure_setting != pressure {
+        return false;
+    }
+    if a.time_known {
+        (a.start_time - start).num_minutes().abs() <= DUPLICATE_WINDOW_MINUTES
+    } else {
+        a.start_time.with_timezone(tz).date_naive() == start.with_timezone(tz).date_naive()
+    }
+}
+
+// Parses and checks every row against the active profile's sessions, in trash or not.
+pub fn preview<Tz: TimeZone>(conn: &Connection, text: &str, mapping: &ColumnMapping, tz: &Tz) -> Result<ImportReport, ImportError> {
+    let delimiter = mapping.delimiter.filter(char::is_ascii).map(|d| d as u8).unwrap_or_else(|| detect_delimiter(text));
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(mapping.has_header)
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = if mapping.has_header { Some(reader.headers()?.clone()) } else { None };
+    let columns = Columns {
+        date: resolve(&mapping.date, headers.as_ref())?,
+        time: mapping.time.as_ref().map(|c| resolve(c, headers.as_ref())).transpose()?,
+        pressure: resolve(&mapping.pressure, headers.as_ref())?,
+        reps: mapping.reps.as_ref().map(|c| resolve(c, headers.as_ref())).transpose()?,
+        notes: mapping.notes.as_ref().map(|c| resolve(c, headers.as_ref())).transpose()?,
+    };
+
+    let mut records = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        if record.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+        records.push(record);
+    }
+    let (date_format, other_date_formats) = match &mapping.date_format {
+        Some(format) => (format.clone(), Vec::new()),
+        None => {
+            let cells: Vec<&str> = records.iter().filter_map(|r| r.get(columns.date)).map(str::trim).filter(|c| !c.is_empty()).collect();
+            if cells.is_empty() { (DATE_FORMATS[0].to_string(), Vec::new()) } else { detect_date_format(&cells)? }
+        }
+    };
+
+    let existing = existing_sessions(conn)?;
+    let mut report = ImportReport {
+        dry_run: true,
+        delimiter: (delimiter as char).to_string(),
+        date_format,
+        other_date_formats,
+        rows: Vec::new(),
+        ready: 0,
+        errors: 0,
+        duplicates: 0,
+        imported: 0,
+    };
+    for record in &records {
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let mut row = ImportRow { line, status: RowStatus::Ready, errors: Vec::new(), session: None, duplicate_of: None, duplicate_of_line: None, session_id: None };
+        match parse_row(&Cells { record }, &columns, &report.date_format, mapping.pressure_unit, tz) {
+            Err(errors) => {
+                row.status = RowStatus::Error;
+                row.errors = errors;
+                report.errors += 1;
+            }
+            Ok(session) => {
+                row.duplicate_of = existing
+                    .iter()
+                    .find(|(_, start, pressure)| same_session(tz, &session, *start, *pressure))
+                    .map(|(id, _, _)| *id);
+                row.duplicate_of_line = report
+                    .rows
+                    .iter()
+                    .filter(|earlier| earlier.status == RowStatus::Ready)
+                    .find(|earlier| {
+                        earlier.session.as_ref().is_some_and(|e| same_session(tz, &session, e.start_time, e.pressure_setting))
+                    })
+                    .map(|earlier| earlier.line);
+                if row.duplicate_of.is_some() || row.duplicate_of_line.is_some() {
+                    row.status = RowStatus::Duplicate;
+                    report.duplicates += 1;
+                } else {
+                    report.ready += 1;
+                }
+                row.session = Some(session);
+            }
+        }
+        report.rows.push(row);
+    }
+    Ok(report)
+}
+
+fn existing_sessions(conn: &Connection) -> rusqlite::Result<Vec<(i64, DateTime<Utc>, i32)>> {
+    let mut stmt = conn.prepare("SELECT id, start_time, pressure_setting FROM sessions WHERE profile_id = ?1")?;
+    let rows = stmt.query_map([db::active_profile_id(conn)?], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, i32>(2)?))
+    })?;
+    let mut sessions = Vec::new();
+    for row in rows {
+        let (id, start, pressure) = row?;
+        if let Ok(start) = DateTime::parse_from_rfc3339(&start) {
+            sessions.push((id, start.with_timezone(&Utc), pressure));
+        }
+    }
+    Ok(sessions)
+}
+
+// Imports every ready row in one transaction. Rows with errors and duplicates are left out
+// and reported as in the preview.
+pub fn import<Tz: TimeZone>(conn: &Connection, text: &str, mapping: &ColumnMapping, tz: &Tz) -> Result<ImportReport, ImportError> {
+    let mut report = preview(conn, text, mapping, tz)?;
+    report.dry_run = false;
+    let tx = conn.unchecked_transaction()?;
+    let profile_id = db::active_profile_id(&tx)?;
+    for row in report.rows.iter_mut().filter(|row| row.status == RowStatus::Ready) {
+        let Some(session) = &row.session else {
+            continue;
+        };
+        let rep_times: Vec<DateTime<Utc>> =
+            (1..=session.reps).map(|i| session.start_time + Duration::seconds(i * REP_SPACING_SECONDS)).collect();
+        let end_time = rep_times.last().copied().unwrap_or(session.start_time);
+        tx.execute(
+            "INSERT INTO sessions (uuid, profile_id, start_time, end_time, pressure_setting, notes)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                sync::new_uuid(),
+                profile_id,
+                session.start_time.to_rfc3339(),
+                end_time.to_rfc3339(),
+                session.pressure_setting,
+                session.notes
+            ],
+        )?;
+        let session_id = tx.last_insert_rowid();
+        for at in rep_times {
+            db::add_rep_at(&tx, session_id, at)?;
+        }
+        row.status = RowStatus::Imported;
+        row.session_id = Some(session_id);
+        report.ready -= 1;
+        report.imported += 1;
+    }
+    tx.commit()?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Timelike};
+
+    fn utc() -> FixedOffset {
+        FixedOffset::east_opt(0).unwrap()
+    }
+
+    fn mapping(json: &str) -> ColumnMapping {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn detects_day_first_dates_and_reports_the_alternative() {
+        let conn = db::initialize_database(":memory:").unwrap();
+        let csv = "Date;Pressure;Reps\n01/03/2024;30;25\n02/03/2024;30;25\n";
+        let report = preview(&conn, csv, &mapping(r#"{"date": "Date", "pressure": "Pressure", "reps": "Reps"}"#), &utc()).unwrap();
+        assert_eq!(report.delimiter, ";");
+        assert_eq!(report.date_format, "%d/%m/%Y");
+        assert_eq!(report.other_date_formats, vec!["%m/%d/%Y".to_string()]);
+        assert_eq!(report.ready, 2);
+        let first = report.rows[0].session.as_ref().unwrap();
+        assert_eq!(first.start_time.date_naive(), NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+        assert!(!first.time_known);
+    }
+
+    #[test]
+    fn picks_the_only_format_that_reads_every_date() {
+        let conn = db::initialize_database(":memory:").unwrap();
+        let csv = "date,pressure\n03/25/2024,30\n03/26/2024,30\n";
+        let report = preview(&conn, csv, &ColumnMapping::default(), &utc()).unwrap();
+        assert_eq!(report.date_format, "%m/%d/%Y");
+        assert!(report.other_date_formats.is_empty());
+    }
+
+    #[test]
+    fn reports_row_level_errors() {
+        let conn = db::initialize_database(":memory:").unwrap();
+        let csv = "date,time,pressure,reps\n2024-03-01,25:00,30,25\n2024-03-02,08:00,-5,lots\n2024-03-03,8:30 pm,2.9 kPa,25\n";
+        let m = mapping(r#"{"time": "time", "reps": "reps"}"#);
+        let report = preview(&conn, csv, &m, &utc()).unwrap();
+        assert_eq!(report.rows[0].errors.iter().map(|e| e.field).collect::<Vec<_>>(), vec!["time"]);
+        assert_eq!(report.rows[1].errors.iter().map(|e| e.field).collect::<Vec<_>>(), vec!["pressure", "reps"]);
+        assert_eq!(report.rows[1].line, 3);
+        // kPa is converted to cmH2O
+        let third = report.rows[2].session.as_ref().unwrap();
+        assert_eq!(third.pressure_setting, 30);
+        assert_eq!(third.start_time.hour(), 20);
+        assert_eq!((report.ready, report.errors), (1, 2));
+    }
+
+    #[test]
+    fn skips_duplicates_of_existing_sessions_and_earlier_rows() {
+        let conn = db::initialize_database(":memory:").unwrap();
+        let csv = "date,pressure,reps\n2024-03-01,30,25\n2024-03-01,30,25\n2024-03-02,30,20\n";
+        let first = import(&conn, csv, &mapping(r#"{"reps": 3}"#), &utc()).unwrap();
+        assert!(!first.dry_run);
+        assert_eq!((first.imported, first.duplicates), (2, 1));
+        assert_eq!(first.rows[1].duplicate_of_line, Some(2));
+        let sessions = db::get_all_sessions(&conn).unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[1].rep_count, 25);
+
+        let again = preview(&conn, csv, &ColumnMapping::default(), &utc()).unwrap();
+        assert_eq!((again.ready, again.duplicates), (0, 3));
+        assert_eq!(again.rows[2].duplicate_of, first.rows[2].session_id);
+    }
+
+    #[test]
+    fn rejects_unknown_columns_and_unreadable_dates() {
+        let conn = db::initialize_database(":memory:").unwrap();
+        let missing = preview(&conn, "day,pressure\n2024-03-01,30\n", &ColumnMapping::default(), &utc());
+        assert!(matches!(missing, Err(ImportError::MissingColumn(_))));
+        let unreadable = preview(&conn, "date,pressure\nyesterday,30\n", &ColumnMapping::default(), &utc());
+        assert!(matches!(unreadable, Err(ImportError::UnknownDateFormat)));
+    }
+}
@@ -0,0 +1,396 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use log::warn;
+use rusqlite::types::Value;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::db;
+
+// --- Integrity check and repair ---
+//
+// Finds rows the rest of the app can't read or that contradict each other: timestamps that
+// aren't RFC 3339, reps whose session is gone, sessions ending before they start and more
+// than one open session per profile. Every issue that can be fixed comes with the repair
+// that would be applied, so the app can show it before the user agrees. SQLite's own
+// integrity_check is reported too, but page-level corruption can only be fixed by
+// restoring a backup.
+
+// Caps the messages integrity_check produces on a badly damaged file
+const MAX_CORRUPTION_MESSAGES: u32 = 100;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    Corruption,
+    BadTimestamp,
+    OrphanedRep,
+    EndBeforeStart,
+    DuplicateOpenSession,
+}
+
+impl IssueKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            IssueKind::Corruption => "corruption",
+            IssueKind::BadTimestamp => "bad_timestamp",
+            IssueKind::OrphanedRep => "orphaned_rep",
+            IssueKind::EndBeforeStart => "end_before_start",
+            IssueKind::DuplicateOpenSession => "duplicate_open_session",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Repair {
+    // Writes `value` into the issue's column
+    SetTimestamp { value: DateTime<Utc> },
+    // Deletes the row; deleting a session takes its reps and score with it
+    DeleteRow,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Issue {
+    // Stays the same between checks as long as the problem does, for passing to repair
+    pub id: String,
+    pub kind: IssueKind,
+    pub table: &'static str,
+    pub row_id: Option<i64>,
+    pub column: Option<&'static str>,
+    pub profile_id: Option<i64>,
+    pub detail: String,
+    pub repair: Option<Repair>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IntegrityReport {
+    pub ok: bool,
+    pub issues: Vec<Issue>,
+    // Issues fixed by the repair that produced this report
+    pub repaired: u32,
+}
+
+impl Issue {
+    fn new(kind: IssueKind, table: &'static str, row_id: i64, column: &'static str, detail: String, repair: Option<Repair>) -> Self {
+        Issue {
+            id: format!("{}:{}:{}:{}", kind.as_str(), table, row_id, column),
+            kind,
+            table,
+            row_id: Some(row_id),
+            column: Some(column),
+            profile_id: None,
+            detail,
+            repair,
+        }
+    }
+
+    fn for_profile(mut self, profile_id: Option<i64>) -> Self {
+        self.profile_id = profile_id;
+        self
+    }
+}
+
+// --- Timestamps ---
+
+// The app only ever writes RFC 3339; anything else is an issue.
+fn parse_stored(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::Text(text) => DateTime::parse_from_rfc3339(text).ok().map(|dt| dt.with_timezone(&Utc)),
+        _ => None,
+    }
+}
+
+// Reads timestamps in the other shapes they turn up in: SQLite's own datetime format,
+// naive ISO 8601 and Unix time in seconds or milliseconds, all taken as UTC.
+fn recover(value: &Value) -> Option<DateTime<Utc>> {
+    let from_unix = |n: i64| {
+        let at = if n.abs() >= 100_000_000_000 { Utc.timestamp_millis_opt(n) } else { Utc.timestamp_opt(n, 0) };
+        at.single()
+    };
+    match value {
+        Value::Integer(n) => from_unix(*n),
+        Value::Real(n) => from_unix(*n as i64),
+        Value::Text(text) => {
+            let text = text.trim();
+            if let Ok(n) = text.parse::<i64>() {
+                return from_unix(n);
+            }
+            ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+                .iter()
+                .find_map(|format| NaiveDateTime::parse_from_str(text.trim_end_matches(['Z', 'z']), format).ok())
+                .map(|naive| naive.and_utc())
+                .or_else(|| DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f%:z").ok().map(|dt| dt.with_timezone(&Utc)))
+        }
+        _ => None,
+    }
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(n) => n.to_string(),
+        Value::Real(n) => n.to_string(),
+        Value::Text(text) => format!("\"{}\"", text),
+        Value::Blob(blob) => format!("{} bytes of binary data", blob.len()),
+    }
+}
+
+// --- Checks ---
+
+fn corruption(conn: &Connection) -> rusqlite::Result<Vec<Issue>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA integrity_check({})", MAX_CORRUPTION_MESSAGES))?;
+    let messages = stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(messages
+        .into_iter()
+        .filter(|message| message != "ok")
+        .enumerate()
+        .map(|(i, message)| Issue {
+            id: format!("{}:{}", IssueKind::Corruption.as_str(), i),
+            kind: IssueKind::Corruption,
+            table: "",
+            row_id: None,
+            column: None,
+            profile_id: None,
+            detail: message,
+            repair: None,
+        })
+        .collect())
+}
+
+struct RepTimes {
+    first: DateTime<Utc>,
+    last: DateTime<Utc>,
+}
+
+fn reps(conn: &Connection, issues: &mut Vec<Issue>) -> rusqlite::Result<HashMap<i64, RepTimes>> {
+    let mut stmt = conn.prepare(
+        "SELECT r.id, r.session_id, r.rep_timestamp, s.id IS NOT NULL, s.profile_id
+         FROM reps r LEFT JOIN sessions s ON s.id = r.session_id
+         ORDER BY r.id",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, Value>(2)?, row.get::<_, bool>(3)?, row.get::<_, Option<i64>>(4)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut times: HashMap<i64, RepTimes> = HashMap::new();
+    for (id, session_id, timestamp, has_session, profile_id) in rows {
+        if !has_session {
+            let detail = format!("session {} no longer exists", session_id);
+            issues.push(Issue::new(IssueKind::OrphanedRep, "reps", id, "session_id", detail, Some(Repair::DeleteRow)));
+            continue;
+        }
+        let at = match parse_stored(&timestamp) {
+            Some(at) => at,
+            None => {
+                let recovered = recover(&timestamp);
+                let repair = Some(recovered.map_or(Repair::DeleteRow, |value| Repair::SetTimestamp { value }));
+                let detail = format!("rep_timestamp {} can't be read", describe(&timestamp));
+                issues.push(Issue::new(IssueKind::BadTimestamp, "reps", id, "rep_timestamp", detail, repair).for_profile(profile_id));
+                match recovered {
+                    Some(at) => at,
+                    None => continue,
+                }
+            }
+        };
+        let entry = times.entry(session_id).or_insert(RepTimes { first: at, last: at });
+        entry.first = entry.first.min(at);
+        entry.last = entry.last.max(at);
+    }
+    Ok(times)
+}
+
+struct OpenSession {
+    id: i64,
+    start: Option<DateTime<Utc>>,
+    end: DateTime<Utc>,
+}
+
+fn sessions(conn: &Connection, rep_times: &HashMap<i64, RepTimes>, issues: &mut Vec<Issue>) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare("SELECT id, profile_id, start_time, end_time, deleted_at FROM sessions ORDER BY id")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, Value>(2)?, row.get::<_, Value>(3)?, row.get::<_, Value>(4)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut open: HashMap<i64, Vec<OpenSession>> = HashMap::new();
+    for (id, profile_id, start_value, end_value, deleted_value) in rows {
+        let reps = rep_times.get(&id);
+        let mut push = |issue: Issue| issues.push(issue.for_profile(Some(profile_id)));
+        let end_parsed = parse_stored(&end_value);
+
+        // The start falls back to the first rep, then the end; a session with none of
+        // them has nothing left worth keeping
+        let start = parse_stored(&start_value);
+        if start.is_none() {
+            let repair = recover(&start_value).or(reps.map(|r| r.first)).or(end_parsed).or_else(|| recover(&end_value));
+            let detail = format!("start_time {} can't be read", describe(&start_value));
+            push(Issue::new(
+                IssueKind::BadTimestamp,
+                "sessions",
+                id,
+                "start_time",
+                detail,
+                Some(repair.map_or(Repair::DeleteRow, |value| Repair::SetTimestamp { value })),
+            ));
+        }
+        let start_or_repaired = start.or_else(|| recover(&start_value)).or(reps.map(|r| r.first));
+
+        if end_value != Value::Null && end_parsed.is_none() {
+            let repair = recover(&end_value).or(reps.map(|r| r.last)).or(start_or_repaired);
+            let detail = format!("end_time {} can't be read", describe(&end_value));
+            push(Issue::new(IssueKind::BadTimestamp, "sessions", id, "end_time", detail, repair.map(|value| Repair::SetTimestamp { value })));
+        }
+
+        if let (Some(start), Some(end)) = (start, end_parsed)
+            && end < start
+        {
+            let value = reps.map(|r| r.last).filter(|last| *last >= start).unwrap_or(start);
+            let detail = format!("ends at {} before it starts at {}", end.to_rfc3339(), start.to_rfc3339());
+            push(Issue::new(IssueKind::EndBeforeStart, "sessions", id, "end_time", detail, Some(Repair::SetTimestamp { value })));
+        }
+
+        if deleted_value != Value::Null && parse_stored(&deleted_value).is_none() {
+            // Keeps the session in the trash for the full retention period from now
+            let value = recover(&deleted_value).unwrap_or_else(Utc::now);
+            let detail = format!("deleted_at {} can't be read", describe(&deleted_value));
+            push(Issue::new(IssueKind::BadTimestamp, "sessions", id, "deleted_at", detail, Some(Repair::SetTimestamp { value })));
+        }
+
+        if end_value == Value::Null && deleted_value == Value::Null {
+            let end = reps.map(|r| r.last).or(start_or_repaired).unwrap_or_else(Utc::now);
+            open.entry(profile_id).or_default().push(OpenSession { id, start: start_or_repaired, end });
+        }
+    }
+
+    // The newest open session is the one the app resumes; older ones are ended at their last rep
+    for (profile_id, mut sessions) in open {
+        sessions.sort_by_key(|session| (session.start, session.id));
+        sessions.pop();
+        for session in sessions {
+            let detail = "left open while a newer session was started".to_string();
+            let repair = Some(Repair::SetTimestamp { value: session.end });
+            issues.push(Issue::new(IssueKind::DuplicateOpenSession, "sessions", session.id, "end_time", detail, repair).for_profile(Some(profile_id)));
+        }
+    }
+    Ok(())
+}
+
+// Checks the whole database, every profile included.
+pub fn check(conn: &Connection) -> rusqlite::Result<IntegrityReport> {
+    let mut issues = corruption(conn)?;
+    let rep_times = reps(conn, &mut issues)?;
+    sessions(conn, &rep_times, &mut issues)?;
+    Ok(IntegrityReport { ok: issues.is_empty(), issues, repaired: 0 })
+}
+
+// Applies the repairs of the issues with the given ids, in one transaction, then checks
+// again. Ids that no longer match an issue are ignored, so a stale list is harmless.
+pub fn repair(conn: &Connection, ids: &[String]) -> rusqlite::Result<IntegrityReport> {
+    let tx = conn.unchecked_transaction()?;
+    let selected: Vec<Issue> = check(&tx)?.issues.into_iter().filter(|issue| ids.contains(&issue.id)).collect();
+    // Reps first, so sessions ended below are scored with readable rep times
+    let (rep_issues, session_issues): (Vec<&Issue>, Vec<&Issue>) = selected.iter().partition(|issue| issue.table == "reps");
+    let mut repaired = 0;
+    let mut ended = Vec::new();
+    for issue in rep_issues.into_iter().chain(session_issues) {
+        let (Some(repair), Some(row_id), Some(column)) = (&issue.repair, issue.row_id, issue.column) else {
+            continue;
+        };
+        let changed = match repair {
+            Repair::SetTimestamp { value } => tx.execute(
+                &format!("UPDATE {} SET {} = ?1 WHERE id = ?2", issue.table, column),
+                params![value.to_rfc3339(), row_id],
+            )?,
+            Repair::DeleteRow => tx.execute(&format!("DELETE FROM {} WHERE id = ?1", issue.table), [row_id])?,
+        };
+        if issue.table == "sessions" && column == "end_time" && changed > 0 {
+            ended.push(row_id);
+        }
+        repaired += changed as u32;
+    }
+    for session_id in ended {
+        // A session whose start is still unreadable is scored on a later repair
+        if let Err(e) = db::score_completed_session(&tx, session_id) {
+            warn!("Could not score repaired session {}: {}", session_id, e);
+        }
+    }
+    let mut report = check(&tx)?;
+    tx.commit()?;
+    report.repaired = repaired;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(report: &IntegrityReport, kind: IssueKind) -> Vec<String> {
+        report.issues.iter().filter(|issue| issue.kind == kind).map(|issue| issue.id.clone()).collect()
+    }
+
+    #[test]
+    fn clean_database_has_no_issues() {
+        let conn = db::initialize_database(":memory:").unwrap();
+        let session = db::start_new_session(&conn, 30, "").unwrap();
+        db::add_rep(&conn, session).unwrap();
+        db::end_session(&conn, session, "", &Utc::now()).unwrap();
+        let report = check(&conn).unwrap();
+        assert!(report.ok, "{:?}", report.issues);
+    }
+
+    #[test]
+    fn repairs_unreadable_timestamps_and_keeps_history_visible() {
+        let conn = db::initialize_database(":memory:").unwrap();
+        let good = db::start_new_session(&conn, 30, "").unwrap();
+        db::end_session(&conn, good, "", &Utc::now()).unwrap();
+        let bad = db::start_new_session(&conn, 35, "").unwrap();
+        db::add_rep_at(&conn, bad, Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 5).unwrap()).unwrap();
+        db::end_session(&conn, bad, "", &Utc::now()).unwrap();
+        conn.execute("UPDATE sessions SET start_time = '2024-03-01 09:00:00' WHERE id = ?1", [bad]).unwrap();
+
+        // Tolerant loading skips the bad row instead of failing
+        let (sessions, skipped) = db::get_all_sessions_checked(&conn).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(skipped[0].session_id, bad);
+
+        let report = check(&conn).unwrap();
+        let issue = &report.issues[0];
+        assert_eq!((issue.kind, issue.column), (IssueKind::BadTimestamp, Some("start_time")));
+        assert_eq!(issue.repair, Some(Repair::SetTimestamp { value: Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap() }));
+
+        let repaired = repair(&conn, std::slice::from_ref(&issue.id)).unwrap();
+        assert_eq!(repaired.repaired, 1);
+        assert!(repaired.ok, "{:?}", repaired.issues);
+        assert_eq!(db::get_all_sessions(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn finds_orphans_inverted_sessions_and_extra_open_sessions() {
+        let conn = db::initialize_database(":memory:").unwrap();
+        let first = db::start_new_session(&conn, 30, "").unwrap();
+        db::add_rep_at(&conn, first, Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap()).unwrap();
+        conn.execute("UPDATE sessions SET start_time = '2024-03-01T08:59:00+00:00' WHERE id = ?1", [first]).unwrap();
+        db::start_new_session(&conn, 30, "").unwrap();
+        let inverted = db::start_new_session(&conn, 30, "").unwrap();
+        conn.execute("UPDATE sessions SET end_time = '2000-01-01T00:00:00+00:00' WHERE id = ?1", [inverted]).unwrap();
+        conn.execute("PRAGMA foreign_keys = OFF", []).unwrap();
+        conn.execute("INSERT INTO reps (session_id, rep_timestamp) VALUES (999, '2024-03-01T09:00:00+00:00')", []).unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+
+        let report = check(&conn).unwrap();
+        assert_eq!(ids(&report, IssueKind::OrphanedRep).len(), 1);
+        assert_eq!(ids(&report, IssueKind::EndBeforeStart), vec![format!("end_before_start:sessions:{}:end_time", inverted)]);
+        // The older of the two open sessions is ended at its last rep
+        let open = report.issues.iter().find(|issue| issue.kind == IssueKind::DuplicateOpenSession).unwrap();
+        assert_eq!(open.row_id, Some(first));
+        assert_eq!(open.repair, Some(Repair::SetTimestamp { value: Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap() }));
+
+        let all: Vec<String> = report.issues.iter().map(|issue| issue.id.clone()).collect();
+        let repaired = repair(&conn, &all).unwrap();
+        assert_eq!(repaired.repaired, 3);
+        assert!(repaired.ok, "{:?}", repaired.issues);
+        assert!(db::get_all_sessions(&conn).unwrap().iter().any(|session| session.id == first && session.score.is_some()));
+    }
+}
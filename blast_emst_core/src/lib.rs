@@ -1,12 +1,54 @@
 use jni::JNIEnv;
 use jni::objects::{JClass, JString};
-use jni::sys::{jstring, jint, jlong};
+use jni::sys::{jstring, jint, jlong, jboolean, jdouble, JNI_TRUE, JNI_FALSE};
 use std::sync::Mutex;
-use log::{info, error};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use log::{info, warn, error};
 
+mod achievements;
+mod backup;
+mod cadence;
+mod calendar;
+mod checkins;
+pub mod dashboard;
 mod db;
+mod devices;
+mod encryption;
+mod fhir;
+mod guidance;
+mod import;
+mod integrity;
+mod profile;
+mod progression;
+mod questionnaires;
+mod reminders;
+mod scoring;
+mod snapshots;
+pub mod share;
+mod stats;
+pub mod sync;
+pub mod sync_server;
+mod units;
 
 static DB_CONNECTION: Mutex<Option<rusqlite::Connection>> = Mutex::new(None);
+// The guided session in progress, if any, with the id of the session it logs reps to.
+// Always lock DB_CONNECTION first when holding both.
+static GUIDANCE: Mutex<Option<(i64, guidance::Guide)>> = Mutex::new(None);
+// Where snapshots of the open database go; None for in-memory databases. Always lock
+// DB_CONNECTION first when holding both.
+static SNAPSHOTS: Mutex<Option<snapshots::SnapshotStore>> = Mutex::new(None);
+
+// Takes a snapshot if the schedule calls for one. Failures are logged rather than passed
+// on, as a missed snapshot shouldn't fail what triggered it.
+fn snapshot_if_due(conn: &rusqlite::Connection, trigger: snapshots::Trigger) {
+    if let Some(store) = &*SNAPSHOTS.lock().unwrap() {
+        match store.take_if_due(conn, trigger) {
+            Ok(Some(snapshot)) => info!("Took database snapshot {}.", snapshot.id),
+            Ok(None) => {}
+            Err(e) => error!("Failed to take database snapshot: {}", e),
+        }
+    }
+}
 
 #[unsafe(no_mangle)]
 #[allow(non_snake_case)]
@@ -15,11 +57,19 @@ pub extern "C" fn Java_com_example_blastemst_RustBridge_initDatabase(
     _class: JClass,
     db_path: JString,
 ) {
+    #[cfg(target_os = "android")]
     android_log::init("Rust").unwrap();
     let path_str: String = env.get_string(&db_path).expect("Invalid DB path").into();
     match db::initialize_database(&path_str) {
         Ok(connection) => {
-            *DB_CONNECTION.lock().unwrap() = Some(connection);
+            match db::purge_expired_sessions(&connection) {
+                Ok(purged) => info!("Purged {} expired sessions from the trash.", purged),
+                Err(e) => error!("Failed to purge expired sessions: {}", e),
+            }
+            let mut db_conn_guard = DB_CONNECTION.lock().unwrap();
+            *SNAPSHOTS.lock().unwrap() = snapshots::directory_for(&path_str).map(|dir| snapshots::SnapshotStore::new(dir, None));
+            snapshot_if_due(&connection, snapshots::Trigger::Opened);
+            *db_conn_guard = Some(connection);
             info!("Database initialized successfully at path: {}", path_str);
         }
         Err(e) => {
@@ -28,6 +78,55 @@ pub extern "C" fn Java_com_example_blastemst_RustBridge_initDatabase(
     }
 }
 
+// Like initDatabase, but opens the database encrypted with a 256-bit key from the app's
+// keystore, given as 64 hex digits. An existing plaintext database is encrypted in place
+// on first use. Returns false if the key is wrong or the build lacks SQLCipher support.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_initEncryptedDatabase(
+    mut env: JNIEnv,
+    _class: JClass,
+    db_path: JString,
+    key_hex: JString,
+) -> jboolean {
+    // Ignore the error if logging is already set up, e.g. when retrying after a wrong key
+    #[cfg(target_os = "android")]
+    let _ = android_log::init("Rust");
+    let path_str: String = env.get_string(&db_path).expect("Invalid DB path").into();
+    let key_str: String = env.get_string(&key_hex).expect("Invalid database key").into();
+    let Some(key) = encryption::DatabaseKey::from_hex(&key_str) else {
+        error!("Database key must be 64 hex digits.");
+        return JNI_FALSE;
+    };
+    match db::initialize_encrypted_database(&path_str, &key) {
+        Ok(connection) => {
+            match db::purge_expired_sessions(&connection) {
+                Ok(purged) => info!("Purged {} expired sessions from the trash.", purged),
+                Err(e) => error!("Failed to purge expired sessions: {}", e),
+            }
+            let mut db_conn_guard = DB_CONNECTION.lock().unwrap();
+            let store = snapshots::directory_for(&path_str).map(|dir| snapshots::SnapshotStore::new(dir, Some(key)));
+            if let Some(store) = &store {
+                // Snapshots from before the database was encrypted would otherwise stay in plaintext
+                match store.secure_existing() {
+                    Ok(deleted) if deleted > 0 => warn!("Deleted {} snapshots the database key cannot open.", deleted),
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to encrypt existing snapshots: {}", e),
+                }
+            }
+            *SNAPSHOTS.lock().unwrap() = store;
+            snapshot_if_due(&connection, snapshots::Trigger::Opened);
+            *db_conn_guard = Some(connection);
+            info!("Encrypted database initialized successfully at path: {}", path_str);
+            JNI_TRUE
+        }
+        Err(e) => {
+            error!("Failed to initialize encrypted database: {}", e);
+            JNI_FALSE
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 #[allow(non_snake_case)]
 pub extern "C" fn Java_com_example_blastemst_RustBridge_startSession(
@@ -83,6 +182,33 @@ pub extern "C" fn Java_com_example_blastemst_RustBridge_getAllSessions(
     }
 }
 
+// Like getAllSessions, but also lists the rows that couldn't be read:
+// {"sessions": [...], "skipped": [{"session_id": 4, "problem": "..."}]}.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getAllSessionsChecked(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match db::get_all_sessions_checked(conn) {
+            Ok((sessions, skipped)) => {
+                let json = serde_json::json!({ "sessions": sessions, "skipped": skipped });
+                env.new_string(json.to_string()).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to get all sessions: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
 #[unsafe(no_mangle)]
 #[allow(non_snake_case)]
 pub extern "C" fn Java_com_example_blastemst_RustBridge_getProfile(
@@ -110,30 +236,40 @@ pub extern "C" fn Java_com_example_blastemst_RustBridge_getProfile(
     }
 }
 
+// Saves the active profile. Returns a JSON array of field errors, which is empty when the
+// profile was saved; malformed dates and diagnoses are reported against their own field.
 #[unsafe(no_mangle)]
 #[allow(non_snake_case)]
 pub extern "C" fn Java_com_example_blastemst_RustBridge_updateProfile(
     mut env: JNIEnv,
     _class: JClass,
     profile_json: JString,
-) {
+) -> jstring {
     info!("Attempting to update user profile.");
     let profile_str: String = env.get_string(&profile_json).expect("Invalid profile JSON").into();
-    match serde_json::from_str::<db::UserProfile>(&profile_str) {
+    let errors = match serde_json::from_str::<profile::ProfileInput>(&profile_str) {
         Ok(profile) => {
             let db_conn_guard = DB_CONNECTION.lock().unwrap();
             if let Some(conn) = &*db_conn_guard {
-                if let Err(e) = db::update_profile(conn, &profile) {
-                    error!("Failed to update profile in DB: {}", e);
+                match db::update_profile(conn, &profile) {
+                    Ok(_) => Vec::new(),
+                    Err(e) => {
+                        error!("Failed to update profile in DB: {}", e);
+                        e.field_errors()
+                    }
                 }
             } else {
                 error!("Database connection not initialized for update.");
+                vec![profile::FieldError { field: "profile", message: "Database connection not initialized".to_string() }]
             }
         }
         Err(e) => {
             error!("Failed to deserialize profile JSON: {}", e);
+            vec![profile::FieldError { field: "profile", message: e.to_string() }]
         }
-    }
+    };
+    let json_string = serde_json::to_string(&errors).unwrap_or_else(|_| "[]".to_string());
+    env.new_string(json_string).expect("Couldn't create Java string").into_raw()
 }
 
 #[unsafe(no_mangle)]
@@ -208,6 +344,8 @@ pub extern "C" fn Java_com_example_blastemst_RustBridge_addRep(
     }
 }
 
+// Returns a JSON array of the achievements unlocked by this session, usually empty. Streaks
+// are counted in the weeks of the device's UTC offset.
 #[unsafe(no_mangle)]
 #[allow(non_snake_case)]
 pub extern "C" fn Java_com_example_blastemst_RustBridge_endSession(
@@ -215,17 +353,33 @@ pub extern "C" fn Java_com_example_blastemst_RustBridge_endSession(
     _class: JClass,
     session_id: jlong,
     notes: JString, // Add notes parameter (JString)
-) {
+    utc_offset_minutes: jint,
+) -> jstring {
     let notes_str: String = env.get_string(&notes).expect("Invalid notes string from Java").into(); // Convert JString to Rust String
     info!("Attempting to end session id: {} with notes: '{}'", session_id, notes_str); // Log notes
     let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("[]").expect("Couldn't create default JSON string.");
+    let Some(offset) = FixedOffset::east_opt(utc_offset_minutes * 60) else {
+        error!("Invalid UTC offset: {} minutes", utc_offset_minutes);
+        return default_json.into_raw();
+    };
     if let Some(conn) = &*db_conn_guard {
-        match db::end_session(conn, session_id, &notes_str) { // Pass notes_str to db::end_session
-            Ok(_) => info!("Successfully ended session."),
-            Err(e) => error!("Failed to end session: {}", e),
+        let now = Utc::now().with_timezone(&offset);
+        match db::end_session(conn, session_id, &notes_str, &now) { // Pass notes_str to db::end_session
+            Ok(unlocked) => {
+                info!("Successfully ended session, unlocking {} achievements.", unlocked.len());
+                snapshot_if_due(conn, snapshots::Trigger::SessionEnded);
+                let json_string = serde_json::to_string(&unlocked).unwrap_or_else(|_| "[]".to_string());
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to end session: {}", e);
+                default_json.into_raw()
+            }
         }
     } else {
         error!("Database connection not initialized.");
+        default_json.into_raw()
     }
 }
 
@@ -341,4 +495,1596 @@ pub extern "C" fn Java_com_example_blastemst_RustBridge_deleteSession(
     } else {
         error!("Database connection not initialized for delete operation.");
     }
-}
\ No newline at end of file
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_listDeletedSessions(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    info!("Attempting to list deleted sessions.");
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("[]").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match db::list_deleted_sessions(conn) {
+            Ok(sessions) => {
+                let json_string = serde_json::to_string(&sessions).unwrap_or_else(|_| "[]".to_string());
+                info!("Successfully retrieved {} deleted sessions.", sessions.len());
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to list deleted sessions: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_restoreSession(
+    _env: JNIEnv,
+    _class: JClass,
+    session_id: jlong,
+) {
+    info!("Attempting to restore session with id: {}", session_id);
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        if let Err(e) = db::restore_session(conn, session_id) {
+            error!("Failed to restore session {}: {}", session_id, e);
+        }
+    } else {
+        error!("Database connection not initialized for restore operation.");
+    }
+}
+
+// How many days deleted sessions stay in the trash. This is device-wide, so it can't go
+// through getSetting/setSetting, which are per profile.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getTrashRetentionDays(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jint {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        match db::get_trash_retention_days(conn) {
+            Ok(days) => days.min(jint::MAX as i64) as jint,
+            Err(e) => {
+                error!("Failed to get trash retention: {}", e);
+                db::DEFAULT_TRASH_RETENTION_DAYS as jint
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        db::DEFAULT_TRASH_RETENTION_DAYS as jint
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_setTrashRetentionDays(
+    _env: JNIEnv,
+    _class: JClass,
+    days: jint,
+) -> jboolean {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        match db::set_trash_retention_days(conn, days as i64) {
+            Ok(()) => JNI_TRUE,
+            Err(e) => {
+                error!("Failed to set trash retention: {}", e);
+                JNI_FALSE
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        JNI_FALSE
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_listProfiles(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    info!("Attempting to list profiles.");
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("[]").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match db::list_profiles(conn) {
+            Ok(profiles) => {
+                let json_string = serde_json::to_string(&profiles).unwrap_or_else(|_| "[]".to_string());
+                info!("Successfully retrieved {} profiles.", profiles.len());
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to list profiles: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_createProfile(
+    mut env: JNIEnv,
+    _class: JClass,
+    profile_json: JString,
+) -> jlong {
+    info!("Attempting to create a new profile.");
+    let profile_str: String = env.get_string(&profile_json).expect("Invalid profile JSON").into();
+    match serde_json::from_str::<profile::ProfileInput>(&profile_str) {
+        Ok(profile) => {
+            let db_conn_guard = DB_CONNECTION.lock().unwrap();
+            if let Some(conn) = &*db_conn_guard {
+                match db::create_profile(conn, &profile) {
+                    Ok(new_id) => {
+                        info!("Successfully created profile with id: {}", new_id);
+                        new_id
+                    }
+                    Err(e) => {
+                        error!("Failed to create profile: {}", e);
+                        -1
+                    }
+                }
+            } else {
+                error!("Database connection not initialized.");
+                -1
+            }
+        }
+        Err(e) => {
+            error!("Failed to deserialize profile JSON: {}", e);
+            -1
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getActiveProfileId(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jlong {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        match db::active_profile_id(conn) {
+            Ok(id) => id,
+            Err(e) => {
+                error!("Failed to get active profile id: {}", e);
+                -1
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        -1
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_switchProfile(
+    _env: JNIEnv,
+    _class: JClass,
+    profile_id: jlong,
+) -> jboolean {
+    info!("Attempting to switch to profile with id: {}", profile_id);
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        match db::switch_profile(conn, profile_id) {
+            Ok(_) => JNI_TRUE,
+            Err(e) => {
+                error!("Failed to switch to profile {}: {}", profile_id, e);
+                JNI_FALSE
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        JNI_FALSE
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_deleteProfile(
+    _env: JNIEnv,
+    _class: JClass,
+    profile_id: jlong,
+) {
+    info!("Attempting to delete profile with id: {}", profile_id);
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        if let Err(e) = db::delete_profile(conn, profile_id) {
+            error!("Failed to delete profile {}: {}", profile_id, e);
+        }
+    } else {
+        error!("Database connection not initialized for delete operation.");
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getTrainerDevices(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let json_string = serde_json::to_string(&devices::CATALOG).unwrap_or_else(|_| "[]".to_string());
+    env.new_string(json_string).expect("Couldn't create Java string").into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getDefaultPressure(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jint {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        match db::get_default_pressure(conn) {
+            Ok(pressure) => pressure,
+            Err(e) => {
+                error!("Failed to get default pressure: {}", e);
+                devices::FALLBACK_DEFAULT_PRESSURE
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        devices::FALLBACK_DEFAULT_PRESSURE
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getPressureUnit(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let unit = if let Some(conn) = &*db_conn_guard {
+        db::get_pressure_unit(conn).unwrap_or_else(|e| {
+            error!("Failed to get pressure unit: {}", e);
+            units::PressureUnit::default()
+        })
+    } else {
+        error!("Database connection not initialized.");
+        units::PressureUnit::default()
+    };
+    env.new_string(unit.as_str()).expect("Couldn't create Java string").into_raw()
+}
+
+// Converts a pressure the user entered in any unit to the whole cmH2O value used for storage.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_toCanonicalPressure(
+    mut env: JNIEnv,
+    _class: JClass,
+    value: jdouble,
+    unit: JString,
+) -> jint {
+    let unit_str: String = env.get_string(&unit).expect("Invalid unit string").into();
+    match units::PressureUnit::parse(&unit_str) {
+        Some(unit) => units::Pressure::new(value, unit).cmh2o().round() as jint,
+        None => {
+            error!("Unknown pressure unit: {}", unit_str);
+            -1
+        }
+    }
+}
+
+// Formats a stored cmH2O pressure in the active profile's display unit, e.g. "22.1 mmHg".
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_formatPressure(
+    env: JNIEnv,
+    _class: JClass,
+    pressure_cmh2o: jint,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let unit = match &*db_conn_guard {
+        Some(conn) => db::get_pressure_unit(conn).unwrap_or_default(),
+        None => units::PressureUnit::default(),
+    };
+    let text = units::Pressure::from_cmh2o(pressure_cmh2o as f64).format(unit);
+    env.new_string(text).expect("Couldn't create Java string").into_raw()
+}
+
+// Decides whether the user should be reminded to train, returning a ReminderDecision as
+// JSON. The app passes its current UTC offset so days and weeks match the user's calendar.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_nextReminder(
+    env: JNIEnv,
+    _class: JClass,
+    utc_offset_minutes: jint,
+) -> jstring {
+    info!("Deciding on the next reminder.");
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    let Some(offset) = FixedOffset::east_opt(utc_offset_minutes * 60) else {
+        error!("Invalid UTC offset: {} minutes", utc_offset_minutes);
+        return default_json.into_raw();
+    };
+    if let Some(conn) = &*db_conn_guard {
+        let now = Utc::now().with_timezone(&offset);
+        let targets = match db::get_training_targets(conn) {
+            Ok(targets) => targets,
+            Err(e) => {
+                error!("Failed to get training targets: {}", e);
+                return default_json.into_raw();
+            }
+        };
+        let schedule = match db::get_reminder_schedule(conn) {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                error!("Failed to load reminder schedule: {}", e);
+                return default_json.into_raw();
+            }
+        };
+        match db::get_recent_session_end_times(conn, Utc::now() - Duration::days(8)) {
+            Ok(history) => {
+                let decision = reminders::next_reminder(&now, &history, &targets);
+                // Never remind during quiet hours or on rest days
+                let decision = reminders::apply_schedule(decision, &schedule, &offset);
+                info!("Reminder decision: {:?}", decision);
+                let json_string = serde_json::to_string(&decision).unwrap_or_default();
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to load session history for reminders: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getReminderSchedule(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("{}").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match db::get_reminder_schedule(conn) {
+            Ok(schedule) => {
+                let json_string = serde_json::to_string(&schedule).unwrap_or_else(|_| "{}".to_string());
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to get reminder schedule: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_setReminderSchedule(
+    mut env: JNIEnv,
+    _class: JClass,
+    schedule_json: JString,
+) -> jboolean {
+    info!("Attempting to update the reminder schedule.");
+    let schedule_str: String = env.get_string(&schedule_json).expect("Invalid schedule JSON").into();
+    match serde_json::from_str::<reminders::ReminderSchedule>(&schedule_str) {
+        Ok(schedule) => {
+            let db_conn_guard = DB_CONNECTION.lock().unwrap();
+            if let Some(conn) = &*db_conn_guard {
+                match db::set_reminder_schedule(conn, &schedule) {
+                    Ok(_) => JNI_TRUE,
+                    Err(e) => {
+                        error!("Failed to save reminder schedule: {}", e);
+                        JNI_FALSE
+                    }
+                }
+            } else {
+                error!("Database connection not initialized.");
+                JNI_FALSE
+            }
+        }
+        Err(e) => {
+            error!("Failed to deserialize reminder schedule JSON: {}", e);
+            JNI_FALSE
+        }
+    }
+}
+
+// Returns a JSON array of the next `count` scheduled reminder times (RFC 3339, UTC).
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getUpcomingReminders(
+    env: JNIEnv,
+    _class: JClass,
+    utc_offset_minutes: jint,
+    count: jint,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("[]").expect("Couldn't create default JSON string.");
+    let Some(offset) = FixedOffset::east_opt(utc_offset_minutes * 60) else {
+        error!("Invalid UTC offset: {} minutes", utc_offset_minutes);
+        return default_json.into_raw();
+    };
+    if let Some(conn) = &*db_conn_guard {
+        match db::get_reminder_schedule(conn) {
+            Ok(schedule) => {
+                let now = Utc::now().with_timezone(&offset);
+                let upcoming = schedule.upcoming_reminders(&now, count.max(0) as usize);
+                let json_string = serde_json::to_string(&upcoming).unwrap_or_else(|_| "[]".to_string());
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to get reminder schedule: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// Today's progress toward the daily target as JSON, e.g. {"completed":1,"target":2,...}.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getDailyProgress(
+    env: JNIEnv,
+    _class: JClass,
+    utc_offset_minutes: jint,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    let Some(offset) = FixedOffset::east_opt(utc_offset_minutes * 60) else {
+        error!("Invalid UTC offset: {} minutes", utc_offset_minutes);
+        return default_json.into_raw();
+    };
+    if let Some(conn) = &*db_conn_guard {
+        let now = Utc::now().with_timezone(&offset);
+        let result = db::get_training_targets(conn).and_then(|targets| {
+            let history = db::get_recent_session_end_times(conn, Utc::now() - Duration::days(2))?;
+            Ok(stats::daily_progress(&now, &history, &targets))
+        });
+        match result {
+            Ok(progress) => {
+                let json_string = serde_json::to_string(&progress).unwrap_or_default();
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to get daily progress: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// This week's adherence to the daily and weekly targets as JSON.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getWeeklyAdherence(
+    env: JNIEnv,
+    _class: JClass,
+    utc_offset_minutes: jint,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    let Some(offset) = FixedOffset::east_opt(utc_offset_minutes * 60) else {
+        error!("Invalid UTC offset: {} minutes", utc_offset_minutes);
+        return default_json.into_raw();
+    };
+    if let Some(conn) = &*db_conn_guard {
+        let now = Utc::now().with_timezone(&offset);
+        let result = db::get_training_targets(conn).and_then(|targets| {
+            let history = db::get_recent_session_end_times(conn, Utc::now() - Duration::days(8))?;
+            Ok(stats::weekly_adherence(&now, &history, &targets))
+        });
+        match result {
+            Ok(adherence) => {
+                let json_string = serde_json::to_string(&adherence).unwrap_or_default();
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to get weekly adherence: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getAchievements(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("[]").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match db::get_achievements(conn) {
+            Ok(achievements) => {
+                let json_string = serde_json::to_string(&achievements).unwrap_or_else(|_| "[]".to_string());
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to get achievements: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// The pressure timeline with weekly maxima, plateaus, regressions, change since the first
+// session and the latest setting as a share of the baseline MEP, as JSON.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getPressureTrend(
+    env: JNIEnv,
+    _class: JClass,
+    utc_offset_minutes: jint,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    let Some(offset) = FixedOffset::east_opt(utc_offset_minutes * 60) else {
+        error!("Invalid UTC offset: {} minutes", utc_offset_minutes);
+        return default_json.into_raw();
+    };
+    if let Some(conn) = &*db_conn_guard {
+        let result = db::get_pressure_unit(conn).and_then(|unit| {
+            let history = db::get_pressure_history(conn)?;
+            let baseline_mep = db::get_profile(conn)?.baseline_mep;
+            Ok(progression::analyze(&history, baseline_mep, &offset, unit))
+        });
+        match result {
+            Ok(trend) => {
+                let json_string = serde_json::to_string(&trend).unwrap_or_default();
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to get pressure trend: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getScoreSummary(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match db::get_score_summary(conn) {
+            Ok(summary) => {
+                let json_string = serde_json::to_string(&summary).unwrap_or_default();
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to get score summary: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getSessionCadence(
+    env: JNIEnv,
+    _class: JClass,
+    session_id: jlong,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match db::get_session_cadence(conn, session_id) {
+            Ok(cadence) => {
+                let json_string = serde_json::to_string(&cadence).unwrap_or_default();
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to get cadence for session {}: {}", session_id, e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// Logs a rep for every RepCompleted cue, at the time the blow phase ended.
+fn log_guided_reps(conn: &rusqlite::Connection, session_id: i64, cues: &[guidance::Cue]) {
+    for cue in cues {
+        if let guidance::Cue::RepCompleted { at, .. } = cue
+            && let Err(e) = db::add_rep_at(conn, session_id, *at)
+        {
+            error!("Failed to add guided rep: {}", e);
+        }
+    }
+}
+
+// Feeds the guided session a timestamp (ms since the epoch) and an optional user event and
+// returns the resulting GuidanceUpdate as JSON, or an empty string without a guided session.
+fn drive_guidance(env: &JNIEnv, now_millis: jlong, event: Option<guidance::UserEvent>) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let mut guidance_guard = GUIDANCE.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    let Some(now) = DateTime::from_timestamp_millis(now_millis) else {
+        error!("Invalid timestamp: {}", now_millis);
+        return default_json.into_raw();
+    };
+    let Some((session_id, guide)) = guidance_guard.as_mut() else {
+        error!("No guided session in progress.");
+        return default_json.into_raw();
+    };
+    let cues = match event {
+        Some(event) => guide.handle(event, now),
+        None => guide.tick(now),
+    };
+    if let Some(conn) = &*db_conn_guard {
+        log_guided_reps(conn, *session_id, &cues);
+    } else {
+        error!("Database connection not initialized.");
+    }
+    let update = guidance::GuidanceUpdate { session_id: *session_id, state: guide.state(now), cues };
+    let json_string = serde_json::to_string(&update).unwrap_or_default();
+    env.new_string(json_string).unwrap_or(default_json).into_raw()
+}
+
+// Starts guided pacing for an already started session, paced by the active profile's
+// prescription. Replaces any guided session still in progress.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_startGuidance(
+    env: JNIEnv,
+    _class: JClass,
+    session_id: jlong,
+    now_millis: jlong,
+) -> jstring {
+    {
+        let db_conn_guard = DB_CONNECTION.lock().unwrap();
+        let Some(conn) = &*db_conn_guard else {
+            error!("Database connection not initialized.");
+            return env.new_string("").expect("Couldn't create default JSON string.").into_raw();
+        };
+        let config = match db::get_prescription(conn) {
+            Ok(prescription) => guidance::GuidanceConfig::from_prescription(&prescription),
+            Err(e) => {
+                error!("Failed to get prescription: {}", e);
+                return env.new_string("").expect("Couldn't create default JSON string.").into_raw();
+            }
+        };
+        info!("Starting guidance for session id: {}", session_id);
+        *GUIDANCE.lock().unwrap() = Some((session_id, guidance::Guide::new(config)));
+    }
+    drive_guidance(&env, now_millis, Some(guidance::UserEvent::Start))
+}
+
+// Called periodically (e.g. every 250 ms) while the guidance screen is visible.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_guidanceTick(
+    env: JNIEnv,
+    _class: JClass,
+    now_millis: jlong,
+) -> jstring {
+    drive_guidance(&env, now_millis, None)
+}
+
+// `event` is one of "start", "pause", "resume", "skip_rest" or "stop".
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_guidanceEvent(
+    mut env: JNIEnv,
+    _class: JClass,
+    event: JString,
+    now_millis: jlong,
+) -> jstring {
+    let event_str: String = env.get_string(&event).expect("Invalid event string from Java").into();
+    let Some(event) = guidance::UserEvent::parse(&event_str) else {
+        error!("Unknown guidance event: {}", event_str);
+        return env.new_string("").expect("Couldn't create default JSON string.").into_raw();
+    };
+    drive_guidance(&env, now_millis, Some(event))
+}
+
+// Drops the guided session, e.g. once the app has ended the session.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_clearGuidance(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    *GUIDANCE.lock().unwrap() = None;
+}
+
+// Returns a JSON array with the definition of every built-in questionnaire.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getQuestionnaires(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let definitions: Vec<_> = questionnaires::Instrument::ALL.iter().map(|i| i.definition()).collect();
+    let json_string = serde_json::to_string(&definitions).unwrap_or_else(|_| "[]".to_string());
+    env.new_string(json_string).expect("Couldn't create Java string").into_raw()
+}
+
+// Scores and stores a completed questionnaire. `answers_json` is a JSON array with one
+// value per item and `completed_on` a YYYY-MM-DD date. Returns the stored response as
+// JSON, or an empty string if the answers are invalid.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_submitQuestionnaire(
+    mut env: JNIEnv,
+    _class: JClass,
+    instrument: JString,
+    completed_on: JString,
+    answers_json: JString,
+) -> jstring {
+    let instrument_str: String = env.get_string(&instrument).expect("Invalid instrument string from Java").into();
+    let completed_on_str: String = env.get_string(&completed_on).expect("Invalid date string from Java").into();
+    let answers_str: String = env.get_string(&answers_json).expect("Invalid answers JSON").into();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    let Some(instrument) = questionnaires::Instrument::parse(&instrument_str) else {
+        error!("Unknown questionnaire: {}", instrument_str);
+        return default_json.into_raw();
+    };
+    let Some(completed_on) = profile::parse_date(&completed_on_str) else {
+        error!("Invalid questionnaire date: {}", completed_on_str);
+        return default_json.into_raw();
+    };
+    let answers: Vec<u8> = match serde_json::from_str(&answers_str) {
+        Ok(answers) => answers,
+        Err(e) => {
+            error!("Failed to deserialize questionnaire answers: {}", e);
+            return default_json.into_raw();
+        }
+    };
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        match db::save_questionnaire_response(conn, instrument, completed_on, &answers) {
+            Ok(response) => {
+                let json_string = serde_json::to_string(&response).unwrap_or_default();
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to save {} response: {}", instrument_str, e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// `instrument` may be empty to list the responses to every questionnaire.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getQuestionnaireResponses(
+    mut env: JNIEnv,
+    _class: JClass,
+    instrument: JString,
+) -> jstring {
+    let instrument_str: String = env.get_string(&instrument).expect("Invalid instrument string from Java").into();
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("[]").expect("Couldn't create default JSON string.");
+    let instrument = questionnaires::Instrument::parse(&instrument_str);
+    if instrument.is_none() && !instrument_str.is_empty() {
+        error!("Unknown questionnaire: {}", instrument_str);
+        return default_json.into_raw();
+    }
+    if let Some(conn) = &*db_conn_guard {
+        match db::list_questionnaire_responses(conn, instrument) {
+            Ok(responses) => {
+                let json_string = serde_json::to_string(&responses).unwrap_or_else(|_| "[]".to_string());
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to list questionnaire responses: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getQuestionnaireTrend(
+    mut env: JNIEnv,
+    _class: JClass,
+    instrument: JString,
+) -> jstring {
+    let instrument_str: String = env.get_string(&instrument).expect("Invalid instrument string from Java").into();
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    let Some(instrument) = questionnaires::Instrument::parse(&instrument_str) else {
+        error!("Unknown questionnaire: {}", instrument_str);
+        return default_json.into_raw();
+    };
+    if let Some(conn) = &*db_conn_guard {
+        match db::get_questionnaire_trend(conn, instrument) {
+            Ok(trend) => {
+                let json_string = serde_json::to_string(&trend).unwrap_or_default();
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to get {} trend: {}", instrument_str, e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// Saves a pre- or post-session check-in. Returns a JSON object with the saved check-in
+// (including its adverse-symptom flags) under "checkin" and any validation problems
+// under "errors".
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_saveCheckin(
+    mut env: JNIEnv,
+    _class: JClass,
+    checkin_json: JString,
+) -> jstring {
+    let checkin_str: String = env.get_string(&checkin_json).expect("Invalid check-in JSON").into();
+    let result = match serde_json::from_str::<checkins::Checkin>(&checkin_str) {
+        Ok(checkin) => {
+            let db_conn_guard = DB_CONNECTION.lock().unwrap();
+            if let Some(conn) = &*db_conn_guard {
+                db::save_checkin(conn, &checkin).map_err(|e| {
+                    error!("Failed to save check-in: {}", e);
+                    e.field_errors()
+                })
+            } else {
+                error!("Database connection not initialized.");
+                Err(vec![profile::FieldError { field: "checkin", message: "Database connection not initialized".to_string() }])
+            }
+        }
+        Err(e) => {
+            error!("Failed to deserialize check-in JSON: {}", e);
+            Err(vec![profile::FieldError { field: "checkin", message: e.to_string() }])
+        }
+    };
+    let json = match result {
+        Ok(checkin) => serde_json::json!({ "checkin": checkin, "errors": [] }),
+        Err(errors) => serde_json::json!({ "checkin": null, "errors": errors }),
+    };
+    env.new_string(json.to_string()).expect("Couldn't create Java string").into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getSessionCheckins(
+    env: JNIEnv,
+    _class: JClass,
+    session_id: jlong,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("[]").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match db::get_session_checkins(conn, session_id) {
+            Ok(checkins) => {
+                let json_string = serde_json::to_string(&checkins).unwrap_or_else(|_| "[]".to_string());
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to get check-ins for session {}: {}", session_id, e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// Returns the safety warning as JSON, or an empty string when there is nothing to warn about.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getSafetyWarning(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match db::get_safety_warning(conn) {
+            Ok(Some(warning)) => {
+                let json_string = serde_json::to_string(&warning).unwrap_or_default();
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Ok(None) => default_json.into_raw(),
+            Err(e) => {
+                error!("Failed to get safety warning: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// Writes an encrypted backup of the whole database to `path`. Returns false on failure.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_exportBackup(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: JString,
+    passphrase: JString,
+) -> jboolean {
+    let path_str: String = env.get_string(&path).expect("Invalid backup path").into();
+    let passphrase_str: String = env.get_string(&passphrase).expect("Invalid passphrase").into();
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        match backup::export_backup(conn, &passphrase_str) {
+            Ok(archive) => match std::fs::write(&path_str, archive) {
+                Ok(()) => {
+                    info!("Backup written to {}", path_str);
+                    JNI_TRUE
+                }
+                Err(e) => {
+                    error!("Failed to write backup: {}", e);
+                    JNI_FALSE
+                }
+            },
+            Err(e) => {
+                error!("Failed to create backup: {}", e);
+                JNI_FALSE
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        JNI_FALSE
+    }
+}
+
+// Restores the backup at `path`, replacing the current data. Returns an empty string on
+// success, otherwise one of "wrong_passphrase", "corrupted", "not_a_backup",
+// "unsupported_version" or "error".
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_importBackup(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: JString,
+    passphrase: JString,
+) -> jstring {
+    let path_str: String = env.get_string(&path).expect("Invalid backup path").into();
+    let passphrase_str: String = env.get_string(&passphrase).expect("Invalid passphrase").into();
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let result = match (&*db_conn_guard, std::fs::read(&path_str)) {
+        (Some(conn), Ok(archive)) => backup::import_backup(conn, &archive, &passphrase_str).map_err(|e| {
+            error!("Failed to restore backup: {}", e);
+            e.code()
+        }),
+        (None, _) => {
+            error!("Database connection not initialized.");
+            Err("error")
+        }
+        (_, Err(e)) => {
+            error!("Failed to read backup: {}", e);
+            Err("error")
+        }
+    };
+    if result.is_ok() {
+        info!("Backup restored from {}", path_str);
+        // The guided session may refer to a session the backup doesn't have
+        *GUIDANCE.lock().unwrap() = None;
+    }
+    env.new_string(result.err().unwrap_or("")).expect("Couldn't create Java string").into_raw()
+}
+
+// Returns the active profile's data as a FHIR R4 Bundle in JSON.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_exportFhirBundle(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match db::get_fhir_bundle(conn) {
+            Ok(bundle) => env.new_string(bundle.to_string()).unwrap_or(default_json).into_raw(),
+            Err(e) => {
+                error!("Failed to build FHIR bundle: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// Lists the database snapshots on this device as JSON, newest first.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_listSnapshots(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let default_json = env.new_string("[]").expect("Couldn't create default JSON string.");
+    let Some(store) = &*SNAPSHOTS.lock().unwrap() else {
+        return default_json.into_raw();
+    };
+    match store.list() {
+        Ok(snapshots) => {
+            let json_string = serde_json::to_string(&snapshots).unwrap_or_else(|_| "[]".to_string());
+            env.new_string(json_string).unwrap_or(default_json).into_raw()
+        }
+        Err(e) => {
+            error!("Failed to list snapshots: {}", e);
+            default_json.into_raw()
+        }
+    }
+}
+
+// Takes a snapshot now, whatever the schedule says.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_takeSnapshot(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jboolean {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let Some(conn) = &*db_conn_guard else {
+        error!("Database connection not initialized.");
+        return JNI_FALSE;
+    };
+    let Some(store) = &*SNAPSHOTS.lock().unwrap() else {
+        error!("The database has no snapshot directory.");
+        return JNI_FALSE;
+    };
+    match store.take(conn, snapshots::Reason::Manual) {
+        Ok(snapshot) => {
+            info!("Took database snapshot {}.", snapshot.id);
+            JNI_TRUE
+        }
+        Err(e) => {
+            error!("Failed to take database snapshot: {}", e);
+            JNI_FALSE
+        }
+    }
+}
+
+// Replaces the database with a snapshot from listSnapshots. Returns "" on success,
+// otherwise "not_found", "wrong_key" or "error".
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_restoreSnapshot(
+    mut env: JNIEnv,
+    _class: JClass,
+    snapshot_id: JString,
+) -> jstring {
+    let id_str: String = env.get_string(&snapshot_id).expect("Invalid snapshot id").into();
+    info!("Restoring database snapshot {}.", id_str);
+    let mut db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let result = match (db_conn_guard.as_mut(), &*SNAPSHOTS.lock().unwrap()) {
+        (Some(conn), Some(store)) => store.restore(conn, &id_str).map_err(|e| {
+            error!("Failed to restore snapshot: {}", e);
+            e.code()
+        }),
+        (None, _) => {
+            error!("Database connection not initialized.");
+            Err("error")
+        }
+        (_, None) => Err("not_found"),
+    };
+    if result.is_ok() {
+        // A guided session may refer to a session the snapshot doesn't have
+        *GUIDANCE.lock().unwrap() = None;
+    }
+    env.new_string(result.err().unwrap_or("")).expect("Couldn't create Java string").into_raw()
+}
+
+// Returns the snapshot schedule and how many snapshots are kept, e.g.
+// {"schedule":"after_session","keep":5}.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getSnapshotSettings(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match snapshots::get_settings(conn) {
+            Ok(settings) => match serde_json::to_string(&settings) {
+                Ok(json_string) => env.new_string(json_string).unwrap_or(default_json).into_raw(),
+                Err(e) => {
+                    error!("Failed to serialize snapshot settings: {}", e);
+                    default_json.into_raw()
+                }
+            },
+            Err(e) => {
+                error!("Failed to get snapshot settings: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_setSnapshotSettings(
+    mut env: JNIEnv,
+    _class: JClass,
+    settings_json: JString,
+) -> jboolean {
+    let json_str: String = env.get_string(&settings_json).expect("Invalid snapshot settings JSON").into();
+    let settings: snapshots::SnapshotSettings = match serde_json::from_str(&json_str) {
+        Ok(settings) => settings,
+        Err(e) => {
+            error!("Failed to deserialize snapshot settings: {}", e);
+            return JNI_FALSE;
+        }
+    };
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        match snapshots::set_settings(conn, &settings) {
+            Ok(()) => JNI_TRUE,
+            Err(e) => {
+                error!("Failed to save snapshot settings: {}", e);
+                JNI_FALSE
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        JNI_FALSE
+    }
+}
+
+// Checks the whole database and returns an IntegrityReport as JSON, or "" on failure.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_checkIntegrity(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    info!("Checking database integrity.");
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match integrity::check(conn) {
+            Ok(report) => match serde_json::to_string(&report) {
+                Ok(json_string) => env.new_string(json_string).unwrap_or(default_json).into_raw(),
+                Err(e) => {
+                    error!("Failed to serialize integrity report: {}", e);
+                    default_json.into_raw()
+                }
+            },
+            Err(e) => {
+                error!("Integrity check failed: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// Applies the repairs of the issues whose ids are in the JSON array and returns the
+// report of a fresh check, or "" on failure.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_repairIntegrity(
+    mut env: JNIEnv,
+    _class: JClass,
+    issue_ids_json: JString,
+) -> jstring {
+    let ids_str: String = env.get_string(&issue_ids_json).expect("Invalid issue ids JSON").into();
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    let ids: Vec<String> = match serde_json::from_str(&ids_str) {
+        Ok(ids) => ids,
+        Err(e) => {
+            error!("Failed to deserialize issue ids: {}", e);
+            return default_json.into_raw();
+        }
+    };
+    if let Some(conn) = &*db_conn_guard {
+        match integrity::repair(conn, &ids) {
+            Ok(report) => {
+                info!("Repaired {} integrity issues.", report.repaired);
+                match serde_json::to_string(&report) {
+                    Ok(json_string) => env.new_string(json_string).unwrap_or(default_json).into_raw(),
+                    Err(e) => {
+                        error!("Failed to serialize integrity report: {}", e);
+                        default_json.into_raw()
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Integrity repair failed: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// Returns the active profile's completed sessions and the planned sessions of the next
+// `weeks` weeks (counting this one, 1 to 52) as an iCalendar (.ics) file, or "" on failure.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_exportCalendar(
+    env: JNIEnv,
+    _class: JClass,
+    utc_offset_minutes: jint,
+    weeks: jint,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_ics = env.new_string("").expect("Couldn't create default string.");
+    let Some(offset) = FixedOffset::east_opt(utc_offset_minutes * 60) else {
+        error!("Invalid UTC offset: {} minutes", utc_offset_minutes);
+        return default_ics.into_raw();
+    };
+    if let Some(conn) = &*db_conn_guard {
+        let now = Utc::now().with_timezone(&offset);
+        match db::get_calendar(conn, &now, weeks.clamp(1, calendar::MAX_WEEKS as jint) as u32) {
+            Ok(ics) => env.new_string(ics).unwrap_or(default_ics).into_raw(),
+            Err(e) => {
+                error!("Failed to build calendar: {}", e);
+                default_ics.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_ics.into_raw()
+    }
+}
+
+// Writes a signed share bundle with the active profile's completed sessions between two
+// local dates (YYYY-MM-DD, inclusive). Returns "" on success, otherwise "invalid_range" or
+// "error".
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_exportShareBundle(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: JString,
+    from: JString,
+    to: JString,
+) -> jstring {
+    let path_str: String = env.get_string(&path).expect("Invalid share bundle path").into();
+    let from_str: String = env.get_string(&from).expect("Invalid date string from Java").into();
+    let to_str: String = env.get_string(&to).expect("Invalid date string from Java").into();
+    let (Some(from), Some(to)) = (profile::parse_date(&from_str), profile::parse_date(&to_str)) else {
+        error!("Invalid share range: {} to {}", from_str, to_str);
+        return env.new_string("invalid_range").expect("Couldn't create Java string").into_raw();
+    };
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let result = match &*db_conn_guard {
+        Some(conn) => share::export_share_bundle(conn, from, to)
+            .map_err(|e| {
+                error!("Failed to create share bundle: {}", e);
+                e.code()
+            })
+            .and_then(|bundle| {
+                std::fs::write(&path_str, bundle).map_err(|e| {
+                    error!("Failed to write share bundle: {}", e);
+                    "error"
+                })
+            }),
+        None => {
+            error!("Database connection not initialized.");
+            Err("error")
+        }
+    };
+    if result.is_ok() {
+        info!("Share bundle written to {}", path_str);
+    }
+    env.new_string(result.err().unwrap_or("")).expect("Couldn't create Java string").into_raw()
+}
+
+// Returns the fingerprint of the active profile's signing key, for the patient to read out
+// to their clinician.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getShareKeyFingerprint(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_string = env.new_string("").expect("Couldn't create default string.");
+    if let Some(conn) = &*db_conn_guard {
+        match share::key_fingerprint(conn) {
+            Ok(fingerprint) => env.new_string(fingerprint).unwrap_or(default_string).into_raw(),
+            Err(e) => {
+                error!("Failed to load the share signing key: {}", e);
+                default_string.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_string.into_raw()
+    }
+}
+
+// Verifies a share bundle. Pass "" as the expected fingerprint to accept any signer.
+// Returns {"bundle": ..., "error": null} or {"bundle": null, "error": code}.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_verifyShareBundle(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: JString,
+    expected_fingerprint: JString,
+) -> jstring {
+    let path_str: String = env.get_string(&path).expect("Invalid share bundle path").into();
+    let fingerprint_str: String = env.get_string(&expected_fingerprint).expect("Invalid fingerprint").into();
+    let expected = Some(fingerprint_str.as_str()).filter(|f| !f.trim().is_empty());
+    let result = match std::fs::read(&path_str) {
+        Ok(bundle) => share::verify(&bundle, expected).map_err(|e| {
+            error!("Share bundle failed verification: {}", e);
+            e.code()
+        }),
+        Err(e) => {
+            error!("Failed to read share bundle: {}", e);
+            Err("error")
+        }
+    };
+    let json = match result {
+        Ok(verified) => serde_json::json!({ "bundle": verified, "error": null }),
+        Err(code) => serde_json::json!({ "bundle": null, "error": code }),
+    };
+    env.new_string(json.to_string()).expect("Couldn't create Java string").into_raw()
+}
+
+// Shared by previewCsvImport and importCsv. Returns {"report": ..., "error": null} or
+// {"report": null, "error": code}.
+fn run_csv_import(env: &mut JNIEnv, csv: &JString, mapping_json: &JString, dry_run: bool) -> jstring {
+    let csv_str: String = env.get_string(csv).expect("Invalid CSV text").into();
+    let mapping_str: String = env.get_string(mapping_json).expect("Invalid mapping JSON").into();
+    let mapping = if mapping_str.trim().is_empty() {
+        Ok(import::ColumnMapping::default())
+    } else {
+        serde_json::from_str::<import::ColumnMapping>(&mapping_str)
+    };
+    let result = match mapping {
+        Ok(mapping) => {
+            let db_conn_guard = DB_CONNECTION.lock().unwrap();
+            if let Some(conn) = &*db_conn_guard {
+                let report = if dry_run {
+                    import::preview(conn, &csv_str, &mapping, &chrono::Local)
+                } else {
+                    import::import(conn, &csv_str, &mapping, &chrono::Local)
+                };
+                report.map_err(|e| {
+                    error!("CSV import failed: {}", e);
+                    e.code()
+                })
+            } else {
+                error!("Database connection not initialized.");
+                Err("error")
+            }
+        }
+        Err(e) => {
+            error!("Failed to deserialize column mapping: {}", e);
+            Err("invalid_mapping")
+        }
+    };
+    let json = match result {
+        Ok(report) => serde_json::json!({ "report": report, "error": null }),
+        Err(code) => serde_json::json!({ "report": null, "error": code }),
+    };
+    env.new_string(json.to_string()).expect("Couldn't create Java string").into_raw()
+}
+
+// Parses a CSV export with the given column mapping and reports what importing it would
+// do, row by row, without changing anything.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_previewCsvImport(
+    mut env: JNIEnv,
+    _class: JClass,
+    csv: JString,
+    mapping_json: JString,
+) -> jstring {
+    run_csv_import(&mut env, &csv, &mapping_json, true)
+}
+
+// Imports the ready rows of a CSV export into the active profile.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_importCsv(
+    mut env: JNIEnv,
+    _class: JClass,
+    csv: JString,
+    mapping_json: JString,
+) -> jstring {
+    run_csv_import(&mut env, &csv, &mapping_json, false)
+}
+
+// --- Sync ---
+//
+// The app does the HTTP requests described in SYNC_PROTOCOL.md; these functions produce
+// and consume their bodies. A sync round is: getSyncPush, POST it, ackSyncPush with its
+// through_seq, repeat while records remain; then GET pull and applySyncPull, repeating
+// while the response says `more`.
+
+// Points the active profile at a sync server and space. Returns false on failure.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_configureSync(
+    mut env: JNIEnv,
+    _class: JClass,
+    server_url: JString,
+    space: JString,
+) -> jboolean {
+    let server_url_str: String = env.get_string(&server_url).expect("Invalid server URL").into();
+    let space_str: String = env.get_string(&space).expect("Invalid space name").into();
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        match sync::configure(conn, &server_url_str, &space_str) {
+            Ok(()) => JNI_TRUE,
+            Err(e) => {
+                error!("Failed to configure sync: {}", e);
+                JNI_FALSE
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        JNI_FALSE
+    }
+}
+
+// Returns the device id, server, space, pull cursor and number of unpushed changes.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getSyncConfig(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match sync::get_config(conn) {
+            Ok(config) => {
+                let json_string = serde_json::to_string(&config).unwrap_or_default();
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to get sync config: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// Returns the body of the next push request.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_getSyncPush(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    if let Some(conn) = &*db_conn_guard {
+        match sync::pending_push(conn) {
+            Ok(push) => {
+                let json_string = serde_json::to_string(&push).unwrap_or_default();
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to collect sync changes: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
+
+// Marks a push as accepted by the server.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_ackSyncPush(
+    _env: JNIEnv,
+    _class: JClass,
+    through_seq: jlong,
+) -> jboolean {
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        match sync::acknowledge_push(conn, through_seq) {
+            Ok(()) => JNI_TRUE,
+            Err(e) => {
+                error!("Failed to acknowledge sync push: {}", e);
+                JNI_FALSE
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        JNI_FALSE
+    }
+}
+
+// Applies a pull response body. Returns counts of applied and skipped records with the new
+// cursor, or "" if the response couldn't be applied.
+#[unsafe(no_mangle)]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_example_blastemst_RustBridge_applySyncPull(
+    mut env: JNIEnv,
+    _class: JClass,
+    response_json: JString,
+) -> jstring {
+    let response_str: String = env.get_string(&response_json).expect("Invalid pull response").into();
+    let default_json = env.new_string("").expect("Couldn't create default JSON string.");
+    let response: sync::PullResponse = match serde_json::from_str(&response_str) {
+        Ok(response) => response,
+        Err(e) => {
+            error!("Failed to deserialize pull response: {}", e);
+            return default_json.into_raw();
+        }
+    };
+    let db_conn_guard = DB_CONNECTION.lock().unwrap();
+    if let Some(conn) = &*db_conn_guard {
+        match sync::apply_pull(conn, &response) {
+            Ok(result) => {
+                let json_string = serde_json::to_string(&result).unwrap_or_default();
+                env.new_string(json_string).unwrap_or(default_json).into_raw()
+            }
+            Err(e) => {
+                error!("Failed to apply pull response: {}", e);
+                default_json.into_raw()
+            }
+        }
+    } else {
+        error!("Database connection not initialized.");
+        default_json.into_raw()
+    }
+}
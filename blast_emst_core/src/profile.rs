@@ -0,0 +1,385 @@
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+
+use crate::db::UserProfile;
+use crate::devices;
+
+// --- Clinical fields of the user profile ---
+
+// Conditions EMST is commonly prescribed for. Stored in the database using the same
+// snake_case names as in JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Indication {
+    Parkinsons,
+    Dysphagia,
+    Stroke,
+    MultipleSclerosis,
+    Als,
+    HeadAndNeckCancer,
+    Copd,
+    ChronicCough,
+    VoiceDisorder,
+    Other,
+}
+
+impl Indication {
+    pub const ALL: [Indication; 10] = [
+        Indication::Parkinsons,
+        Indication::Dysphagia,
+        Indication::Stroke,
+        Indication::MultipleSclerosis,
+        Indication::Als,
+        Indication::HeadAndNeckCancer,
+        Indication::Copd,
+        Indication::ChronicCough,
+        Indication::VoiceDisorder,
+        Indication::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Indication::Parkinsons => "parkinsons",
+            Indication::Dysphagia => "dysphagia",
+            Indication::Stroke => "stroke",
+            Indication::MultipleSclerosis => "multiple_sclerosis",
+            Indication::Als => "als",
+            Indication::HeadAndNeckCancer => "head_and_neck_cancer",
+            Indication::Copd => "copd",
+            Indication::ChronicCough => "chronic_cough",
+            Indication::VoiceDisorder => "voice_disorder",
+            Indication::Other => "other",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Indication> {
+        Indication::ALL.into_iter().find(|indication| indication.as_str() == value)
+    }
+}
+
+// Dates travel as "YYYY-MM-DD". The app sends an empty string for a date the user hasn't
+// filled in, which is treated the same as null.
+pub fn deserialize_optional_date<'de, D>(deserializer: D) -> Result<Option<NaiveDate>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(s) => parse_date(s)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid date '{}', expected YYYY-MM-DD", s))),
+    }
+}
+
+pub fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+}
+
+// A profile as the app's form sends it. Dates and the diagnosis stay text until
+// validate_profile parses them, so a malformed value is reported against its own field
+// instead of failing the whole request. Any id is ignored.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct ProfileInput {
+    pub first_name: String,
+    pub last_name: String,
+    // "YYYY-MM-DD", or empty
+    pub dob: Option<String>,
+    pub speech_therapist: String,
+    pub therapist_phone: String,
+    pub therapist_email: String,
+    // One of the Indication names, or empty
+    pub diagnosis: Option<String>,
+    pub diagnosis_notes: String,
+    pub trainer_device: String,
+    pub baseline_mep: Option<f64>,
+    pub treatment_start_date: Option<String>,
+}
+
+// None for a missing or blank value.
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|value| !value.is_empty())
+}
+
+// --- Validation ---
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum ProfileError {
+    Invalid(Vec<FieldError>),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProfileError::Invalid(errors) => {
+                let fields: Vec<&str> = errors.iter().map(|e| e.field).collect();
+                write!(f, "invalid profile fields: {}", fields.join(", "))
+            }
+            ProfileError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ProfileError {
+    fn from(e: rusqlite::Error) -> Self {
+        ProfileError::Database(e)
+    }
+}
+
+impl ProfileError {
+    // The list of field errors handed back to the app. Database failures aren't tied to a
+    // field, so they are reported against the profile as a whole.
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        match self {
+            ProfileError::Invalid(errors) => errors.clone(),
+            ProfileError::Database(e) => vec![FieldError { field: "profile", message: e.to_string() }],
+        }
+    }
+}
+
+const MAX_NAME_LENGTH: usize = 100;
+const EARLIEST_BIRTH_YEAR: i32 = 1900;
+// Healthy adults rarely exceed 200 cmH2O; anything past this is almost certainly a typo.
+const MAX_BASELINE_MEP_CMH2O: f64 = 300.0;
+
+// Parses an optional "YYYY-MM-DD" field, with the message to show when it isn't one.
+fn parse_field_date(value: &Option<String>) -> Result<Option<NaiveDate>, String> {
+    match non_empty(value) {
+        None => Ok(None),
+        Some(value) => parse_date(value).map(Some).ok_or_else(|| format!("Enter the date as YYYY-MM-DD, not '{}'", value)),
+    }
+}
+
+// Checks every field and returns all problems at once so the form can highlight them
+// together. A valid input comes back as the profile to store, without an id.
+pub fn validate_profile(input: &ProfileInput, today: NaiveDate) -> Result<UserProfile, ProfileError> {
+    let mut errors = Vec::new();
+    let mut fail = |field: &'static str, message: String| errors.push(FieldError { field, message });
+
+    for (field, value) in [
+        ("first_name", &input.first_name),
+        ("last_name", &input.last_name),
+        ("speech_therapist", &input.speech_therapist),
+    ] {
+        if value.chars().count() > MAX_NAME_LENGTH {
+            fail(field, format!("Must be at most {} characters", MAX_NAME_LENGTH));
+        }
+    }
+
+    let dob = parse_field_date(&input.dob).unwrap_or_else(|message| {
+        fail("dob", message);
+        None
+    });
+    if let Some(dob) = dob {
+        if dob > today {
+            fail("dob", "Date of birth cannot be in the future".to_string());
+        } else if dob.year() < EARLIEST_BIRTH_YEAR {
+            fail("dob", format!("Date of birth must be after {}", EARLIEST_BIRTH_YEAR));
+        }
+    }
+
+    let treatment_start_date = parse_field_date(&input.treatment_start_date).unwrap_or_else(|message| {
+        fail("treatment_start_date", message);
+        None
+    });
+    if let Some(start) = treatment_start_date
+        && let Some(dob) = dob
+        && start < dob
+    {
+        fail("treatment_start_date", "Treatment cannot start before the date of birth".to_string());
+    }
+
+    let diagnosis = non_empty(&input.diagnosis).and_then(|value| {
+        let diagnosis = Indication::parse(value);
+        if diagnosis.is_none() {
+            fail("diagnosis", format!("Unknown diagnosis '{}'", value));
+        }
+        diagnosis
+    });
+
+    if let Some(mep) = input.baseline_mep {
+        if !mep.is_finite() || mep <= 0.0 {
+            fail("baseline_mep", "Baseline MEP must be a positive number".to_string());
+        } else if mep > MAX_BASELINE_MEP_CMH2O {
+            fail("baseline_mep", format!("Baseline MEP must be at most {} cmH2O", MAX_BASELINE_MEP_CMH2O));
+        }
+    }
+
+    if !input.trainer_device.is_empty() && devices::find_device(&input.trainer_device).is_none() {
+        fail("trainer_device", "Choose a trainer from the device catalog".to_string());
+    }
+
+    if !input.therapist_phone.is_empty() && !is_valid_phone(&input.therapist_phone) {
+        fail("therapist_phone", "Enter a phone number using digits, spaces and + - ( )".to_string());
+    }
+
+    if !input.therapist_email.is_empty() && !is_valid_email(&input.therapist_email) {
+        fail("therapist_email", "Enter a valid email address".to_string());
+    }
+
+    if !errors.is_empty() {
+        return Err(ProfileError::Invalid(errors));
+    }
+    Ok(UserProfile {
+        id: 0,
+        first_name: input.first_name.clone(),
+        last_name: input.last_name.clone(),
+        dob,
+        speech_therapist: input.speech_therapist.clone(),
+        therapist_phone: input.therapist_phone.clone(),
+        therapist_email: input.therapist_email.clone(),
+        diagnosis,
+        diagnosis_notes: input.diagnosis_notes.clone(),
+        trainer_device: input.trainer_device.clone(),
+        baseline_mep: input.baseline_mep,
+        treatment_start_date,
+        dob_legacy: None,
+    })
+}
+
+fn is_valid_phone(phone: &str) -> bool {
+    let allowed = phone.chars().all(|c| c.is_ascii_digit() || " +-().".contains(c));
+    let digits = phone.chars().filter(|c| c.is_ascii_digit()).count();
+    allowed && (6..=15).contains(&digits)
+}
+
+// Deliberately loose: one '@', something on either side and a dot in the domain.
+fn is_valid_email(email: &str) -> bool {
+    if email.chars().any(char::is_whitespace) {
+        return false;
+    }
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.split('.').count() >= 2
+                && domain.split('.').all(|part| !part.is_empty())
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, 1).unwrap()
+    }
+
+    fn invalid_fields(input: &ProfileInput) -> Vec<&'static str> {
+        match validate_profile(input, today()) {
+            Ok(_) => Vec::new(),
+            Err(e) => e.field_errors().into_iter().map(|error| error.field).collect(),
+        }
+    }
+
+    fn text(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn accepts_an_empty_profile() {
+        assert!(validate_profile(&ProfileInput::default(), today()).is_ok());
+    }
+
+    #[test]
+    fn parses_dates_and_the_diagnosis() {
+        let input = ProfileInput {
+            dob: text("1950-05-10"),
+            treatment_start_date: text(" "),
+            diagnosis: text("parkinsons"),
+            ..Default::default()
+        };
+        let profile = validate_profile(&input, today()).unwrap();
+        assert_eq!(profile.dob, NaiveDate::from_ymd_opt(1950, 5, 10));
+        assert_eq!(profile.treatment_start_date, None);
+        assert_eq!(profile.diagnosis, Some(Indication::Parkinsons));
+    }
+
+    #[test]
+    fn unreadable_values_are_reported_against_their_field() {
+        let input = ProfileInput {
+            dob: text("10/05/1950"),
+            treatment_start_date: text("soon"),
+            diagnosis: text("flu"),
+            ..Default::default()
+        };
+        assert_eq!(invalid_fields(&input), vec!["dob", "treatment_start_date", "diagnosis"]);
+        let errors = validate_profile(&input, today()).unwrap_err().field_errors();
+        assert_eq!(errors[0].message, "Enter the date as YYYY-MM-DD, not '10/05/1950'");
+    }
+
+    #[test]
+    fn reports_every_bad_field_at_once() {
+        let input = ProfileInput {
+            first_name: "x".repeat(MAX_NAME_LENGTH + 1),
+            dob: text("2024-06-02"),
+            baseline_mep: Some(0.0),
+            trainer_device: "not_a_trainer".to_string(),
+            therapist_phone: "call me".to_string(),
+            therapist_email: "therapist@clinic".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            invalid_fields(&input),
+            vec!["first_name", "dob", "baseline_mep", "trainer_device", "therapist_phone", "therapist_email"]
+        );
+    }
+
+    #[test]
+    fn checks_field_limits_at_their_edges() {
+        let at_limits = ProfileInput {
+            first_name: "x".repeat(MAX_NAME_LENGTH),
+            dob: text("1900-01-01"),
+            treatment_start_date: text("1900-01-01"),
+            baseline_mep: Some(MAX_BASELINE_MEP_CMH2O),
+            therapist_phone: "123456".to_string(),
+            therapist_email: "a@b.c".to_string(),
+            ..Default::default()
+        };
+        assert!(invalid_fields(&at_limits).is_empty());
+
+        let past_limits = ProfileInput {
+            dob: text("1899-12-31"),
+            baseline_mep: Some(MAX_BASELINE_MEP_CMH2O + 0.1),
+            therapist_phone: "1234567890123456".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(invalid_fields(&past_limits), vec!["dob", "baseline_mep", "therapist_phone"]);
+        assert_eq!(invalid_fields(&ProfileInput { baseline_mep: Some(f64::NAN), ..Default::default() }), vec!["baseline_mep"]);
+    }
+
+    #[test]
+    fn treatment_cannot_start_before_birth() {
+        let input = ProfileInput {
+            dob: text("1950-05-10"),
+            treatment_start_date: text("1950-05-09"),
+            ..Default::default()
+        };
+        assert_eq!(invalid_fields(&input), vec!["treatment_start_date"]);
+    }
+
+    #[test]
+    fn empty_dates_deserialize_as_none() {
+        #[derive(Deserialize)]
+        struct Dates {
+            #[serde(deserialize_with = "deserialize_optional_date")]
+            dob: Option<NaiveDate>,
+        }
+        let parsed: Dates = serde_json::from_str(r#"{"dob": " "}"#).unwrap();
+        assert_eq!(parsed.dob, None);
+        let parsed: Dates = serde_json::from_str(r#"{"dob": "1950-05-10"}"#).unwrap();
+        assert_eq!(parsed.dob, NaiveDate::from_ymd_opt(1950, 5, 10));
+        assert!(serde_json::from_str::<Dates>(r#"{"dob": "10/05/1950"}"#).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+
+use crate::stats;
+use crate::units::{DisplayPressure, Pressure, PressureUnit};
+
+// --- Pressure progression ---
+
+// Weeks in a row at the same top pressure before training is considered to have stalled
+pub const PLATEAU_WEEKS: usize = 3;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PressurePoint {
+    pub session_id: i64,
+    pub date: DateTime<Utc>,
+    pub pressure_setting: i32,
+    pub pressure: DisplayPressure,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WeeklyPressure {
+    pub week_start: NaiveDate,
+    pub max_pressure: i32,
+    pub pressure: DisplayPressure,
+    pub sessions: u32,
+}
+
+// A run of weeks with training but no increase in the weekly top pressure
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Plateau {
+    pub from_week: NaiveDate,
+    pub to_week: NaiveDate,
+    pub weeks: usize,
+    pub pressure_setting: i32,
+}
+
+// A week whose top pressure dropped below the previous training week's
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Regression {
+    pub week_start: NaiveDate,
+    pub from_pressure: i32,
+    pub to_pressure: i32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PressureTrend {
+    pub unit: PressureUnit,
+    pub sessions: Vec<PressurePoint>,
+    pub weekly: Vec<WeeklyPressure>,
+    pub first_pressure: Option<i32>,
+    pub latest_pressure: Option<i32>,
+    // Change in the trainer setting since the first session
+    pub percent_change: Option<f64>,
+    // The MEP measured by the therapist and entered in the profile, in cmH2O
+    pub baseline_mep: Option<f64>,
+    // The latest trainer setting as a share of the baseline MEP
+    pub percent_of_mep: Option<f64>,
+    pub plateaus: Vec<Plateau>,
+    pub regressions: Vec<Regression>,
+    // True when the most recent weeks form a plateau that is still going on
+    pub currently_plateaued: bool,
+}
+
+fn display(pressure_setting: i32, unit: PressureUnit) -> DisplayPressure {
+    DisplayPressure::new(Pressure::from_cmh2o(pressure_setting as f64), unit)
+}
+
+// Analyzes completed sessions given as (session id, end time, pressure in cmH2O), oldest
+// first, along with the profile's `baseline_mep` if it has one. Weeks are grouped in
+// the timezone `tz`; weeks without training are skipped rather than breaking a plateau.
+pub fn analyze<Tz: TimeZone>(
+    history: &[(i64, DateTime<Utc>, i32)],
+    baseline_mep: Option<f64>,
+    tz: &Tz,
+    unit: PressureUnit,
+) -> PressureTrend {
+    let sessions: Vec<PressurePoint> = history
+        .iter()
+        .map(|&(session_id, date, pressure_setting)| PressurePoint {
+            session_id,
+            date,
+            pressure_setting,
+            pressure: display(pressure_setting, unit),
+        })
+        .collect();
+
+    let mut weekly: Vec<WeeklyPressure> = Vec::new();
+    for point in &sessions {
+        let week_start = stats::week_start(point.date.with_timezone(tz).date_naive());
+        match weekly.iter_mut().find(|week| week.week_start == week_start) {
+            Some(week) => {
+                week.max_pressure = week.max_pressure.max(point.pressure_setting);
+                week.sessions += 1;
+            }
+            None => weekly.push(WeeklyPressure {
+                week_start,
+                max_pressure: point.pressure_setting,
+                pressure: display(point.pressure_setting, unit),
+                sessions: 1,
+            }),
+        }
+    }
+    weekly.sort_by_key(|week| week.week_start);
+    for week in &mut weekly {
+        week.pressure = display(week.max_pressure, unit);
+    }
+
+    let regressions = weekly
+        .windows(2)
+        .filter(|pair| pair[1].max_pressure < pair[0].max_pressure)
+        .map(|pair| Regression {
+            week_start: pair[1].week_start,
+            from_pressure: pair[0].max_pressure,
+            to_pressure: pair[1].max_pressure,
+        })
+        .collect();
+
+    let mut plateaus = Vec::new();
+    let mut run_start = 0;
+    for i in 1..=weekly.len() {
+        let run_continues = i < weekly.len() && weekly[i].max_pressure == weekly[run_start].max_pressure;
+        if !run_continues {
+            let weeks = i - run_start;
+            if weeks >= PLATEAU_WEEKS {
+                plateaus.push(Plateau {
+                    from_week: weekly[run_start].week_start,
+                    to_week: weekly[i - 1].week_start,
+                    weeks,
+                    pressure_setting: weekly[run_start].max_pressure,
+                });
+            }
+            run_start = i;
+        }
+    }
+    let currently_plateaued = plateaus
+        .last()
+        .zip(weekly.last())
+        .is_some_and(|(plateau, last)| plateau.to_week == last.week_start);
+
+    let first_pressure = sessions.first().map(|point| point.pressure_setting);
+    let latest_pressure = sessions.last().map(|point| point.pressure_setting);
+    let percent_change = match (first_pressure, latest_pressure) {
+        (Some(first), Some(latest)) if first > 0 => Some((latest - first) as f64 / first as f64 * 100.0),
+        _ => None,
+    };
+    let baseline_mep = baseline_mep.filter(|mep| *mep > 0.0);
+    let percent_of_mep = baseline_mep.zip(latest_pressure).map(|(mep, latest)| latest as f64 / mep * 100.0);
+
+    PressureTrend {
+        unit,
+        sessions,
+        weekly,
+        first_pressure,
+        latest_pressure,
+        percent_change,
+        baseline_mep,
+        percent_of_mep,
+        plateaus,
+        regressions,
+        currently_plateaued,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    // (session id, end time, pressure) with one session on the Monday of each week,
+    // starting Monday 6 May 2024
+    fn weekly_history(pressures: &[i32]) -> Vec<(i64, DateTime<Utc>, i32)> {
+        let first = Utc.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+        pressures
+            .iter()
+            .enumerate()
+            .map(|(i, pressure)| (i as i64 + 1, first + Duration::weeks(i as i64), *pressure))
+            .collect()
+    }
+
+    fn trend(pressures: &[i32], baseline_mep: Option<f64>) -> PressureTrend {
+        analyze(&weekly_history(pressures), baseline_mep, &Utc, PressureUnit::CmH2O)
+    }
+
+    #[test]
+    fn a_plateau_needs_the_full_number_of_weeks() {
+        let short = trend(&[30, 35, 35, 40], None);
+        assert!(short.plateaus.is_empty());
+        assert!(!short.currently_plateaued);
+
+        let long = trend(&[30, 35, 35, 35, 40], None);
+        assert_eq!(long.plateaus.len(), 1);
+        assert_eq!((long.plateaus[0].weeks, long.plateaus[0].pressure_setting), (PLATEAU_WEEKS, 35));
+        assert!(!long.currently_plateaued);
+
+        assert!(trend(&[30, 35, 35, 35], None).currently_plateaued);
+    }
+
+    #[test]
+    fn weeks_without_training_do_not_break_a_plateau() {
+        let mut history = weekly_history(&[35, 35, 35]);
+        history[2].1 += Duration::weeks(3);
+        let trend = analyze(&history, None, &Utc, PressureUnit::CmH2O);
+        assert_eq!(trend.weekly.len(), 3);
+        assert!(trend.currently_plateaued);
+    }
+
+    #[test]
+    fn regressions_compare_weekly_maxima() {
+        let mut history = weekly_history(&[40, 35]);
+        // A lighter session in the first week doesn't lower its maximum
+        history.insert(1, (9, history[0].1 + Duration::days(1), 30));
+        let trend = analyze(&history, None, &Utc, PressureUnit::CmH2O);
+        assert_eq!(trend.weekly.iter().map(|week| week.sessions).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(trend.regressions, vec![Regression { week_start: trend.weekly[1].week_start, from_pressure: 40, to_pressure: 35 }]);
+    }
+
+    #[test]
+    fn change_is_measured_from_the_first_session_and_the_mep_separately() {
+        let measured = trend(&[30, 60], Some(80.0));
+        assert_eq!((measured.first_pressure, measured.percent_change), (Some(30), Some(100.0)));
+        assert_eq!((measured.baseline_mep, measured.percent_of_mep), (Some(80.0), Some(75.0)));
+
+        let unmeasured = trend(&[30, 60], None);
+        assert_eq!((unmeasured.percent_change, unmeasured.percent_of_mep), (Some(100.0), None));
+        assert_eq!(trend(&[30, 60], Some(0.0)).baseline_mep, None);
+
+        let empty = trend(&[], Some(80.0));
+        assert_eq!((empty.percent_change, empty.percent_of_mep), (None, None));
+    }
+}
@@ -0,0 +1,282 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use std::fmt;
+
+// --- Outcome questionnaires ---
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Instrument {
+    // Eating Assessment Tool, for swallowing difficulty
+    Eat10,
+    // Voice Handicap Index, 10-item short form
+    Vhi10,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct AnswerOption {
+    pub value: u8,
+    pub label: &'static str,
+}
+
+// A validated instrument: every item is answered with one of `options` and the score is
+// the sum of the answers.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct InstrumentDefinition {
+    pub id: Instrument,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub items: &'static [&'static str],
+    pub options: &'static [AnswerOption],
+    pub max_score: u32,
+    // Totals at or above this are outside the normal range
+    pub abnormal_threshold: u32,
+    // How far apart two totals must be before the change counts as real rather than noise
+    pub meaningful_change: u32,
+}
+
+const EAT10_ITEMS: [&str; 10] = [
+    "My swallowing problem has caused me to lose weight.",
+    "My swallowing problem interferes with my ability to go out for meals.",
+    "Swallowing liquids takes extra effort.",
+    "Swallowing solids takes extra effort.",
+    "Swallowing pills takes extra effort.",
+    "Swallowing is painful.",
+    "The pleasure of eating is affected by my swallowing.",
+    "When I swallow food sticks in my throat.",
+    "I cough when I eat.",
+    "Swallowing is stressful.",
+];
+
+const EAT10_OPTIONS: [AnswerOption; 5] = [
+    AnswerOption { value: 0, label: "No problem" },
+    AnswerOption { value: 1, label: "Mild problem" },
+    AnswerOption { value: 2, label: "Moderate problem" },
+    AnswerOption { value: 3, label: "Marked problem" },
+    AnswerOption { value: 4, label: "Severe problem" },
+];
+
+const VHI10_ITEMS: [&str; 10] = [
+    "My voice makes it difficult for people to hear me.",
+    "People have difficulty understanding me in a noisy room.",
+    "My voice difficulties restrict personal and social life.",
+    "I feel left out of conversations because of my voice.",
+    "My voice problem causes me to lose income.",
+    "I feel as though I have to strain to produce voice.",
+    "The clarity of my voice is unpredictable.",
+    "My voice problem upsets me.",
+    "My voice makes me feel handicapped.",
+    "People ask, \"What's wrong with your voice?\"",
+];
+
+const VHI10_OPTIONS: [AnswerOption; 5] = [
+    AnswerOption { value: 0, label: "Never" },
+    AnswerOption { value: 1, label: "Almost never" },
+    AnswerOption { value: 2, label: "Sometimes" },
+    AnswerOption { value: 3, label: "Almost always" },
+    AnswerOption { value: 4, label: "Always" },
+];
+
+impl Instrument {
+    pub const ALL: [Instrument; 2] = [Instrument::Eat10, Instrument::Vhi10];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Instrument::Eat10 => "eat10",
+            Instrument::Vhi10 => "vhi10",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Instrument> {
+        Instrument::ALL.into_iter().find(|instrument| instrument.as_str() == value)
+    }
+
+    pub fn definition(&self) -> InstrumentDefinition {
+        match self {
+            Instrument::Eat10 => InstrumentDefinition {
+                id: *self,
+                name: "EAT-10",
+                description: "Eating Assessment Tool. How much of a problem is each of the following for you?",
+                items: &EAT10_ITEMS,
+                options: &EAT10_OPTIONS,
+                max_score: 40,
+                abnormal_threshold: 3,
+                meaningful_change: 3,
+            },
+            Instrument::Vhi10 => InstrumentDefinition {
+                id: *self,
+                name: "VHI-10",
+                description: "Voice Handicap Index. How often does each statement apply to you?",
+                items: &VHI10_ITEMS,
+                options: &VHI10_OPTIONS,
+                max_score: 40,
+                abnormal_threshold: 12,
+                meaningful_change: 6,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum QuestionnaireError {
+    WrongAnswerCount { expected: usize, got: usize },
+    // `item` counts from 1, as printed on the form
+    InvalidAnswer { item: usize, value: u8 },
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for QuestionnaireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QuestionnaireError::WrongAnswerCount { expected, got } => {
+                write!(f, "expected {} answers, got {}", expected, got)
+            }
+            QuestionnaireError::InvalidAnswer { item, value } => {
+                write!(f, "{} is not a valid answer for item {}", value, item)
+            }
+            QuestionnaireError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for QuestionnaireError {
+    fn from(e: rusqlite::Error) -> Self {
+        QuestionnaireError::Database(e)
+    }
+}
+
+// Checks the answers against the instrument and returns their total.
+pub fn score(instrument: Instrument, answers: &[u8]) -> Result<u32, QuestionnaireError> {
+    let definition = instrument.definition();
+    if answers.len() != definition.items.len() {
+        return Err(QuestionnaireError::WrongAnswerCount { expected: definition.items.len(), got: answers.len() });
+    }
+    for (i, value) in answers.iter().enumerate() {
+        if !definition.options.iter().any(|option| option.value == *value) {
+            return Err(QuestionnaireError::InvalidAnswer { item: i + 1, value: *value });
+        }
+    }
+    Ok(answers.iter().map(|value| *value as u32).sum())
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct QuestionnaireResponse {
+    pub id: i64,
+    pub instrument: Instrument,
+    pub completed_on: NaiveDate,
+    pub answers: Vec<u8>,
+    pub total: u32,
+    pub abnormal: bool,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TrendPoint {
+    pub completed_on: NaiveDate,
+    pub total: u32,
+}
+
+// Both instruments score higher for worse symptoms, so a negative change is an improvement.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct QuestionnaireTrend {
+    pub instrument: Instrument,
+    pub points: Vec<TrendPoint>,
+    pub baseline: Option<u32>,
+    pub latest: Option<u32>,
+    pub change: Option<i64>,
+    pub improved: bool,
+    pub worsened: bool,
+    pub latest_abnormal: bool,
+}
+
+// Builds the trend from responses ordered oldest first.
+pub fn trend(instrument: Instrument, responses: &[QuestionnaireResponse]) -> QuestionnaireTrend {
+    let definition = instrument.definition();
+    let points: Vec<TrendPoint> = responses
+        .iter()
+        .filter(|response| response.instrument == instrument)
+        .map(|response| TrendPoint { completed_on: response.completed_on, total: response.total })
+        .collect();
+    let baseline = points.first().map(|point| point.total);
+    let latest = points.last().map(|point| point.total);
+    let change = baseline.zip(latest).map(|(baseline, latest)| latest as i64 - baseline as i64);
+    let meaningful = definition.meaningful_change as i64;
+    QuestionnaireTrend {
+        instrument,
+        baseline,
+        latest,
+        change,
+        improved: change.is_some_and(|change| change <= -meaningful),
+        worsened: change.is_some_and(|change| change >= meaningful),
+        latest_abnormal: latest.is_some_and(|total| total >= definition.abnormal_threshold),
+        points,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(instrument: Instrument, day: u32, total: u32) -> QuestionnaireResponse {
+        QuestionnaireResponse {
+            id: day as i64,
+            instrument,
+            completed_on: NaiveDate::from_ymd_opt(2024, 5, day).unwrap(),
+            answers: Vec::new(),
+            total,
+            abnormal: false,
+            recorded_at: DateTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn scores_the_sum_of_valid_answers() {
+        assert_eq!(score(Instrument::Eat10, &[0; 10]).unwrap(), 0);
+        assert_eq!(score(Instrument::Vhi10, &[4; 10]).unwrap(), Instrument::Vhi10.definition().max_score);
+    }
+
+    #[test]
+    fn rejects_missing_or_out_of_range_answers() {
+        assert!(matches!(
+            score(Instrument::Eat10, &[0; 9]),
+            Err(QuestionnaireError::WrongAnswerCount { expected: 10, got: 9 })
+        ));
+        let mut answers = [1; 10];
+        answers[6] = 5;
+        assert!(matches!(
+            score(Instrument::Eat10, &answers),
+            Err(QuestionnaireError::InvalidAnswer { item: 7, value: 5 })
+        ));
+    }
+
+    #[test]
+    fn change_counts_from_exactly_the_meaningful_difference() {
+        let meaningful = Instrument::Vhi10.definition().meaningful_change;
+        let almost = trend(Instrument::Vhi10, &[response(Instrument::Vhi10, 1, 20), response(Instrument::Vhi10, 8, 20 - meaningful + 1)]);
+        assert!(!almost.improved && !almost.worsened);
+
+        let improved = trend(Instrument::Vhi10, &[response(Instrument::Vhi10, 1, 20), response(Instrument::Vhi10, 8, 20 - meaningful)]);
+        assert_eq!(improved.change, Some(-(meaningful as i64)));
+        assert!(improved.improved);
+
+        let worsened = trend(Instrument::Vhi10, &[response(Instrument::Vhi10, 1, 20), response(Instrument::Vhi10, 8, 20 + meaningful)]);
+        assert!(worsened.worsened);
+    }
+
+    #[test]
+    fn trend_only_uses_its_own_instrument() {
+        let responses = [
+            response(Instrument::Eat10, 1, 10),
+            response(Instrument::Vhi10, 2, 30),
+            response(Instrument::Eat10, 9, 2),
+        ];
+        let eat10 = trend(Instrument::Eat10, &responses);
+        assert_eq!((eat10.baseline, eat10.latest, eat10.points.len()), (Some(10), Some(2), 2));
+        // EAT-10 is abnormal from 3, so 2 is back in the normal range
+        assert!(!eat10.latest_abnormal);
+        assert!(trend(Instrument::Eat10, &responses[..1]).latest_abnormal);
+
+        let empty = trend(Instrument::Vhi10, &[]);
+        assert_eq!((empty.change, empty.improved, empty.latest_abnormal), (None, false, false));
+    }
+}
@@ -0,0 +1,445 @@
+use chrono::{DateTime, Datelike, Days, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+use crate::stats::{self, TrainingTargets};
+
+// --- Reminder decisions ---
+
+// How long after the last session the user is nudged if they are behind on their goal.
+pub const INACTIVITY_PERIOD: Duration = Duration::hours(24);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReminderReason {
+    // The weekly goal is already met (or there is no goal); check again next week
+    GoalMet,
+    // The user needs a session on every remaining day of the week and hasn't trained today
+    CatchUp,
+    // It has been a day since the last session and the goal isn't met yet
+    Inactive,
+    // Nothing is due yet; check again at `at`
+    Quiet,
+}
+
+// When `remind` is true the reminder is due now. Otherwise `at` is when the app should
+// ask again, which is never later than the moment a reminder could next become due.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ReminderDecision {
+    pub remind: bool,
+    pub at: DateTime<Utc>,
+    pub reason: ReminderReason,
+}
+
+// Decides whether to remind the user to train. `now` carries the user's timezone, which
+// decides where days and weeks (starting Monday) begin. `history` holds the end times of
+// completed sessions and only needs to cover the current week and the latest session.
+pub fn next_reminder<Tz: TimeZone>(now: &DateTime<Tz>, history: &[DateTime<Utc>], targets: &TrainingTargets) -> ReminderDecision {
+    let tz = now.timezone();
+    let today = now.date_naive();
+    let next_week_start = stats::week_start(today) + Days::new(7);
+    let now_utc = now.with_timezone(&Utc);
+
+    let adherence = stats::weekly_adherence(now, history, targets);
+    let sessions_needed = adherence.target.saturating_sub(adherence.counted);
+    let sessions_today = stats::daily_progress(now, history, targets).completed;
+    // How many sessions a day can hold, and how long to wait between them
+    let sessions_per_day = targets.per_day.max(1);
+    let inactivity_period = INACTIVITY_PERIOD / sessions_per_day as i32;
+    // Including what is left of today
+    let days_left_in_week = (next_week_start - today).num_days() as u32;
+    let capacity_left = days_left_in_week * sessions_per_day - sessions_today.min(sessions_per_day);
+    let last_session = history.iter().max().copied();
+
+    if sessions_needed == 0 {
+        return ReminderDecision {
+            remind: false,
+            at: start_of_day(&tz, next_week_start).unwrap_or(now_utc + Duration::days(7)),
+            reason: ReminderReason::GoalMet,
+        };
+    }
+
+    if sessions_today >= sessions_per_day {
+        // Today's sessions are done, so the earliest useful nudge is tomorrow
+        return ReminderDecision {
+            remind: false,
+            at: start_of_day(&tz, today + Days::new(1)).unwrap_or(now_utc + Duration::days(1)),
+            reason: ReminderReason::Quiet,
+        };
+    }
+
+    // A fresh install has nothing to be inactive since, so give the user a day to start
+    let inactivity_due = last_session.unwrap_or(now_utc) + inactivity_period;
+
+    if sessions_needed >= capacity_left {
+        // Leave a rest between sessions on the same day before nudging again
+        if sessions_today > 0 && inactivity_due > now_utc {
+            return ReminderDecision { remind: false, at: inactivity_due, reason: ReminderReason::Quiet };
+        }
+        return ReminderDecision { remind: true, at: now_utc, reason: ReminderReason::CatchUp };
+    }
+
+    if inactivity_due <= now_utc && last_session.is_some() {
+        return ReminderDecision { remind: true, at: now_utc, reason: ReminderReason::Inactive };
+    }
+
+    // Falling far enough behind turns into a catch-up reminder at the start of the day from
+    // which every remaining session slot is needed, which may come before the inactivity
+    // reminder.
+    let catch_up_day = next_week_start - Days::new(sessions_needed.div_ceil(sessions_per_day) as u64);
+    let catch_up_due = start_of_day(&tz, catch_up_day).unwrap_or(inactivity_due);
+    ReminderDecision {
+        remind: false,
+        at: inactivity_due.min(catch_up_due),
+        reason: ReminderReason::Quiet,
+    }
+}
+
+// Midnight at the start of `date` in the given timezone, or the first valid moment of the
+// day if midnight is skipped by a daylight saving change.
+fn start_of_day<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> Option<DateTime<Utc>> {
+    (0..24).find_map(|hour| {
+        tz.from_local_datetime(&date.and_hms_opt(hour, 0, 0)?)
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc))
+    })
+}
+
+// --- Reminder schedules ---
+
+// Reminds at `time` on each of `weekdays`, or every day when `weekdays` is empty.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReminderRule {
+    pub time: NaiveTime,
+    #[serde(default)]
+    pub weekdays: Vec<Weekday>,
+}
+
+// A daily window with no reminders. The window wraps past midnight when `end` is earlier
+// than `start`, e.g. 22:00 to 07:00.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+// The user's preferred training times, stored per profile.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct ReminderSchedule {
+    pub rules: Vec<ReminderRule>,
+    pub rest_days: Vec<Weekday>,
+    pub quiet_hours: Option<QuietHours>,
+}
+
+// Profile setting holding the schedule as JSON
+pub const REMINDER_SCHEDULE_KEY: &str = "reminder_schedule";
+
+// The first stretch of days upcoming_reminders looks at, which covers every weekly rule
+// even when today's reminders have passed.
+const SCHEDULE_HORIZON_DAYS: u64 = 8;
+
+impl ReminderSchedule {
+    fn is_rest_day(&self, date: NaiveDate) -> bool {
+        self.rest_days.contains(&date.weekday())
+    }
+
+    fn is_quiet(&self, time: NaiveTime) -> bool {
+        self.quiet_hours.is_some_and(|quiet| quiet.contains(time))
+    }
+
+    // The next `count` reminder instants strictly after `now`, skipping rest days and
+    // anything inside quiet hours. The offset in `now` is used for the whole projection,
+    // so the app should ask again after a daylight saving change.
+    pub fn upcoming_reminders<Tz: TimeZone>(&self, now: &DateTime<Tz>, count: usize) -> Vec<DateTime<Utc>> {
+        // The first window reaches today's weekday next week; after that the rules repeat,
+        // so a week without a single reminder means there are no more to find.
+        let mut instants = self.reminders_on_days(now, 0..SCHEDULE_HORIZON_DAYS);
+        let mut start = SCHEDULE_HORIZON_DAYS;
+        while !instants.is_empty() && instants.len() < count {
+            let week = self.reminders_on_days(now, start..start + 7);
+            if week.is_empty() {
+                break;
+            }
+            instants.extend(week);
+            start += 7;
+        }
+        instants.truncate(count);
+        instants
+    }
+
+    // Every reminder instant strictly after `now` on the `days` days starting with today,
+    // in order, with the same rules and caveats as upcoming_reminders.
+    pub fn reminders_within<Tz: TimeZone>(&self, now: &DateTime<Tz>, days: u64) -> Vec<DateTime<Utc>> {
+        self.reminders_on_days(now, 0..days)
+    }
+
+    // Reminder instants strictly after `now` on the days `offsets` away from today, in order.
+    fn reminders_on_days<Tz: TimeZone>(&self, now: &DateTime<Tz>, offsets: Range<u64>) -> Vec<DateTime<Utc>> {
+        let tz = &now.timezone();
+        let now_utc = now.with_timezone(&Utc);
+        let mut instants: Vec<DateTime<Utc>> = offsets
+            .map(|offset| now.date_naive() + Days::new(offset))
+            .filter(|date| !self.is_rest_day(*date))
+            .flat_map(|date| {
+                self.rules
+                    .iter()
+                    .filter(move |rule| rule.weekdays.is_empty() || rule.weekdays.contains(&date.weekday()))
+                    .filter(|rule| !self.is_quiet(rule.time))
+                    .filter_map(move |rule| local_instant(tz, date, rule.time))
+            })
+            .filter(|instant| *instant > now_utc)
+            .collect();
+        instants.sort();
+        instants.dedup();
+        instants
+    }
+
+    // The earliest instant at or after `at` that isn't on a rest day or in quiet hours.
+    // Falls back to `at` if the schedule rules out every day.
+    pub fn next_allowed<Tz: TimeZone>(&self, at: &DateTime<Tz>) -> DateTime<Utc> {
+        let tz = at.timezone();
+        let mut candidate = at.with_timezone(&Utc);
+        for _ in 0..SCHEDULE_HORIZON_DAYS * 2 {
+            let local = candidate.with_timezone(&tz);
+            let (date, time) = (local.date_naive(), local.time());
+            let next = if self.is_rest_day(date) {
+                start_of_day(&tz, date + Days::new(1))
+            } else if let Some(quiet) = self.quiet_hours.filter(|quiet| quiet.contains(time)) {
+                let end_date = if quiet.start > quiet.end && time >= quiet.start { date + Days::new(1) } else { date };
+                local_instant(&tz, end_date, quiet.end)
+            } else {
+                return candidate;
+            };
+            match next {
+                Some(next) if next > candidate => candidate = next,
+                _ => break,
+            }
+        }
+        at.with_timezone(&Utc)
+    }
+}
+
+// Moves a due or upcoming reminder out of quiet hours and rest days. A reminder that was
+// due now but falls in a quiet period becomes a check at the end of that period.
+pub fn apply_schedule<Tz: TimeZone>(decision: ReminderDecision, schedule: &ReminderSchedule, tz: &Tz) -> ReminderDecision {
+    let allowed = schedule.next_allowed(&decision.at.with_timezone(tz));
+    if allowed == decision.at {
+        return decision;
+    }
+    ReminderDecision { remind: false, at: allowed, reason: ReminderReason::Quiet }
+}
+
+fn local_instant<Tz: TimeZone>(tz: &Tz, date: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&date.and_time(time))
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    // Wednesday 15 May 2024, 10:00 in UTC+2
+    fn wednesday_morning() -> DateTime<FixedOffset> {
+        FixedOffset::east_opt(2 * 3600).unwrap().with_ymd_and_hms(2024, 5, 15, 10, 0, 0).unwrap()
+    }
+
+    fn weekly(per_week: u32) -> TrainingTargets {
+        TrainingTargets { per_day: 0, per_week }
+    }
+
+    fn local(day: u32, hour: u32) -> DateTime<Utc> {
+        FixedOffset::east_opt(2 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, 5, day, hour, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn goal_met_waits_for_next_week() {
+        let history = [local(13, 9), local(14, 9), local(15, 8)];
+        let decision = next_reminder(&wednesday_morning(), &history, &weekly(3));
+        assert!(!decision.remind);
+        assert_eq!(decision.reason, ReminderReason::GoalMet);
+        // Monday 20 May, local midnight
+        assert_eq!(decision.at, local(20, 0));
+    }
+
+    #[test]
+    fn zero_goal_never_reminds() {
+        let decision = next_reminder(&wednesday_morning(), &[], &weekly(0));
+        assert!(!decision.remind);
+        assert_eq!(decision.reason, ReminderReason::GoalMet);
+    }
+
+    #[test]
+    fn sessions_from_last_week_do_not_count() {
+        // Sunday 12 May is the previous week
+        let history = [local(11, 9), local(12, 9)];
+        let decision = next_reminder(&wednesday_morning(), &history, &weekly(2));
+        assert_ne!(decision.reason, ReminderReason::GoalMet);
+    }
+
+    #[test]
+    fn end_of_week_catch_up_reminds_immediately() {
+        // Five sessions still needed with Wednesday to Sunday left
+        let history = [local(12, 9)];
+        let decision = next_reminder(&wednesday_morning(), &history, &weekly(5));
+        assert!(decision.remind);
+        assert_eq!(decision.reason, ReminderReason::CatchUp);
+        assert_eq!(decision.at, wednesday_morning().with_timezone(&Utc));
+    }
+
+    #[test]
+    fn catch_up_is_quiet_once_trained_today() {
+        let history = [local(15, 8)];
+        let decision = next_reminder(&wednesday_morning(), &history, &weekly(6));
+        assert!(!decision.remind);
+        assert_eq!(decision.reason, ReminderReason::Quiet);
+        assert_eq!(decision.at, local(16, 0));
+    }
+
+    #[test]
+    fn inactivity_reminds_after_a_day() {
+        let history = [local(13, 9), local(14, 9)];
+        let decision = next_reminder(&wednesday_morning(), &history, &weekly(4));
+        assert!(decision.remind);
+        assert_eq!(decision.reason, ReminderReason::Inactive);
+    }
+
+    #[test]
+    fn quiet_until_a_day_after_the_last_session() {
+        let history = [local(14, 20)];
+        let decision = next_reminder(&wednesday_morning(), &history, &weekly(3));
+        assert!(!decision.remind);
+        assert_eq!(decision.reason, ReminderReason::Quiet);
+        assert_eq!(decision.at, local(15, 20));
+    }
+
+    #[test]
+    fn quiet_check_comes_early_when_catch_up_day_is_sooner() {
+        // Four sessions needed from Wednesday: Thursday is the last day that leaves room for
+        // all of them, which comes before a fresh install's one-day grace period ends.
+        let decision = next_reminder(&wednesday_morning(), &[], &weekly(4));
+        assert!(!decision.remind);
+        assert_eq!(decision.reason, ReminderReason::Quiet);
+        assert_eq!(decision.at, local(16, 0));
+    }
+
+    #[test]
+    fn fresh_install_waits_a_day() {
+        let decision = next_reminder(&wednesday_morning(), &[], &weekly(3));
+        assert!(!decision.remind);
+        assert_eq!(decision.reason, ReminderReason::Quiet);
+        assert_eq!(decision.at, wednesday_morning().with_timezone(&Utc) + INACTIVITY_PERIOD);
+    }
+
+    fn schedule() -> ReminderSchedule {
+        ReminderSchedule {
+            rules: vec![
+                ReminderRule { time: NaiveTime::from_hms_opt(8, 0, 0).unwrap(), weekdays: vec![] },
+                ReminderRule { time: NaiveTime::from_hms_opt(19, 0, 0).unwrap(), weekdays: vec![Weekday::Wed, Weekday::Thu] },
+            ],
+            rest_days: vec![Weekday::Fri],
+            quiet_hours: Some(QuietHours {
+                start: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(7, 30, 0).unwrap(),
+            }),
+        }
+    }
+
+    #[test]
+    fn upcoming_reminders_follow_rules_and_rest_days() {
+        let upcoming = schedule().upcoming_reminders(&wednesday_morning(), 5);
+        // Wednesday 8:00 has passed; Friday is a rest day
+        assert_eq!(upcoming, vec![local(15, 19), local(16, 8), local(16, 19), local(18, 8), local(19, 8)]);
+    }
+
+    #[test]
+    fn upcoming_reminders_look_past_the_first_week() {
+        let mut mondays = schedule();
+        mondays.rules = vec![ReminderRule { time: NaiveTime::from_hms_opt(8, 0, 0).unwrap(), weekdays: vec![Weekday::Mon] }];
+        let upcoming = mondays.upcoming_reminders(&wednesday_morning(), 6);
+        assert_eq!(upcoming.len(), 6);
+        assert_eq!(upcoming[0], local(20, 8));
+        assert_eq!(upcoming[5], local(20, 8) + Duration::weeks(5));
+
+        // Twenty reminders from a twice-weekly rule run well past the first eight days
+        let upcoming = schedule().upcoming_reminders(&wednesday_morning(), 20);
+        assert_eq!(upcoming.len(), 20);
+        assert!(upcoming.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn upcoming_reminders_stop_when_every_day_is_ruled_out() {
+        let mut schedule = schedule();
+        schedule.rules = vec![ReminderRule { time: NaiveTime::from_hms_opt(8, 0, 0).unwrap(), weekdays: vec![Weekday::Fri] }];
+        assert!(schedule.upcoming_reminders(&wednesday_morning(), 3).is_empty());
+        assert!(ReminderSchedule::default().upcoming_reminders(&wednesday_morning(), 3).is_empty());
+    }
+
+    #[test]
+    fn upcoming_reminders_skip_quiet_hours() {
+        let mut schedule = schedule();
+        schedule.rules = vec![ReminderRule { time: NaiveTime::from_hms_opt(6, 0, 0).unwrap(), weekdays: vec![] }];
+        assert!(schedule.upcoming_reminders(&wednesday_morning(), 3).is_empty());
+    }
+
+    #[test]
+    fn quiet_hours_defer_a_due_reminder() {
+        let late_evening = FixedOffset::east_opt(2 * 3600).unwrap().with_ymd_and_hms(2024, 5, 15, 23, 0, 0).unwrap();
+        let decision = ReminderDecision { remind: true, at: late_evening.with_timezone(&Utc), reason: ReminderReason::Inactive };
+        let deferred = apply_schedule(decision, &schedule(), late_evening.offset());
+        assert!(!deferred.remind);
+        assert_eq!(deferred.at, local(16, 7) + Duration::minutes(30));
+    }
+
+    #[test]
+    fn rest_days_defer_to_the_next_morning() {
+        let friday = FixedOffset::east_opt(2 * 3600).unwrap().with_ymd_and_hms(2024, 5, 17, 12, 0, 0).unwrap();
+        // Midnight Saturday is still in quiet hours, so the check lands at the end of them
+        assert_eq!(schedule().next_allowed(&friday), local(18, 7) + Duration::minutes(30));
+    }
+
+    #[test]
+    fn allowed_reminders_are_left_alone() {
+        let decision = next_reminder(&wednesday_morning(), &[local(13, 9)], &weekly(5));
+        assert_eq!(apply_schedule(decision.clone(), &schedule(), wednesday_morning().offset()), decision);
+    }
+
+    #[test]
+    fn twice_daily_waits_between_sessions_then_reminds() {
+        let targets = TrainingTargets { per_day: 2, per_week: 14 };
+        // One session this morning; the second is due twelve hours later
+        let history = [local(13, 8), local(13, 20), local(14, 8), local(14, 20), local(15, 8)];
+        let decision = next_reminder(&wednesday_morning(), &history, &targets);
+        assert!(!decision.remind);
+        assert_eq!(decision.at, local(15, 20));
+
+        let evening = FixedOffset::east_opt(2 * 3600).unwrap().with_ymd_and_hms(2024, 5, 15, 20, 30, 0).unwrap();
+        let decision = next_reminder(&evening, &history, &targets);
+        assert!(decision.remind);
+    }
+
+    #[test]
+    fn daily_target_caps_what_counts_toward_the_week() {
+        let targets = TrainingTargets { per_day: 1, per_week: 3 };
+        // Three sessions on Monday only count once
+        let history = [local(13, 8), local(13, 12), local(13, 18)];
+        let decision = next_reminder(&wednesday_morning(), &history, &targets);
+        assert_ne!(decision.reason, ReminderReason::GoalMet);
+    }
+}
@@ -0,0 +1,227 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+// --- Session quality scoring ---
+
+// Profile settings making up the prescription, alongside "default_reps"
+pub const REPS_PER_SET_KEY: &str = "reps_per_set";
+pub const REP_REST_SECONDS_KEY: &str = "rep_rest_seconds";
+pub const SET_REST_SECONDS_KEY: &str = "set_rest_seconds";
+
+// What a session is scored against. The defaults follow the usual EMST protocol of
+// 25 breaths in 5 sets of 5.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Prescription {
+    pub reps: u32,
+    pub reps_per_set: u32,
+    // Minimum rest between breaths within a set
+    pub rep_rest_seconds: u32,
+    // Minimum rest between sets
+    pub set_rest_seconds: u32,
+}
+
+impl Default for Prescription {
+    fn default() -> Self {
+        Prescription { reps: 25, reps_per_set: 5, rep_rest_seconds: 15, set_rest_seconds: 60 }
+    }
+}
+
+impl Prescription {
+    pub fn sets(&self) -> u32 {
+        self.reps.div_ceil(self.reps_per_set.max(1))
+    }
+
+    // The shortest session that respects every rest period
+    pub fn minimum_duration_seconds(&self) -> u32 {
+        let sets = self.sets();
+        self.reps.saturating_sub(sets) * self.rep_rest_seconds + sets.saturating_sub(1) * self.set_rest_seconds
+    }
+
+    // A pause this long or longer between two breaths starts a new set. Halfway between the
+    // two rest periods keeps a slightly long breather from splitting a set.
+    pub fn set_gap_seconds(&self) -> u32 {
+        (self.rep_rest_seconds + self.set_rest_seconds) / 2
+    }
+}
+
+// Each component is 0-100; `total` is their weighted sum.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionScore {
+    pub total: u32,
+    pub reps_score: u32,
+    pub sets_score: u32,
+    pub rest_score: u32,
+    pub duration_score: u32,
+    pub reps_completed: u32,
+    pub sets_completed: u32,
+}
+
+const REPS_WEIGHT: f64 = 0.40;
+const SETS_WEIGHT: f64 = 0.20;
+const REST_WEIGHT: f64 = 0.25;
+const DURATION_WEIGHT: f64 = 0.15;
+// Sessions up to this many times the minimum duration still get full marks; past that the
+// user was probably interrupted.
+const DURATION_SLACK: f64 = 3.0;
+
+fn percent(ratio: f64) -> u32 {
+    (ratio.clamp(0.0, 1.0) * 100.0).round() as u32
+}
+
+// Splits rep timestamps (oldest first) into sets wherever the pause reaches the set gap.
+pub fn split_into_sets(rep_times: &[DateTime<Utc>], prescription: &Prescription) -> Vec<Vec<DateTime<Utc>>> {
+    let gap = prescription.set_gap_seconds() as i64;
+    let mut sets: Vec<Vec<DateTime<Utc>>> = Vec::new();
+    for (i, time) in rep_times.iter().enumerate() {
+        let new_set = i == 0 || (*time - rep_times[i - 1]).num_seconds() >= gap;
+        match sets.last_mut() {
+            Some(set) if !new_set => set.push(*time),
+            _ => sets.push(vec![*time]),
+        }
+    }
+    sets
+}
+
+// Scores a finished session from its start and end time and the time each rep was logged.
+pub fn score_session(
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    rep_times: &[DateTime<Utc>],
+    prescription: &Prescription,
+) -> SessionScore {
+    let reps_completed = rep_times.len() as u32;
+    let sets = split_into_sets(rep_times, prescription);
+    let sets_completed = sets
+        .iter()
+        .filter(|set| set.len() as u32 >= prescription.reps_per_set)
+        .count() as u32;
+
+    let reps_score = percent(reps_completed as f64 / prescription.reps.max(1) as f64);
+    let sets_score = percent(sets_completed as f64 / prescription.sets().max(1) as f64);
+
+    // Every pause within a set should be at least the rep rest; pauses between sets are
+    // long by definition.
+    let pauses: Vec<i64> = sets
+        .iter()
+        .flat_map(|set| set.windows(2).map(|pair| (pair[1] - pair[0]).num_seconds()))
+        .collect();
+    let rest_score = if pauses.is_empty() {
+        if reps_completed > 0 { 100 } else { 0 }
+    } else {
+        let rested = pauses.iter().filter(|pause| **pause >= prescription.rep_rest_seconds as i64).count();
+        percent(rested as f64 / pauses.len() as f64)
+    };
+
+    let actual = (end_time - start_time).num_seconds().max(0) as f64;
+    let expected = prescription.minimum_duration_seconds() as f64;
+    let duration_score = if reps_completed == 0 {
+        0
+    } else if expected == 0.0 {
+        100
+    } else if actual > expected * DURATION_SLACK {
+        percent(expected * DURATION_SLACK / actual)
+    } else {
+        percent(actual / expected)
+    };
+
+    let total = (reps_score as f64 * REPS_WEIGHT
+        + sets_score as f64 * SETS_WEIGHT
+        + rest_score as f64 * REST_WEIGHT
+        + duration_score as f64 * DURATION_WEIGHT)
+        .round() as u32;
+
+    SessionScore {
+        total,
+        reps_score,
+        sets_score,
+        rest_score,
+        duration_score,
+        reps_completed,
+        sets_completed,
+    }
+}
+
+// Averages over every scored session of a profile
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct ScoreSummary {
+    pub scored_sessions: u32,
+    pub average_total: Option<f64>,
+    pub average_reps_score: Option<f64>,
+    pub average_sets_score: Option<f64>,
+    pub average_rest_score: Option<f64>,
+    pub average_duration_score: Option<f64>,
+    pub best_total: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 15, 9, 0, 0).unwrap()
+    }
+
+    // Rep times following the prescription with the given pauses within and between sets
+    fn reps(prescription: &Prescription, rep_rest: i64, set_rest: i64) -> Vec<DateTime<Utc>> {
+        let mut times = vec![start()];
+        for i in 1..prescription.reps {
+            let pause = if i % prescription.reps_per_set == 0 { set_rest } else { rep_rest };
+            times.push(*times.last().unwrap() + Duration::seconds(pause));
+        }
+        times
+    }
+
+    #[test]
+    fn a_session_following_the_prescription_scores_full_marks() {
+        let prescription = Prescription::default();
+        assert_eq!(prescription.minimum_duration_seconds(), 20 * 15 + 4 * 60);
+        let times = reps(&prescription, 15, 60);
+        let score = score_session(start(), *times.last().unwrap(), &times, &prescription);
+        assert_eq!((score.total, score.reps_completed, score.sets_completed), (100, 25, 5));
+    }
+
+    #[test]
+    fn a_pause_of_exactly_the_set_gap_starts_a_new_set() {
+        let prescription = Prescription::default();
+        let gap = prescription.set_gap_seconds() as i64;
+        assert_eq!(split_into_sets(&reps(&prescription, 15, gap), &prescription).len(), 5);
+        assert_eq!(split_into_sets(&reps(&prescription, 15, gap - 1), &prescription).len(), 1);
+    }
+
+    #[test]
+    fn rest_counts_from_exactly_the_prescribed_pause() {
+        let prescription = Prescription::default();
+        let times = reps(&prescription, 15, 60);
+        assert_eq!(score_session(start(), *times.last().unwrap(), &times, &prescription).rest_score, 100);
+        let rushed = reps(&prescription, 14, 60);
+        assert_eq!(score_session(start(), *rushed.last().unwrap(), &rushed, &prescription).rest_score, 0);
+    }
+
+    #[test]
+    fn duration_allows_slack_before_losing_marks() {
+        let prescription = Prescription::default();
+        let times = reps(&prescription, 15, 60);
+        let expected = prescription.minimum_duration_seconds() as i64;
+        let slack = (expected as f64 * DURATION_SLACK) as i64;
+        let score_at = |seconds: i64| score_session(start(), start() + Duration::seconds(seconds), &times, &prescription).duration_score;
+        assert_eq!(score_at(expected), 100);
+        assert_eq!(score_at(slack), 100);
+        assert_eq!(score_at(slack * 2), 50);
+        assert_eq!(score_at(expected / 2), 50);
+    }
+
+    #[test]
+    fn an_empty_session_scores_zero() {
+        let score = score_session(start(), start() + Duration::minutes(5), &[], &Prescription::default());
+        assert_eq!(score, SessionScore { total: 0, reps_score: 0, sets_score: 0, rest_score: 0, duration_score: 0, reps_completed: 0, sets_completed: 0 });
+    }
+
+    #[test]
+    fn degenerate_prescriptions_do_not_divide_by_zero() {
+        let prescription = Prescription { reps: 0, reps_per_set: 0, rep_rest_seconds: 0, set_rest_seconds: 0 };
+        assert_eq!(prescription.sets(), 0);
+        let score = score_session(start(), start(), &[start()], &prescription);
+        assert_eq!((score.reps_score, score.duration_score), (100, 100));
+    }
+}
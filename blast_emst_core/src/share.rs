@@ -0,0 +1,422 @@
+use chacha20poly1305::aead::OsRng;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+use crate::db::{self, Session, UserProfile};
+use crate::stats::TrainingTargets;
+use crate::units::{DisplayPressure, Pressure, PressureUnit};
+
+// --- Clinician share bundles ---
+//
+// A share bundle carries a date range of a patient's sessions to their clinician, signed
+// so the clinician's tool can tell it came unmodified from the patient's app. Layout:
+//
+//   magic "EMSTSHR\0" | format version (u8) | ed25519 public key (32) | signature (64)
+//   | contents (JSON)
+//
+// The signature covers everything except itself. Each profile has its own signing key,
+// created on first export; the clinician confirms the key once by comparing its
+// fingerprint with the one shown in the patient's app, and can then pin it.
+//
+// Signing keys never leave the device they were created on. They live in their own table,
+// which backups and snapshots leave out, so a copy of the database restored elsewhere
+// can't sign as the patient. After a backup is restored each profile gets a new key on its
+// next export, and the clinician confirms the new fingerprint.
+
+const MAGIC: &[u8; 8] = b"EMSTSHR\0";
+pub const FORMAT_VERSION: u8 = 1;
+const KEY_LEN: usize = 32;
+const SIGNATURE_LEN: usize = 64;
+const HEADER_LEN: usize = MAGIC.len() + 1 + KEY_LEN + SIGNATURE_LEN;
+
+// One hex-encoded ed25519 secret key per profile
+pub const KEY_TABLE: &str = "share_keys";
+
+#[derive(Debug)]
+pub enum ShareError {
+    NotAShareBundle,
+    UnsupportedVersion(u8),
+    // The contents or signature were altered after signing
+    BadSignature,
+    // Correctly signed, but not by the key the clinician expected
+    UntrustedKey,
+    InvalidRange,
+    Format(serde_json::Error),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for ShareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShareError::NotAShareBundle => write!(f, "not a share bundle"),
+            ShareError::UnsupportedVersion(v) => write!(f, "unsupported share bundle version {}", v),
+            ShareError::BadSignature => write!(f, "the signature does not match the contents"),
+            ShareError::UntrustedKey => write!(f, "signed by an unexpected key"),
+            ShareError::InvalidRange => write!(f, "the start of the range is after its end"),
+            ShareError::Format(e) => write!(f, "invalid share bundle contents: {}", e),
+            ShareError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ShareError {
+    fn from(e: rusqlite::Error) -> Self {
+        ShareError::Database(e)
+    }
+}
+
+impl From<serde_json::Error> for ShareError {
+    fn from(e: serde_json::Error) -> Self {
+        ShareError::Format(e)
+    }
+}
+
+impl ShareError {
+    // Stable identifier handed to the app
+    pub fn code(&self) -> &'static str {
+        match self {
+            ShareError::NotAShareBundle => "not_a_share_bundle",
+            ShareError::UnsupportedVersion(_) => "unsupported_version",
+            ShareError::BadSignature => "bad_signature",
+            ShareError::Format(_) => "corrupted",
+            ShareError::UntrustedKey => "untrusted_key",
+            ShareError::InvalidRange => "invalid_range",
+            ShareError::Database(_) => "error",
+        }
+    }
+}
+
+// Version of the JSON layout inside the bundle
+pub const CONTENTS_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShareContents {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    // Inclusive, in the patient's local dates
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub profile: UserProfile,
+    // The patient's session goals, so adherence can be judged against them
+    #[serde(default)]
+    pub targets: TrainingTargets,
+    // Completed sessions in the range, newest first
+    pub sessions: Vec<Session>,
+    pub stats: ShareStats,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct ShareStats {
+    pub completed_sessions: u32,
+    pub total_reps: i64,
+    // Days with at least one completed session
+    pub training_days: u32,
+    pub sessions_per_week: f64,
+    // Pressures in cmH2O
+    pub first_pressure: Option<i32>,
+    pub latest_pressure: Option<i32>,
+    pub max_pressure: Option<i32>,
+    pub average_score: Option<f64>,
+    // The patient's display unit, and the pressures above in it for reports. Bundles from
+    // before units existed have neither and read as cmH2O.
+    #[serde(default)]
+    pub pressure_unit: PressureUnit,
+    #[serde(default)]
+    pub first_pressure_display: Option<DisplayPressure>,
+    #[serde(default)]
+    pub latest_pressure_display: Option<DisplayPressure>,
+    #[serde(default)]
+    pub max_pressure_display: Option<DisplayPressure>,
+}
+
+fn local_date(at: DateTime<Utc>) -> NaiveDate {
+    at.with_timezone(&Local).date_naive()
+}
+
+pub fn summarize(sessions: &[Session], from: NaiveDate, to: NaiveDate, unit: PressureUnit) -> ShareStats {
+    let mut days: Vec<NaiveDate> = sessions.iter().map(|session| local_date(session.start_time)).collect();
+    days.dedup();
+    let weeks = ((to - from).num_days() + 1) as f64 / 7.0;
+    let scores: Vec<f64> = sessions.iter().filter_map(|s| s.score.as_ref()).map(|score| score.total as f64).collect();
+    let display = |cmh2o: Option<i32>| cmh2o.map(|cmh2o| DisplayPressure::new(Pressure::from_cmh2o(cmh2o as f64), unit));
+    let first_pressure = sessions.last().map(|session| session.pressure_setting);
+    let latest_pressure = sessions.first().map(|session| session.pressure_setting);
+    let max_pressure = sessions.iter().map(|session| session.pressure_setting).max();
+    // Sessions are newest first
+    ShareStats {
+        completed_sessions: sessions.len() as u32,
+        total_reps: sessions.iter().map(|session| session.rep_count).sum(),
+        training_days: days.len() as u32,
+        sessions_per_week: if weeks > 0.0 { sessions.len() as f64 / weeks } else { 0.0 },
+        first_pressure,
+        latest_pressure,
+        max_pressure,
+        average_score: (!scores.is_empty()).then(|| scores.iter().sum::<f64>() / scores.len() as f64),
+        pressure_unit: unit,
+        first_pressure_display: display(first_pressure),
+        latest_pressure_display: display(latest_pressure),
+        max_pressure_display: display(max_pressure),
+    }
+}
+
+// --- Signing keys ---
+
+// Creates the key table. Called from migrate.
+pub fn prepare_schema(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                profile_id INTEGER PRIMARY KEY,
+                secret TEXT NOT NULL
+            )",
+            KEY_TABLE
+        ),
+        [],
+    )?;
+    Ok(())
+}
+
+// Removes every signing key from a database. Used on snapshot copies and after restoring a
+// backup.
+pub fn forget_keys(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(&format!("DELETE FROM {}", KEY_TABLE), [])?;
+    Ok(())
+}
+
+// All signing keys on this device, for putting back with restore_keys after the database
+// has been replaced.
+pub fn saved_keys(conn: &Connection) -> rusqlite::Result<Vec<(i64, String)>> {
+    conn.prepare(&format!("SELECT profile_id, secret FROM {}", KEY_TABLE))?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect()
+}
+
+// Puts back keys taken with saved_keys, for the profiles that still exist.
+pub fn restore_keys(conn: &Connection, keys: &[(i64, String)]) -> rusqlite::Result<()> {
+    forget_keys(conn)?;
+    for (profile_id, secret) in keys {
+        conn.execute(
+            &format!(
+                "INSERT INTO {} (profile_id, secret)
+                 SELECT id, ?2 FROM user_profile WHERE id = ?1",
+                KEY_TABLE
+            ),
+            params![profile_id, secret],
+        )?;
+    }
+    Ok(())
+}
+
+// The active profile's signing key, created the first time it is needed.
+fn signing_key(conn: &Connection) -> rusqlite::Result<SigningKey> {
+    let profile_id = db::active_profile_id(conn)?;
+    let stored: Option<String> = conn
+        .query_row(
+            &format!("SELECT secret FROM {} WHERE profile_id = ?1", KEY_TABLE),
+            [profile_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(secret) = stored.and_then(|hex| decode_hex::<KEY_LEN>(&hex)) {
+        return Ok(SigningKey::from_bytes(&secret));
+    }
+    let mut secret = [0u8; KEY_LEN];
+    OsRng.fill_bytes(&mut secret);
+    conn.execute(
+        &format!("INSERT OR REPLACE INTO {} (profile_id, secret) VALUES (?1, ?2)", KEY_TABLE),
+        params![profile_id, encode_hex(&secret)],
+    )?;
+    Ok(SigningKey::from_bytes(&secret))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != N * 2 || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+// Short, readable digest of a public key for comparing over the phone: the first 16 bytes
+// of its SHA-256 as eight groups of four hex digits.
+pub fn fingerprint(public_key: &VerifyingKey) -> String {
+    let digest = Sha256::digest(public_key.as_bytes());
+    digest[..16].chunks(2).map(encode_hex).collect::<Vec<_>>().join(" ")
+}
+
+pub fn key_fingerprint(conn: &Connection) -> rusqlite::Result<String> {
+    Ok(fingerprint(&signing_key(conn)?.verifying_key()))
+}
+
+// Signs `contents` into a complete bundle.
+pub fn seal(contents: &[u8], key: &SigningKey) -> Vec<u8> {
+    let public_key = key.verifying_key();
+    let mut signed = Vec::with_capacity(MAGIC.len() + 1 + KEY_LEN + contents.len());
+    signed.extend_from_slice(MAGIC);
+    signed.push(FORMAT_VERSION);
+    signed.extend_from_slice(public_key.as_bytes());
+    signed.extend_from_slice(contents);
+    let signature = key.sign(&signed);
+
+    let mut bundle = Vec::with_capacity(HEADER_LEN + contents.len());
+    bundle.extend_from_slice(&signed[..MAGIC.len() + 1 + KEY_LEN]);
+    bundle.extend_from_slice(&signature.to_bytes());
+    bundle.extend_from_slice(contents);
+    bundle
+}
+
+pub fn export_share_bundle(conn: &Connection, from: NaiveDate, to: NaiveDate) -> Result<Vec<u8>, ShareError> {
+    if from > to {
+        return Err(ShareError::InvalidRange);
+    }
+    let sessions: Vec<Session> = db::get_all_sessions(conn)?
+        .into_iter()
+        .filter(|session| session.end_time.is_some())
+        .filter(|session| (from..=to).contains(&local_date(session.start_time)))
+        .collect();
+    let contents = ShareContents {
+        version: CONTENTS_VERSION,
+        created_at: Utc::now(),
+        from,
+        to,
+        profile: db::get_profile(conn)?,
+        targets: db::get_training_targets(conn)?,
+        stats: summarize(&sessions, from, to, db::get_pressure_unit(conn)?),
+        sessions,
+    };
+    Ok(seal(&serde_json::to_vec(&contents)?, &signing_key(conn)?))
+}
+
+// --- Verification ---
+
+#[derive(Serialize, Debug)]
+pub struct VerifiedShare {
+    pub public_key: String,
+    pub fingerprint: String,
+    pub contents: ShareContents,
+}
+
+// Checks a bundle's signature and returns its contents. With `expected_fingerprint`, the
+// bundle must also be signed by that key; without it, any correctly signed bundle passes
+// and the caller should show the fingerprint for the clinician to confirm.
+pub fn verify(bundle: &[u8], expected_fingerprint: Option<&str>) -> Result<VerifiedShare, ShareError> {
+    if bundle.len() < MAGIC.len() + 1 || &bundle[..MAGIC.len()] != MAGIC {
+        return Err(ShareError::NotAShareBundle);
+    }
+    let version = bundle[MAGIC.len()];
+    if version != FORMAT_VERSION {
+        return Err(ShareError::UnsupportedVersion(version));
+    }
+    if bundle.len() < HEADER_LEN {
+        return Err(ShareError::BadSignature);
+    }
+    let key_start = MAGIC.len() + 1;
+    let key_bytes: [u8; KEY_LEN] = bundle[key_start..key_start + KEY_LEN].try_into().map_err(|_| ShareError::BadSignature)?;
+    let signature_bytes: [u8; SIGNATURE_LEN] =
+        bundle[key_start + KEY_LEN..HEADER_LEN].try_into().map_err(|_| ShareError::BadSignature)?;
+    let public_key = VerifyingKey::from_bytes(&key_bytes).map_err(|_| ShareError::BadSignature)?;
+    let contents = &bundle[HEADER_LEN..];
+
+    let mut signed = Vec::with_capacity(key_start + KEY_LEN + contents.len());
+    signed.extend_from_slice(&bundle[..key_start + KEY_LEN]);
+    signed.extend_from_slice(contents);
+    public_key
+        .verify_strict(&signed, &Signature::from_bytes(&signature_bytes))
+        .map_err(|_| ShareError::BadSignature)?;
+
+    let fingerprint = fingerprint(&public_key);
+    if let Some(expected) = expected_fingerprint {
+        let normalize = |f: &str| f.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_ascii_lowercase();
+        if normalize(expected) != normalize(&fingerprint) {
+            return Err(ShareError::UntrustedKey);
+        }
+    }
+    Ok(VerifiedShare {
+        public_key: encode_hex(public_key.as_bytes()),
+        fingerprint,
+        contents: serde_json::from_slice(contents)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_bundle() -> (Vec<u8>, String) {
+        let conn = db::initialize_database(":memory:").unwrap();
+        db::set_setting(&conn, crate::units::PRESSURE_UNIT_KEY, PressureUnit::MmHg.as_str()).unwrap();
+        let session = db::start_new_session(&conn, 30, "").unwrap();
+        db::add_rep(&conn, session).unwrap();
+        db::end_session(&conn, session, "", &Local::now()).unwrap();
+        let today = Local::now().date_naive();
+        let bundle = export_share_bundle(&conn, today - chrono::Duration::days(6), today).unwrap();
+        (bundle, key_fingerprint(&conn).unwrap())
+    }
+
+    #[test]
+    fn verifies_an_untouched_bundle() {
+        let (bundle, fingerprint) = sample_bundle();
+        let verified = verify(&bundle, Some(&fingerprint)).unwrap();
+        assert_eq!(verified.fingerprint, fingerprint);
+        assert_eq!(verified.contents.sessions.len(), 1);
+        assert_eq!(verified.contents.stats.total_reps, 1);
+        assert_eq!(verified.contents.stats.sessions_per_week, 1.0);
+        assert_eq!(verified.contents.stats.pressure_unit, PressureUnit::MmHg);
+        assert_eq!(verified.contents.stats.latest_pressure_display.as_ref().unwrap().text, "22.1 mmHg");
+    }
+
+    #[test]
+    fn rejects_altered_contents() {
+        let (mut bundle, _) = sample_bundle();
+        let position = bundle.windows(4).position(|w| w == b"\"to\"").unwrap();
+        bundle[position + 7] ^= 1;
+        assert!(matches!(verify(&bundle, None), Err(ShareError::BadSignature)));
+    }
+
+    #[test]
+    fn rejects_a_resigned_bundle_under_a_pinned_key() {
+        let (bundle, fingerprint) = sample_bundle();
+        let forger = SigningKey::from_bytes(&[7u8; KEY_LEN]);
+        let forged = seal(&bundle[HEADER_LEN..], &forger);
+        assert!(verify(&forged, None).is_ok());
+        assert!(matches!(verify(&forged, Some(&fingerprint)), Err(ShareError::UntrustedKey)));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(matches!(verify(b"EMSTBAK\0\x01", None), Err(ShareError::NotAShareBundle)));
+        assert!(matches!(verify(b"EMSTSHR\0\x09", None), Err(ShareError::UnsupportedVersion(9))));
+    }
+
+    #[test]
+    fn signing_key_is_neither_a_setting_nor_backed_up() {
+        let conn = db::initialize_database(":memory:").unwrap();
+        let fingerprint = key_fingerprint(&conn).unwrap();
+        assert_eq!(key_fingerprint(&conn).unwrap(), fingerprint);
+        let in_settings: i64 = conn
+            .query_row(
+                &format!("SELECT COUNT(*) FROM profile_settings WHERE value IN (SELECT secret FROM {})", KEY_TABLE),
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(in_settings, 0);
+
+        let contents = crate::backup::dump(&conn).unwrap();
+        assert!(!contents.tables.contains_key(KEY_TABLE));
+        crate::backup::restore(&conn, &contents).unwrap();
+        assert_ne!(key_fingerprint(&conn).unwrap(), fingerprint);
+    }
+}
@@ -0,0 +1,472 @@
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::db;
+use crate::encryption::{self, DatabaseKey, EncryptionError};
+use crate::share;
+use crate::sync;
+
+// --- Rolling database snapshots ---
+//
+// Whole-database copies taken with SQLite's online backup API into a directory next to the
+// database, so a corrupted file or a wrong deletion can be rolled back without the user
+// having made a backup. Copies of an encrypted database are encrypted with the same key.
+// Only the newest few are kept. Restoring first snapshots the current state, so a restore
+// can itself be undone.
+
+// Device-wide settings, since a snapshot covers every profile
+pub const SCHEDULE_KEY: &str = "snapshot_schedule";
+pub const KEEP_KEY: &str = "snapshot_keep";
+pub const DEFAULT_KEEP: u32 = 5;
+pub const MAX_KEEP: u32 = 50;
+
+const FILE_PREFIX: &str = "snapshot-";
+const FILE_SUFFIX: &str = ".db";
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%3fZ";
+const PAGES_PER_STEP: i32 = 256;
+// How old the newest snapshot may get on the daily schedule
+const DAILY_INTERVAL: Duration = Duration::hours(24);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Schedule {
+    #[default]
+    AfterSession,
+    // At most once a day, checked when the app opens the database and when a session ends
+    Daily,
+    Off,
+}
+
+impl Schedule {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Schedule::AfterSession => "after_session",
+            Schedule::Daily => "daily",
+            Schedule::Off => "off",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Schedule> {
+        match value {
+            "after_session" => Some(Schedule::AfterSession),
+            "daily" => Some(Schedule::Daily),
+            "off" => Some(Schedule::Off),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct SnapshotSettings {
+    pub schedule: Schedule,
+    pub keep: u32,
+}
+
+impl Default for SnapshotSettings {
+    fn default() -> Self {
+        SnapshotSettings { schedule: Schedule::default(), keep: DEFAULT_KEEP }
+    }
+}
+
+pub fn get_settings(conn: &Connection) -> rusqlite::Result<SnapshotSettings> {
+    let schedule = db::get_app_setting(conn, SCHEDULE_KEY)?.and_then(|value| Schedule::parse(&value)).unwrap_or_default();
+    let keep = db::get_app_setting(conn, KEEP_KEY)?
+        .and_then(|value| value.parse::<u32>().ok())
+        .filter(|keep| (1..=MAX_KEEP).contains(keep))
+        .unwrap_or(DEFAULT_KEEP);
+    Ok(SnapshotSettings { schedule, keep })
+}
+
+pub fn set_settings(conn: &Connection, settings: &SnapshotSettings) -> rusqlite::Result<()> {
+    db::set_app_setting(conn, SCHEDULE_KEY, settings.schedule.as_str())?;
+    db::set_app_setting(conn, KEEP_KEY, &settings.keep.clamp(1, MAX_KEEP).to_string())
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    Session,
+    Daily,
+    Manual,
+    // The state just before a snapshot was restored
+    PreRestore,
+}
+
+impl Reason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Reason::Session => "session",
+            Reason::Daily => "daily",
+            Reason::Manual => "manual",
+            Reason::PreRestore => "pre_restore",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Reason> {
+        match value {
+            "session" => Some(Reason::Session),
+            "daily" => Some(Reason::Daily),
+            "manual" => Some(Reason::Manual),
+            "pre_restore" => Some(Reason::PreRestore),
+            _ => None,
+        }
+    }
+}
+
+// What just happened, for deciding whether the schedule calls for a snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Opened,
+    SessionEnded,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    // The file name, used to pick a snapshot to restore
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub reason: Reason,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    NotFound,
+    Encryption(EncryptionError),
+    Io(io::Error),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::NotFound => write!(f, "no such snapshot"),
+            SnapshotError::Encryption(e) => write!(f, "{}", e),
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<EncryptionError> for SnapshotError {
+    fn from(e: EncryptionError) -> Self {
+        SnapshotError::Encryption(e)
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for SnapshotError {
+    fn from(e: rusqlite::Error) -> Self {
+        SnapshotError::Database(e)
+    }
+}
+
+impl SnapshotError {
+    // Stable identifier handed to the app
+    pub fn code(&self) -> &'static str {
+        match self {
+            SnapshotError::NotFound => "not_found",
+            SnapshotError::Encryption(EncryptionError::WrongKey) => "wrong_key",
+            _ => "error",
+        }
+    }
+}
+
+// Where snapshots of the database at `db_path` are kept. In-memory databases have none.
+pub fn directory_for(db_path: &str) -> Option<PathBuf> {
+    if db_path.is_empty() || db_path == ":memory:" {
+        return None;
+    }
+    Some(Path::new(db_path).parent().unwrap_or(Path::new("")).join("snapshots"))
+}
+
+fn parse_id(id: &str) -> Option<(DateTime<Utc>, Reason)> {
+    let stem = id.strip_prefix(FILE_PREFIX)?.strip_suffix(FILE_SUFFIX)?;
+    let (timestamp, reason) = stem.split_once('-')?;
+    let created_at = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?.and_utc();
+    Some((created_at, Reason::parse(reason)?))
+}
+
+pub struct SnapshotStore {
+    dir: PathBuf,
+    // Set when the live database is encrypted
+    key: Option<DatabaseKey>,
+}
+
+impl SnapshotStore {
+    pub fn new(dir: PathBuf, key: Option<DatabaseKey>) -> Self {
+        SnapshotStore { dir, key }
+    }
+
+    fn open(&self, path: &Path, flags: OpenFlags) -> Result<Connection, SnapshotError> {
+        let conn = Connection::open_with_flags(path, flags)?;
+        if let Some(key) = &self.key {
+            encryption::apply_key(&conn, key)?;
+        }
+        Ok(conn)
+    }
+
+    // Newest first. Files that don't look like snapshots are ignored.
+    pub fn list(&self) -> io::Result<Vec<Snapshot>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut snapshots = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let id = entry.file_name().to_string_lossy().into_owned();
+            if let Some((created_at, reason)) = parse_id(&id) {
+                snapshots.push(Snapshot { id, created_at, reason, size_bytes: entry.metadata()?.len() });
+            }
+        }
+        snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.created_at));
+        Ok(snapshots)
+    }
+
+    // Copies the database into a new snapshot. The copy is written under a temporary name
+    // and renamed once complete, so an interrupted snapshot never shows up in the list.
+    fn copy(&self, conn: &Connection, reason: Reason) -> Result<Snapshot, SnapshotError> {
+        fs::create_dir_all(&self.dir)?;
+        // Names only go down to the millisecond, so two snapshots in quick succession are
+        // spaced out to keep both
+        let mut created_at = Utc::now();
+        let newest = self.list()?.first().map(|snapshot| snapshot.created_at);
+        if let Some(newest) = newest.filter(|newest| *newest + Duration::milliseconds(1) > created_at) {
+            created_at = newest + Duration::milliseconds(1);
+        }
+        let id = format!("{}{}-{}{}", FILE_PREFIX, created_at.format(TIMESTAMP_FORMAT), reason.as_str(), FILE_SUFFIX);
+        let path = self.dir.join(&id);
+        let partial = self.dir.join(format!("{}.partial", id));
+        if partial.exists() {
+            fs::remove_file(&partial)?;
+        }
+        {
+            let mut copy = self.open(&partial, OpenFlags::default())?;
+            Backup::new(conn, &mut copy)?.run_to_completion(PAGES_PER_STEP, std::time::Duration::ZERO, None)?;
+            // Signing keys stay with the live database; restore puts them back
+            share::forget_keys(&copy)?;
+        }
+        fs::rename(&partial, &path)?;
+        let size_bytes = fs::metadata(&path)?.len();
+        let created_at = parse_id(&id).map_or(created_at, |(at, _)| at);
+        Ok(Snapshot { id, created_at, reason, size_bytes })
+    }
+
+    // Brings existing snapshots in line with the store's key once the live database is
+    // encrypted: plaintext snapshots taken before are encrypted in place, and ones the key
+    // can't open are deleted since they could never be restored. Leftover partial copies go
+    // too. Returns the number of snapshots deleted; without a key there is nothing to do.
+    pub fn secure_existing(&self) -> Result<u32, SnapshotError> {
+        let Some(key) = &self.key else {
+            return Ok(0);
+        };
+        if !self.dir.exists() {
+            return Ok(0);
+        }
+        let mut deleted = 0;
+        for snapshot in self.list()? {
+            let path = self.dir.join(&snapshot.id);
+            if encryption::is_plaintext(&path)? {
+                encryption::encrypt_in_place(&path, key)?;
+                continue;
+            }
+            match self.open(&path, OpenFlags::SQLITE_OPEN_READ_ONLY) {
+                Ok(_) => {}
+                Err(SnapshotError::Encryption(EncryptionError::WrongKey)) => {
+                    fs::remove_file(&path)?;
+                    deleted += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "partial") {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(deleted)
+    }
+
+    // Deletes all but the newest `keep` snapshots.
+    fn rotate(&self, keep: u32) -> io::Result<()> {
+        for snapshot in self.list()?.into_iter().skip(keep.max(1) as usize) {
+            fs::remove_file(self.dir.join(&snapshot.id))?;
+        }
+        Ok(())
+    }
+
+    pub fn take(&self, conn: &Connection, reason: Reason) -> Result<Snapshot, SnapshotError> {
+        let snapshot = self.copy(conn, reason)?;
+        self.rotate(get_settings(conn)?.keep)?;
+        Ok(snapshot)
+    }
+
+    // Takes a snapshot if the schedule calls for one after `trigger`.
+    pub fn take_if_due(&self, conn: &Connection, trigger: Trigger) -> Result<Option<Snapshot>, SnapshotError> {
+        let reason = match (get_settings(conn)?.schedule, trigger) {
+            (Schedule::AfterSession, Trigger::SessionEnded) => Reason::Session,
+            (Schedule::Daily, _) => {
+                let latest = self.list()?.first().map(|snapshot| snapshot.created_at);
+                if latest.is_some_and(|at| Utc::now() - at < DAILY_INTERVAL) {
+                    return Ok(None);
+                }
+                Reason::Daily
+            }
+            _ => return Ok(None),
+        };
+        self.take(conn, reason).map(Some)
+    }
+
+    // Replaces the live database with the snapshot `id`, after snapshotting it as it is now.
+    // The schema is brought up to date afterwards in case the snapshot predates an upgrade.
+    // As with backups, the device keeps its sync identity and queues everything restored
+    // for push, so the restored state wins over what the server holds. Snapshot settings
+    // and share signing keys stay as they are.
+    pub fn restore(&self, conn: &mut Connection, id: &str) -> Result<(), SnapshotError> {
+        if parse_id(id).is_none() || !self.list()?.iter().any(|snapshot| snapshot.id == id) {
+            return Err(SnapshotError::NotFound);
+        }
+        let settings = get_settings(conn)?;
+        let device_id = db::get_app_setting(conn, sync::DEVICE_ID_KEY)?;
+        let keys = share::saved_keys(conn)?;
+        // Rotation waits until the restore is done so it can't remove the snapshot being restored
+        self.copy(conn, Reason::PreRestore)?;
+        {
+            let source = self.open(&self.dir.join(id), OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            Backup::new(&source, conn)?.run_to_completion(PAGES_PER_STEP, std::time::Duration::ZERO, None)?;
+        }
+        db::migrate(conn)?;
+        let tx = conn.unchecked_transaction()?;
+        sync::reset_after_restore(&tx, device_id.as_deref())?;
+        set_settings(&tx, &settings)?;
+        share::restore_keys(&tx, &keys)?;
+        tx.commit()?;
+        self.rotate(settings.keep)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> SnapshotStore {
+        SnapshotStore::new(std::env::temp_dir().join(format!("emst-snapshots-{}", sync::new_uuid())), None)
+    }
+
+    fn finished_session(conn: &Connection) -> i64 {
+        let session = db::start_new_session(conn, 30, "").unwrap();
+        db::add_rep(conn, session).unwrap();
+        db::end_session(conn, session, "", &Utc::now()).unwrap();
+        session
+    }
+
+    #[test]
+    fn schedule_decides_when_snapshots_are_taken_and_old_ones_rotate_out() {
+        let conn = db::initialize_database(":memory:").unwrap();
+        let store = temp_store();
+        set_settings(&conn, &SnapshotSettings { schedule: Schedule::AfterSession, keep: 2 }).unwrap();
+        assert!(store.take_if_due(&conn, Trigger::Opened).unwrap().is_none());
+        for _ in 0..3 {
+            assert!(store.take_if_due(&conn, Trigger::SessionEnded).unwrap().is_some());
+        }
+        let snapshots = store.list().unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert!(snapshots[0].created_at >= snapshots[1].created_at);
+        assert_eq!(snapshots[0].reason, Reason::Session);
+
+        set_settings(&conn, &SnapshotSettings { schedule: Schedule::Daily, keep: 2 }).unwrap();
+        // The newest snapshot is less than a day old
+        assert!(store.take_if_due(&conn, Trigger::Opened).unwrap().is_none());
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn restore_brings_back_deleted_sessions_and_can_be_undone() {
+        let mut conn = db::initialize_database(":memory:").unwrap();
+        let store = temp_store();
+        let session = finished_session(&conn);
+        let device_id = sync::device_id(&conn).unwrap();
+        let before = store.take(&conn, Reason::Manual).unwrap();
+
+        db::delete_session(&conn, session).unwrap();
+        set_settings(&conn, &SnapshotSettings { schedule: Schedule::Off, keep: 5 }).unwrap();
+        store.restore(&mut conn, &before.id).unwrap();
+
+        assert_eq!(db::get_all_sessions(&conn).unwrap().len(), 1);
+        assert_eq!(sync::device_id(&conn).unwrap(), device_id);
+        assert_eq!(get_settings(&conn).unwrap().schedule, Schedule::Off);
+        let reasons: Vec<Reason> = store.list().unwrap().iter().map(|snapshot| snapshot.reason).collect();
+        assert_eq!(reasons, vec![Reason::PreRestore, Reason::Manual]);
+
+        assert!(matches!(store.restore(&mut conn, "../elsewhere.db"), Err(SnapshotError::NotFound)));
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn signing_keys_stay_out_of_snapshots_but_survive_a_restore() {
+        let mut conn = db::initialize_database(":memory:").unwrap();
+        let store = temp_store();
+        let fingerprint = share::key_fingerprint(&conn).unwrap();
+        let snapshot = store.take(&conn, Reason::Manual).unwrap();
+
+        let copy = Connection::open(store.dir.join(&snapshot.id)).unwrap();
+        assert!(share::saved_keys(&copy).unwrap().is_empty());
+        drop(copy);
+
+        store.restore(&mut conn, &snapshot.id).unwrap();
+        assert_eq!(share::key_fingerprint(&conn).unwrap(), fingerprint);
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn encrypting_the_database_secures_snapshots_already_taken() {
+        let dir = std::env::temp_dir().join(format!("emst-snapshots-{}", sync::new_uuid()));
+        fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("emst.db");
+        let key = DatabaseKey::from_hex(&"a".repeat(64)).unwrap();
+        let other_key = DatabaseKey::from_hex(&"b".repeat(64)).unwrap();
+
+        let conn = db::initialize_database(db_path.to_str().unwrap()).unwrap();
+        finished_session(&conn);
+        let plaintext_store = SnapshotStore::new(dir.join("snapshots"), None);
+        let plaintext = plaintext_store.take(&conn, Reason::Manual).unwrap();
+        // Encrypted under a key the database no longer uses
+        let foreign = plaintext_store.take(&conn, Reason::Manual).unwrap();
+        encryption::encrypt_in_place(&dir.join("snapshots").join(&foreign.id), &other_key).unwrap();
+        fs::write(dir.join("snapshots").join(format!("{}.partial", plaintext.id)), b"SQLite format 3\0").unwrap();
+        drop(conn);
+
+        let mut conn = db::initialize_encrypted_database(db_path.to_str().unwrap(), &key).unwrap();
+        let store = SnapshotStore::new(dir.join("snapshots"), Some(key));
+        assert_eq!(store.secure_existing().unwrap(), 1);
+
+        let ids: Vec<String> = store.list().unwrap().into_iter().map(|snapshot| snapshot.id).collect();
+        assert_eq!(ids, vec![plaintext.id.clone()]);
+        assert!(!ids.contains(&foreign.id));
+        assert!(!encryption::is_plaintext(&store.dir.join(&plaintext.id)).unwrap());
+        assert_eq!(fs::read_dir(&store.dir).unwrap().count(), 1);
+
+        db::delete_session(&conn, db::get_all_sessions(&conn).unwrap()[0].id).unwrap();
+        store.restore(&mut conn, &plaintext.id).unwrap();
+        assert_eq!(db::get_all_sessions(&conn).unwrap().len(), 1);
+        drop(conn);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+use chrono::{DateTime, Datelike, Days, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+// --- Training targets and adherence ---
+
+// Profile settings holding the targets
+pub const GOAL_SESSIONS_PER_WEEK_KEY: &str = "goal_sessions_per_week";
+pub const GOAL_SESSIONS_PER_DAY_KEY: &str = "goal_sessions_per_day";
+pub const DEFAULT_SESSIONS_PER_WEEK: u32 = 5;
+
+// A per-day target of 0 means the program only prescribes a weekly count.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TrainingTargets {
+    pub per_day: u32,
+    pub per_week: u32,
+}
+
+impl Default for TrainingTargets {
+    fn default() -> Self {
+        TrainingTargets { per_day: 0, per_week: DEFAULT_SESSIONS_PER_WEEK }
+    }
+}
+
+impl TrainingTargets {
+    // The number of sessions that make a full week. Programs that only set a daily target
+    // expect it every day.
+    pub fn weekly_target(&self) -> u32 {
+        if self.per_week == 0 { self.per_day * 7 } else { self.per_week }
+    }
+
+    // How many of a day's sessions count toward the week. Extra sessions beyond the daily
+    // target don't make up for missed days.
+    fn counted(&self, sessions: u32) -> u32 {
+        if self.per_day == 0 { sessions } else { sessions.min(self.per_day) }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DailyProgress {
+    pub date: NaiveDate,
+    pub completed: u32,
+    pub target: u32,
+    pub target_met: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WeeklyAdherence {
+    pub week_start: NaiveDate,
+    pub completed: u32,
+    // Sessions that count toward the weekly target after applying the daily cap
+    pub counted: u32,
+    pub target: u32,
+    pub days_on_target: u32,
+    pub percent: f64,
+    pub days: Vec<DailyProgress>,
+}
+
+// Weeks start on Monday, matching strftime('%W') in the session queries.
+pub fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Days::new(date.weekday().num_days_from_monday() as u64)
+}
+
+fn sessions_on<Tz: TimeZone>(tz: &Tz, history: &[DateTime<Utc>], date: NaiveDate) -> u32 {
+    history
+        .iter()
+        .filter(|t| t.with_timezone(tz).date_naive() == date)
+        .count() as u32
+}
+
+fn progress_for(date: NaiveDate, completed: u32, targets: &TrainingTargets) -> DailyProgress {
+    DailyProgress {
+        date,
+        completed,
+        target: targets.per_day,
+        target_met: targets.per_day > 0 && completed >= targets.per_day,
+    }
+}
+
+// Progress for the day `now` falls on in its own timezone, e.g. "1 of 2 sessions today".
+pub fn daily_progress<Tz: TimeZone>(now: &DateTime<Tz>, history: &[DateTime<Utc>], targets: &TrainingTargets) -> DailyProgress {
+    let today = now.date_naive();
+    progress_for(today, sessions_on(&now.timezone(), history, today), targets)
+}
+
+// Adherence for the week containing `now`. `history` holds end times of completed sessions.
+pub fn weekly_adherence<Tz: TimeZone>(now: &DateTime<Tz>, history: &[DateTime<Utc>], targets: &TrainingTargets) -> WeeklyAdherence {
+    let tz = now.timezone();
+    let start = week_start(now.date_naive());
+    let days: Vec<DailyProgress> = (0..7)
+        .map(|offset| start + Days::new(offset))
+        .map(|date| progress_for(date, sessions_on(&tz, history, date), targets))
+        .collect();
+
+    let completed = days.iter().map(|day| day.completed).sum();
+    let counted = days.iter().map(|day| targets.counted(day.completed)).sum::<u32>();
+    let target = targets.weekly_target();
+    let percent = if target == 0 { 100.0 } else { (counted.min(target) as f64 / target as f64) * 100.0 };
+    WeeklyAdherence {
+        week_start: start,
+        completed,
+        counted,
+        target,
+        days_on_target: days.iter().filter(|day| day.target_met).count() as u32,
+        percent,
+        days,
+    }
+}
+
+// The week starting at `start` as seen from noon on its Monday, for reusing weekly_adherence.
+fn adherence_for_week<Tz: TimeZone>(tz: &Tz, start: NaiveDate, history: &[DateTime<Utc>], targets: &TrainingTargets) -> Option<WeeklyAdherence> {
+    let noon = tz.from_local_datetime(&start.and_hms_opt(12, 0, 0)?).earliest()?;
+    Some(weekly_adherence(&noon, history, targets))
+}
+
+// Adherence for the `weeks` weeks up to and including the one containing `now`, oldest first.
+pub fn recent_weeks<Tz: TimeZone>(now: &DateTime<Tz>, history: &[DateTime<Utc>], targets: &TrainingTargets, weeks: u32) -> Vec<WeeklyAdherence> {
+    let tz = now.timezone();
+    let this_week = week_start(now.date_naive());
+    (0..weeks as u64)
+        .rev()
+        .filter_map(|back| adherence_for_week(&tz, this_week - Days::new(7 * back), history, targets))
+        .collect()
+}
+
+fn on_target(adherence: &WeeklyAdherence) -> bool {
+    adherence.target > 0 && adherence.counted >= adherence.target
+}
+
+// Consecutive weeks meeting the weekly target, ending with the current week if it is
+// already on target and otherwise with the week before, so an unfinished week doesn't
+// break the streak.
+pub fn week_streak<Tz: TimeZone>(now: &DateTime<Tz>, history: &[DateTime<Utc>], targets: &TrainingTargets) -> u32 {
+    let tz = now.timezone();
+    let this_week = week_start(now.date_naive());
+    let mut streak = 0;
+    let mut week = this_week;
+    while let Some(adherence) = adherence_for_week(&tz, week, history, targets) {
+        if on_target(&adherence) {
+            streak += 1;
+        } else if week != this_week {
+            break;
+        }
+        week = week - Days::new(7);
+    }
+    streak
+}
+
+// Weeks on target since the first session in `history`, whether consecutive or not.
+pub fn weeks_on_target<Tz: TimeZone>(now: &DateTime<Tz>, history: &[DateTime<Utc>], targets: &TrainingTargets) -> u32 {
+    let tz = now.timezone();
+    let Some(first) = history.iter().min() else {
+        return 0;
+    };
+    let first_week = week_start(first.with_timezone(&tz).date_naive());
+    let mut week = week_start(now.date_naive());
+    let mut count = 0;
+    while week >= first_week {
+        if adherence_for_week(&tz, week, history, targets).is_some_and(|adherence| on_target(&adherence)) {
+            count += 1;
+        }
+        week = week - Days::new(7);
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    // Monday 3 June 2024
+    fn monday(weeks_back: u64) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, 3).unwrap() - Days::new(7 * weeks_back)
+    }
+
+    fn at(date: NaiveDate, hour: u32) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&date.and_hms_opt(hour, 0, 0).unwrap())
+    }
+
+    // `per_week` sessions on consecutive days from the Monday `weeks_back` weeks ago
+    fn full_week(weeks_back: u64, per_week: u64) -> Vec<DateTime<Utc>> {
+        (0..per_week).map(|day| at(monday(weeks_back) + Days::new(day), 9)).collect()
+    }
+
+    #[test]
+    fn unfinished_week_does_not_break_the_streak() {
+        let targets = TrainingTargets { per_day: 0, per_week: 3 };
+        let history = [full_week(1, 3), full_week(2, 3)].concat();
+        let now = at(monday(0) + Days::new(2), 12);
+        assert_eq!(week_streak(&now, &history, &targets), 2);
+
+        let history = [history, full_week(0, 3)].concat();
+        assert_eq!(week_streak(&now, &history, &targets), 3);
+    }
+
+    #[test]
+    fn a_missed_week_ends_the_streak_but_not_the_total() {
+        let targets = TrainingTargets { per_day: 0, per_week: 2 };
+        let history = [full_week(1, 2), full_week(2, 1), full_week(3, 2), full_week(4, 2)].concat();
+        let now = at(monday(0), 8);
+        assert_eq!(week_streak(&now, &history, &targets), 1);
+        assert_eq!(weeks_on_target(&now, &history, &targets), 3);
+        assert_eq!(weeks_on_target(&now, &[], &targets), 0);
+    }
+
+    #[test]
+    fn extra_sessions_do_not_make_up_for_missed_days() {
+        let targets = TrainingTargets { per_day: 1, per_week: 2 };
+        let day = monday(1);
+        let history = vec![at(day, 8), at(day, 12), at(day, 18)];
+        let week = weekly_adherence(&at(day, 20), &history, &targets);
+        assert_eq!((week.completed, week.counted, week.days_on_target), (3, 1, 1));
+        assert_eq!(week.percent, 50.0);
+        assert_eq!(week_streak(&at(monday(0), 8), &history, &targets), 0);
+    }
+
+    #[test]
+    fn weeks_follow_the_local_calendar() {
+        // 23:30 UTC on Sunday is already Monday in UTC+2, so the session counts for the new week
+        let tz = FixedOffset::east_opt(2 * 3600).unwrap();
+        let targets = TrainingTargets { per_day: 0, per_week: 1 };
+        let history = vec![at(monday(0) - Days::new(1), 23)];
+        let now = at(monday(0) + Days::new(1), 12).with_timezone(&tz);
+        assert_eq!(weekly_adherence(&now, &history, &targets).completed, 1);
+        assert_eq!(weekly_adherence(&now.with_timezone(&Utc), &history, &targets).completed, 0);
+        assert_eq!(week_streak(&now, &history, &targets), 1);
+    }
+
+    #[test]
+    fn recent_weeks_are_oldest_first() {
+        let weeks = recent_weeks(&at(monday(0) + Days::new(3), 12), &[], &TrainingTargets::default(), 3);
+        let starts: Vec<NaiveDate> = weeks.iter().map(|week| week.week_start).collect();
+        assert_eq!(starts, vec![monday(2), monday(1), monday(0)]);
+    }
+}
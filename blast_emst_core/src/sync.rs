@@ -0,0 +1,700 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::db;
+
+// --- Sync ---
+//
+// Sessions and reps carry a UUID that is the same on every device. Triggers on both tables
+// log every local change to `sync_changes`; a push sends the current state of everything
+// changed since the last acknowledged push, and a pull applies whatever the server has
+// received from other devices since the last pull. The app performs the HTTP requests; see
+// SYNC_PROTOCOL.md for the wire format.
+//
+// Conflicts are resolved record by record, last writer wins: the version with the later
+// `modified_at` is kept, and on a tie the one from the greater `device_id`. Deleting a
+// session sends a tombstone that takes part in the same rule. Reps never change after
+// they are logged.
+
+// Device-wide setting holding this install's id
+pub const DEVICE_ID_KEY: &str = "sync_device_id";
+// Profile settings
+pub const SERVER_URL_KEY: &str = "sync_server_url";
+pub const SPACE_KEY: &str = "sync_space";
+const CURSOR_KEY: &str = "sync_cursor";
+const PUSHED_SEQ_KEY: &str = "sync_pushed_seq";
+// Present in app_settings only while pulled records are applied, so the triggers don't
+// log them as local changes
+pub const APPLYING_KEY: &str = "sync_applying";
+
+// Changes sent per push; the app keeps pushing until `through_seq` stops moving
+const PUSH_BATCH: i64 = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Entity {
+    Session,
+    Rep,
+}
+
+impl Entity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Entity::Session => "session",
+            Entity::Rep => "rep",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Entity> {
+        match value {
+            "session" => Some(Entity::Session),
+            "rep" => Some(Entity::Rep),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionData {
+    pub start_time: String,
+    pub end_time: Option<String>,
+    pub pressure_setting: i32,
+    pub notes: String,
+    pub deleted_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RepData {
+    pub session_uuid: String,
+    pub rep_timestamp: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyncRecord {
+    pub entity: Entity,
+    pub uuid: String,
+    pub modified_at: DateTime<Utc>,
+    pub device_id: String,
+    #[serde(default)]
+    pub deleted: bool,
+    // SessionData or RepData; absent for tombstones
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+}
+
+// Whether `incoming` should replace `existing` under last-writer-wins.
+pub fn wins(incoming: (&DateTime<Utc>, &str), existing: (&DateTime<Utc>, &str)) -> bool {
+    incoming.0 > existing.0 || (incoming.0 == existing.0 && incoming.1 > existing.1)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PushRequest {
+    pub device_id: String,
+    pub records: Vec<SyncRecord>,
+    // The last local change included; echoed back to acknowledge_push once the server
+    // has accepted the batch. Ignored by the server.
+    pub through_seq: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PushResponse {
+    pub applied: Vec<String>,
+    // Records the server already had a newer version of; that version arrives on pull
+    pub rejected: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PullResponse {
+    pub records: Vec<SyncRecord>,
+    pub cursor: i64,
+    // More records are waiting; pull again from `cursor`
+    pub more: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct PullResult {
+    pub applied: u32,
+    // Remote versions that lost to an unpushed local change
+    pub kept_local: u32,
+    // Reps whose session isn't on this device
+    pub skipped: u32,
+    pub cursor: i64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SyncConfig {
+    pub device_id: String,
+    pub server_url: Option<String>,
+    pub space: Option<String>,
+    pub cursor: i64,
+    pub pending_changes: i64,
+}
+
+pub fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+// --- Schema ---
+
+// Gives every existing session and rep a UUID and installs the change-logging triggers.
+// Called from initialize_database after the tables exist.
+pub fn prepare_schema(conn: &Connection) -> rusqlite::Result<()> {
+    assign_uuids(conn)?;
+    for table in ["sessions", "reps"] {
+        conn.execute(
+            &format!("CREATE UNIQUE INDEX IF NOT EXISTS {0}_uuid ON {0} (uuid)", table),
+            [],
+        )?;
+    }
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sync_changes (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            profile_id INTEGER NOT NULL,
+            entity TEXT NOT NULL,
+            uuid TEXT NOT NULL,
+            changed_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    let not_applying = format!("NOT EXISTS (SELECT 1 FROM app_settings WHERE key = '{}')", APPLYING_KEY);
+    let now = "strftime('%Y-%m-%dT%H:%M:%fZ', 'now')";
+    for (name, event, row, profile, entity) in [
+        ("sync_session_insert", "INSERT ON sessions", "NEW", "NEW.profile_id", "session"),
+        ("sync_session_update", "UPDATE ON sessions", "NEW", "NEW.profile_id", "session"),
+        ("sync_session_delete", "DELETE ON sessions", "OLD", "OLD.profile_id", "session"),
+        (
+            "sync_rep_insert",
+            "INSERT ON reps",
+            "NEW",
+            "(SELECT profile_id FROM sessions WHERE id = NEW.session_id)",
+            "rep",
+        ),
+    ] {
+        // A rep restored from a backup may arrive before its session; restores queue
+        // everything afterwards instead
+        let owned = if entity == "rep" { format!("AND {} IS NOT NULL", profile) } else { String::new() };
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER IF NOT EXISTS {name} AFTER {event}
+             WHEN {row}.uuid IS NOT NULL AND {not_applying} {owned}
+             BEGIN
+                 INSERT INTO sync_changes (profile_id, entity, uuid, changed_at)
+                 VALUES ({profile}, '{entity}', {row}.uuid, {now});
+             END;"
+        ))?;
+    }
+    Ok(())
+}
+
+// Rows written before sync existed, or restored from such a backup, have no UUID yet.
+fn assign_uuids(conn: &Connection) -> rusqlite::Result<()> {
+    for table in ["sessions", "reps"] {
+        let ids: Vec<i64> = conn
+            .prepare(&format!("SELECT id FROM {} WHERE uuid IS NULL", table))?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        for id in ids {
+            conn.execute(&format!("UPDATE {} SET uuid = ?1 WHERE id = ?2", table), params![new_uuid(), id])?;
+        }
+    }
+    Ok(())
+}
+
+pub fn new_uuid() -> String {
+    Uuid::new_v4().to_string()
+}
+
+// --- Configuration ---
+
+pub fn device_id(conn: &Connection) -> rusqlite::Result<String> {
+    if let Some(id) = db::get_app_setting(conn, DEVICE_ID_KEY)? {
+        return Ok(id);
+    }
+    let id = new_uuid();
+    db::set_app_setting(conn, DEVICE_ID_KEY, &id)?;
+    Ok(id)
+}
+
+fn setting_i64(conn: &Connection, key: &str) -> rusqlite::Result<i64> {
+    Ok(db::get_setting(conn, key)?.and_then(|value| value.parse().ok()).unwrap_or(0))
+}
+
+pub fn get_config(conn: &Connection) -> rusqlite::Result<SyncConfig> {
+    let profile_id = db::active_profile_id(conn)?;
+    let pushed = setting_i64(conn, PUSHED_SEQ_KEY)?;
+    let pending_changes = conn.query_row(
+        "SELECT COUNT(DISTINCT uuid) FROM sync_changes WHERE profile_id = ?1 AND seq > ?2",
+        params![profile_id, pushed],
+        |row| row.get(0),
+    )?;
+    Ok(SyncConfig {
+        device_id: device_id(conn)?,
+        server_url: db::get_setting(conn, SERVER_URL_KEY)?,
+        space: db::get_setting(conn, SPACE_KEY)?,
+        cursor: setting_i64(conn, CURSOR_KEY)?,
+        pending_changes,
+    })
+}
+
+// Points the active profile at a server and space. Changing the space starts over: the
+// pull cursor is reset and the profile's whole history is queued for the next push.
+pub fn configure(conn: &Connection, server_url: &str, space: &str) -> rusqlite::Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let profile_id = db::active_profile_id(&tx)?;
+    let previous_space = db::get_setting(&tx, SPACE_KEY)?;
+    db::set_setting(&tx, SERVER_URL_KEY, server_url)?;
+    db::set_setting(&tx, SPACE_KEY, space)?;
+    if previous_space.as_deref() != Some(space) {
+        db::set_setting(&tx, CURSOR_KEY, "0")?;
+        queue_all(&tx, Some(profile_id))?;
+    }
+    tx.commit()
+}
+
+// Queues every session and rep of a profile, or of all profiles, for the next push.
+fn queue_all(conn: &Connection, profile_id: Option<i64>) -> rusqlite::Result<()> {
+    let now = timestamp(Utc::now());
+    conn.execute(
+        "INSERT INTO sync_changes (profile_id, entity, uuid, changed_at)
+         SELECT profile_id, 'session', uuid, ?2 FROM sessions WHERE ?1 IS NULL OR profile_id = ?1",
+        params![profile_id, now],
+    )?;
+    conn.execute(
+        "INSERT INTO sync_changes (profile_id, entity, uuid, changed_at)
+         SELECT s.profile_id, 'rep', r.uuid, ?2 FROM reps r JOIN sessions s ON s.id = r.session_id
+         WHERE ?1 IS NULL OR s.profile_id = ?1",
+        params![profile_id, now],
+    )?;
+    Ok(())
+}
+
+// --- Push ---
+
+fn session_record(conn: &Connection, uuid: &str, modified_at: DateTime<Utc>, device_id: &str) -> rusqlite::Result<SyncRecord> {
+    let data = conn
+        .query_row(
+            "SELECT start_time, end_time, pressure_setting, notes, deleted_at FROM sessions WHERE uuid = ?1",
+            [uuid],
+            |row| {
+                Ok(SessionData {
+                    start_time: row.get(0)?,
+                    end_time: row.get(1)?,
+                    pressure_setting: row.get(2)?,
+                    notes: row.get(3)?,
+                    deleted_at: row.get(4)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(SyncRecord {
+        entity: Entity::Session,
+        uuid: uuid.to_string(),
+        modified_at,
+        device_id: device_id.to_string(),
+        deleted: data.is_none(),
+        data: data.map(|data| serde_json::to_value(data).unwrap_or_default()),
+    })
+}
+
+fn rep_record(conn: &Connection, uuid: &str, modified_at: DateTime<Utc>, device_id: &str) -> rusqlite::Result<Option<SyncRecord>> {
+    let data = conn
+        .query_row(
+            "SELECT s.uuid, r.rep_timestamp FROM reps r JOIN sessions s ON s.id = r.session_id WHERE r.uuid = ?1",
+            [uuid],
+            |row| Ok(RepData { session_uuid: row.get(0)?, rep_timestamp: row.get(1)? }),
+        )
+        .optional()?;
+    // A rep that is gone went with its session, whose tombstone covers it
+    Ok(data.map(|data| SyncRecord {
+        entity: Entity::Rep,
+        uuid: uuid.to_string(),
+        modified_at,
+        device_id: device_id.to_string(),
+        deleted: false,
+        data: serde_json::to_value(data).ok(),
+    }))
+}
+
+// The next batch of the active profile's local changes. Sessions come before reps so the
+// receiver always knows a rep's session.
+pub fn pending_push(conn: &Connection) -> rusqlite::Result<PushRequest> {
+    let profile_id = db::active_profile_id(conn)?;
+    let device_id = device_id(conn)?;
+    let pushed = setting_i64(conn, PUSHED_SEQ_KEY)?;
+    let changes: Vec<(i64, String, String, String)> = conn
+        .prepare(
+            "SELECT seq, entity, uuid, changed_at FROM sync_changes
+             WHERE profile_id = ?1 AND seq > ?2 ORDER BY seq LIMIT ?3",
+        )?
+        .query_map(params![profile_id, pushed, PUSH_BATCH], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let through_seq = changes.last().map(|change| change.0).unwrap_or(pushed);
+    // Latest change per record, keeping first-seen order
+    let mut latest: Vec<(Entity, String, DateTime<Utc>)> = Vec::new();
+    for (_, entity, uuid, changed_at) in changes {
+        let (Some(entity), Ok(changed_at)) = (Entity::parse(&entity), DateTime::parse_from_rfc3339(&changed_at)) else {
+            continue;
+        };
+        let changed_at = changed_at.with_timezone(&Utc);
+        match latest.iter_mut().find(|(_, existing, _)| *existing == uuid) {
+            Some(entry) => entry.2 = entry.2.max(changed_at),
+            None => latest.push((entity, uuid, changed_at)),
+        }
+    }
+
+    let mut records = Vec::new();
+    for (_, uuid, modified_at) in latest.iter().filter(|(entity, _, _)| *entity == Entity::Session) {
+        records.push(session_record(conn, uuid, *modified_at, &device_id)?);
+    }
+    for (_, uuid, modified_at) in latest.iter().filter(|(entity, _, _)| *entity == Entity::Rep) {
+        records.extend(rep_record(conn, uuid, *modified_at, &device_id)?);
+    }
+    Ok(PushRequest { device_id, records, through_seq })
+}
+
+// Marks every change up to `through_seq` as delivered.
+pub fn acknowledge_push(conn: &Connection, through_seq: i64) -> rusqlite::Result<()> {
+    if through_seq > setting_i64(conn, PUSHED_SEQ_KEY)? {
+        db::set_setting(conn, PUSHED_SEQ_KEY, &through_seq.to_string())?;
+    }
+    Ok(())
+}
+
+// --- Pull ---
+
+// The newest unpushed local change to a record, if any.
+fn pending_change(conn: &Connection, profile_id: i64, pushed: i64, uuid: &str) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    let changed_at: Option<String> = conn.query_row(
+        "SELECT MAX(changed_at) FROM sync_changes WHERE profile_id = ?1 AND seq > ?2 AND uuid = ?3",
+        params![profile_id, pushed, uuid],
+        |row| row.get(0),
+    )?;
+    Ok(changed_at
+        .and_then(|at| DateTime::parse_from_rfc3339(&at).ok())
+        .map(|at| at.with_timezone(&Utc)))
+}
+
+fn apply_session(conn: &Connection, profile_id: i64, record: &SyncRecord) -> rusqlite::Result<Option<i64>> {
+    if record.deleted {
+        conn.execute("DELETE FROM sessions WHERE uuid = ?1 AND profile_id = ?2", params![record.uuid, profile_id])?;
+        return Ok(None);
+    }
+    let Some(data) = record.data.clone().and_then(|data| serde_json::from_value::<SessionData>(data).ok()) else {
+        return Ok(None);
+    };
+    let existing: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM sessions WHERE uuid = ?1 AND profile_id = ?2",
+            params![record.uuid, profile_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let id = match existing {
+        Some(id) => {
+            conn.execute(
+                "UPDATE sessions SET start_time = ?2, end_time = ?3, pressure_setting = ?4, notes = ?5, deleted_at = ?6
+                 WHERE id = ?1",
+                params![id, data.start_time, data.end_time, data.pressure_setting, data.notes, data.deleted_at],
+            )?;
+            id
+        }
+        None => {
+            // A session another profile on this device already holds is left alone
+            let inserted = conn.execute(
+                "INSERT OR IGNORE INTO sessions (uuid, profile_id, start_time, end_time, pressure_setting, notes, deleted_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![record.uuid, profile_id, data.start_time, data.end_time, data.pressure_setting, data.notes, data.deleted_at],
+            )?;
+            if inserted == 0 {
+                return Ok(None);
+            }
+            conn.last_insert_rowid()
+        }
+    };
+    Ok(data.end_time.is_some().then_some(id))
+}
+
+// The rep's session and whether it has ended, or None if the session isn't on this device.
+fn apply_rep(conn: &Connection, profile_id: i64, record: &SyncRecord) -> rusqlite::Result<Option<(i64, bool)>> {
+    let Some(data) = record.data.clone().and_then(|data| serde_json::from_value::<RepData>(data).ok()) else {
+        return Ok(None);
+    };
+    let session: Option<(i64, bool)> = conn
+        .query_row(
+            "SELECT id, end_time IS NOT NULL FROM sessions WHERE uuid = ?1 AND profile_id = ?2",
+            params![data.session_uuid, profile_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let Some((session_id, _)) = session else {
+        return Ok(None);
+    };
+    conn.execute(
+        "INSERT OR IGNORE INTO reps (uuid, session_id, rep_timestamp) VALUES (?1, ?2, ?3)",
+        params![record.uuid, session_id, data.rep_timestamp],
+    )?;
+    Ok(session)
+}
+
+// Applies a pull response to the active profile and advances its cursor. Sessions that
+// arrive ended are scored once their reps are in, and scored again when more of their reps
+// come in a later page.
+pub fn apply_pull(conn: &Connection, response: &PullResponse) -> rusqlite::Result<PullResult> {
+    let tx = conn.unchecked_transaction()?;
+    let profile_id = db::active_profile_id(&tx)?;
+    let local_device = device_id(&tx)?;
+    let pushed = setting_i64(&tx, PUSHED_SEQ_KEY)?;
+    db::set_app_setting(&tx, APPLYING_KEY, "1")?;
+
+    let mut result = PullResult::default();
+    let mut ended = HashSet::new();
+    let mut records: Vec<&SyncRecord> = response.records.iter().collect();
+    records.sort_by_key(|record| record.entity == Entity::Rep);
+    for record in records {
+        if record.device_id == local_device {
+            continue;
+        }
+        if let Some(local) = pending_change(&tx, profile_id, pushed, &record.uuid)?
+            && !wins((&record.modified_at, &record.device_id), (&local, &local_device))
+        {
+            result.kept_local += 1;
+            continue;
+        }
+        match record.entity {
+            Entity::Session => {
+                ended.extend(apply_session(&tx, profile_id, record)?);
+                result.applied += 1;
+            }
+            Entity::Rep => {
+                match apply_rep(&tx, profile_id, record)? {
+                    Some((session_id, session_ended)) => {
+                        if session_ended {
+                            ended.insert(session_id);
+                        }
+                        result.applied += 1;
+                    }
+                    None => result.skipped += 1,
+                }
+            }
+        }
+    }
+    for session_id in ended {
+        db::score_completed_session(&tx, session_id)?;
+    }
+
+    tx.execute("DELETE FROM app_settings WHERE key = ?1", [APPLYING_KEY])?;
+    let cursor = response.cursor.max(setting_i64(&tx, CURSOR_KEY)?);
+    db::set_setting(&tx, CURSOR_KEY, &cursor.to_string())?;
+    tx.commit()?;
+    result.cursor = cursor;
+    Ok(result)
+}
+
+// --- Backups ---
+
+// The change log describes this device's exchange with the server, so it stays out of
+// backups.
+pub const CHANGE_LOG_TABLE: &str = "sync_changes";
+
+// Called after a backup replaced the database. Everything restored is queued for push, the
+// device keeps its own id rather than the one in the backup and the space is pulled again
+// from the start, so a backup from another install merges with the server instead of
+// masquerading as that install.
+pub fn reset_after_restore(conn: &Connection, device_id: Option<&str>) -> rusqlite::Result<()> {
+    assign_uuids(conn)?;
+    conn.execute("DELETE FROM sync_changes", [])?;
+    queue_all(conn, None)?;
+    conn.execute("DELETE FROM app_settings WHERE key = ?1", [DEVICE_ID_KEY])?;
+    if let Some(device_id) = device_id {
+        db::set_app_setting(conn, DEVICE_ID_KEY, device_id)?;
+    }
+    conn.execute(
+        "DELETE FROM profile_settings WHERE key IN (?1, ?2)",
+        params![CURSOR_KEY, PUSHED_SEQ_KEY],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    const REMOTE: &str = "remote-device";
+
+    fn device() -> Connection {
+        db::initialize_database(":memory:").unwrap()
+    }
+
+    fn session_uuid(conn: &Connection, session_id: i64) -> String {
+        conn.query_row("SELECT uuid FROM sessions WHERE id = ?1", [session_id], |row| row.get(0)).unwrap()
+    }
+
+    fn notes(conn: &Connection, session_id: i64) -> Option<String> {
+        conn.query_row("SELECT notes FROM sessions WHERE id = ?1", [session_id], |row| row.get(0))
+            .optional()
+            .unwrap()
+    }
+
+    // When the newest local change to a record was logged
+    fn changed_at(conn: &Connection, uuid: &str) -> DateTime<Utc> {
+        let at: String = conn
+            .query_row("SELECT MAX(changed_at) FROM sync_changes WHERE uuid = ?1", [uuid], |row| row.get(0))
+            .unwrap();
+        DateTime::parse_from_rfc3339(&at).unwrap().with_timezone(&Utc)
+    }
+
+    fn remote_edit(uuid: &str, modified_at: DateTime<Utc>, device_id: &str, notes: &str) -> SyncRecord {
+        let data = SessionData {
+            start_time: timestamp(Utc::now()),
+            end_time: None,
+            pressure_setting: 30,
+            notes: notes.to_string(),
+            deleted_at: None,
+        };
+        SyncRecord {
+            entity: Entity::Session,
+            uuid: uuid.to_string(),
+            modified_at,
+            device_id: device_id.to_string(),
+            deleted: false,
+            data: serde_json::to_value(data).ok(),
+        }
+    }
+
+    fn tombstone(uuid: &str, modified_at: DateTime<Utc>) -> SyncRecord {
+        SyncRecord {
+            entity: Entity::Session,
+            uuid: uuid.to_string(),
+            modified_at,
+            device_id: REMOTE.to_string(),
+            deleted: true,
+            data: None,
+        }
+    }
+
+    fn pull(records: Vec<SyncRecord>, cursor: i64, more: bool) -> PullResponse {
+        PullResponse { records, cursor, more }
+    }
+
+    #[test]
+    fn later_edit_wins_and_device_id_breaks_ties() {
+        let at = Utc::now();
+        let earlier = at - Duration::milliseconds(1);
+        assert!(wins((&at, "a"), (&earlier, "b")));
+        assert!(!wins((&earlier, "b"), (&at, "a")));
+        assert!(wins((&at, "b"), (&at, "a")));
+        assert!(!wins((&at, "a"), (&at, "b")));
+        // A record never replaces itself
+        assert!(!wins((&at, "a"), (&at, "a")));
+    }
+
+    #[test]
+    fn unpushed_local_edit_beats_an_older_remote_one() {
+        let conn = device();
+        let session = db::start_new_session(&conn, 30, "local").unwrap();
+        let uuid = session_uuid(&conn, session);
+        let local = changed_at(&conn, &uuid);
+
+        let older = remote_edit(&uuid, local - Duration::minutes(5), REMOTE, "remote");
+        let result = apply_pull(&conn, &pull(vec![older], 1, false)).unwrap();
+        assert_eq!((result.applied, result.kept_local), (0, 1));
+        assert_eq!(notes(&conn, session).as_deref(), Some("local"));
+
+        let newer = remote_edit(&uuid, local + Duration::minutes(5), REMOTE, "remote");
+        let result = apply_pull(&conn, &pull(vec![newer], 2, false)).unwrap();
+        assert_eq!((result.applied, result.kept_local), (1, 0));
+        assert_eq!(notes(&conn, session).as_deref(), Some("remote"));
+    }
+
+    #[test]
+    fn simultaneous_edits_go_to_the_greater_device_id() {
+        let conn = device();
+        let local_device = device_id(&conn).unwrap();
+        let session = db::start_new_session(&conn, 30, "local").unwrap();
+        let uuid = session_uuid(&conn, session);
+        let local = changed_at(&conn, &uuid);
+
+        // UUIDs are lowercase hex, so these sort below and above any of them
+        let smaller = remote_edit(&uuid, local, "0", "smaller");
+        assert!(!wins((&local, "0"), (&local, &local_device)));
+        assert_eq!(apply_pull(&conn, &pull(vec![smaller], 1, false)).unwrap().kept_local, 1);
+        assert_eq!(notes(&conn, session).as_deref(), Some("local"));
+
+        let greater = remote_edit(&uuid, local, "z", "greater");
+        assert_eq!(apply_pull(&conn, &pull(vec![greater], 2, false)).unwrap().applied, 1);
+        assert_eq!(notes(&conn, session).as_deref(), Some("greater"));
+    }
+
+    #[test]
+    fn tombstone_only_wins_against_an_earlier_edit() {
+        let conn = device();
+        let session = db::start_new_session(&conn, 30, "").unwrap();
+        let uuid = session_uuid(&conn, session);
+        conn.execute("UPDATE sessions SET notes = 'edited' WHERE id = ?1", [session]).unwrap();
+        let local = changed_at(&conn, &uuid);
+
+        // Deleted elsewhere before the edit here: the edit survives and is pushed later
+        let result = apply_pull(&conn, &pull(vec![tombstone(&uuid, local - Duration::seconds(1))], 1, false)).unwrap();
+        assert_eq!(result.kept_local, 1);
+        assert_eq!(notes(&conn, session).as_deref(), Some("edited"));
+        assert!(pending_push(&conn).unwrap().records.iter().any(|record| record.uuid == uuid && !record.deleted));
+
+        // Deleted elsewhere after it: the session goes
+        let result = apply_pull(&conn, &pull(vec![tombstone(&uuid, local + Duration::seconds(1))], 2, false)).unwrap();
+        assert_eq!(result.applied, 1);
+        assert_eq!(notes(&conn, session), None);
+    }
+
+    #[test]
+    fn pull_split_across_pages_scores_with_every_rep() {
+        let conn = device();
+        let start = Utc::now() - Duration::minutes(10);
+        let uuid = new_uuid();
+        let session = SyncRecord {
+            data: serde_json::to_value(SessionData {
+                start_time: timestamp(start),
+                end_time: Some(timestamp(start + Duration::minutes(5))),
+                pressure_setting: 30,
+                notes: String::new(),
+                deleted_at: None,
+            })
+            .ok(),
+            ..remote_edit(&uuid, start, REMOTE, "")
+        };
+        let rep = |second: i64| SyncRecord {
+            entity: Entity::Rep,
+            uuid: new_uuid(),
+            modified_at: start,
+            device_id: REMOTE.to_string(),
+            deleted: false,
+            data: serde_json::to_value(RepData {
+                session_uuid: uuid.clone(),
+                rep_timestamp: timestamp(start + Duration::seconds(second)),
+            })
+            .ok(),
+        };
+
+        let first = pull(vec![session, rep(10), rep(20)], 3, true);
+        let second = pull((3..6).map(|n| rep(n * 10)).collect(), 6, false);
+        assert_eq!(apply_pull(&conn, &first).unwrap().applied, 3);
+        let result = apply_pull(&conn, &second).unwrap();
+        assert_eq!((result.applied, result.skipped, result.cursor), (3, 0, 6));
+
+        let session_id: i64 = conn.query_row("SELECT id FROM sessions WHERE uuid = ?1", [&uuid], |row| row.get(0)).unwrap();
+        let reps_completed: i64 = conn
+            .query_row("SELECT reps_completed FROM session_scores WHERE session_id = ?1", [session_id], |row| row.get(0))
+            .unwrap();
+        assert_eq!(reps_completed, 5);
+        // A page applied again doesn't move the cursor back
+        assert_eq!(apply_pull(&conn, &first).unwrap().cursor, 6);
+        assert_eq!(get_config(&conn).unwrap().pending_changes, 0);
+    }
+}
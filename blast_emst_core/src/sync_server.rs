@@ -0,0 +1,325 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde_json::json;
+use std::path::Path;
+
+use crate::sync::{self, PullResponse, PushRequest, PushResponse, SyncRecord};
+
+// --- Reference sync server ---
+//
+// The protocol logic behind the `sync_server` binary, kept free of any HTTP library so it
+// can be embedded elsewhere. The server only stores the latest version of each record per
+// space and hands out changes in the order it received them; it never looks inside
+// `data`. See SYNC_PROTOCOL.md.
+
+const DEFAULT_PULL_LIMIT: i64 = 500;
+const MAX_PULL_LIMIT: i64 = 1000;
+
+pub struct SyncStore {
+    conn: Connection,
+}
+
+impl SyncStore {
+    pub fn open(path: &Path) -> rusqlite::Result<SyncStore> {
+        SyncStore::prepare(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> rusqlite::Result<SyncStore> {
+        SyncStore::prepare(Connection::open_in_memory()?)
+    }
+
+    fn prepare(conn: Connection) -> rusqlite::Result<SyncStore> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS records (
+                space TEXT NOT NULL,
+                uuid TEXT NOT NULL,
+                entity TEXT NOT NULL,
+                modified_at TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                deleted INTEGER NOT NULL,
+                data TEXT,
+                seq INTEGER NOT NULL,
+                PRIMARY KEY (space, uuid)
+            );
+            CREATE INDEX IF NOT EXISTS records_seq ON records (space, seq);",
+        )?;
+        Ok(SyncStore { conn })
+    }
+
+    // Stores each record that wins against the version already held, giving it the next
+    // sequence number so pulls pick it up.
+    pub fn push(&self, space: &str, request: &PushRequest) -> rusqlite::Result<PushResponse> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut seq: i64 = tx.query_row("SELECT COALESCE(MAX(seq), 0) FROM records", [], |row| row.get(0))?;
+        let mut response = PushResponse::default();
+        for record in &request.records {
+            let existing: Option<(String, String)> = tx
+                .query_row(
+                    "SELECT modified_at, device_id FROM records WHERE space = ?1 AND uuid = ?2",
+                    params![space, record.uuid],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+            let accept = match existing {
+                None => true,
+                Some((modified_at, device_id)) => {
+                    let modified_at = DateTime::parse_from_rfc3339(&modified_at)
+                        .map(|at| at.with_timezone(&Utc))
+                        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+                    sync::wins((&record.modified_at, &record.device_id), (&modified_at, &device_id))
+                }
+            };
+            if !accept {
+                response.rejected.push(record.uuid.clone());
+                continue;
+            }
+            seq += 1;
+            tx.execute(
+                "INSERT OR REPLACE INTO records (space, uuid, entity, modified_at, device_id, deleted, data, seq)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    space,
+                    record.uuid,
+                    record.entity.as_str(),
+                    sync::timestamp(record.modified_at),
+                    record.device_id,
+                    record.deleted,
+                    record.data.as_ref().map(|data| data.to_string()),
+                    seq,
+                ],
+            )?;
+            response.applied.push(record.uuid.clone());
+        }
+        tx.commit()?;
+        Ok(response)
+    }
+
+    pub fn pull(&self, space: &str, since: i64, limit: i64) -> rusqlite::Result<PullResponse> {
+        let mut stmt = self.conn.prepare(
+            "SELECT entity, uuid, modified_at, device_id, deleted, data, seq FROM records
+             WHERE space = ?1 AND seq > ?2 ORDER BY seq LIMIT ?3",
+        )?;
+        // Rows are read back as JSON so they go through the same validation as pushed records
+        let rows: Vec<(Option<SyncRecord>, i64)> = stmt
+            .query_map(params![space, since, limit + 1], |row| {
+                let data: Option<String> = row.get(5)?;
+                let record = json!({
+                    "entity": row.get::<_, String>(0)?,
+                    "uuid": row.get::<_, String>(1)?,
+                    "modified_at": row.get::<_, String>(2)?,
+                    "device_id": row.get::<_, String>(3)?,
+                    "deleted": row.get::<_, bool>(4)?,
+                    "data": data.and_then(|data| serde_json::from_str::<serde_json::Value>(&data).ok()),
+                });
+                Ok((serde_json::from_value(record).ok(), row.get(6)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let more = rows.len() as i64 > limit;
+        let mut response = PullResponse { records: Vec::new(), cursor: since, more };
+        for (record, seq) in rows.into_iter().take(limit as usize) {
+            response.cursor = seq;
+            response.records.extend(record);
+        }
+        Ok(response)
+    }
+}
+
+// --- HTTP handling ---
+
+pub struct Request<'a> {
+    pub method: &'a str,
+    // Path including the query string
+    pub url: &'a str,
+    pub authorization: Option<&'a str>,
+    pub body: &'a str,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: serde_json::Value) -> Response {
+        Response { status, body: body.to_string() }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, json!({ "error": message }))
+    }
+}
+
+pub struct SyncServer {
+    store: SyncStore,
+    // Shared secret clients send as `Authorization: Bearer <token>`; None accepts anyone
+    token: Option<String>,
+}
+
+// Space names are chosen by the users setting up sync; keep them to something that is safe
+// in URLs and logs.
+fn valid_space(space: &str) -> bool {
+    (1..=64).contains(&space.len()) && space.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+impl SyncServer {
+    pub fn new(store: SyncStore, token: Option<String>) -> SyncServer {
+        SyncServer { store, token }
+    }
+
+    pub fn handle(&self, request: &Request) -> Response {
+        let (path, query) = request.url.split_once('?').unwrap_or((request.url, ""));
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        if segments == ["v1", "health"] {
+            return match request.method {
+                "GET" => Response::json(200, json!({ "status": "ok" })),
+                _ => Response::error(405, "method not allowed"),
+            };
+        }
+        let ["v1", "spaces", space, action] = segments.as_slice() else {
+            return Response::error(404, "not found");
+        };
+        if let Some(token) = &self.token {
+            let expected = format!("Bearer {}", token);
+            if request.authorization != Some(expected.as_str()) {
+                return Response::error(401, "unauthorized");
+            }
+        }
+        if !valid_space(space) {
+            return Response::error(400, "invalid space name");
+        }
+
+        let result = match (request.method, *action) {
+            ("POST", "push") => {
+                let push: PushRequest = match serde_json::from_str(request.body) {
+                    Ok(push) => push,
+                    Err(e) => return Response::error(400, &format!("invalid push body: {}", e)),
+                };
+                if push.records.iter().any(|record| record.device_id != push.device_id) {
+                    return Response::error(400, "records must come from the pushing device");
+                }
+                self.store.push(space, &push).map(|response| serde_json::to_value(response).unwrap_or_default())
+            }
+            ("GET", "pull") => {
+                let since = query_param(query, "since").and_then(|v| v.parse().ok()).unwrap_or(0);
+                let limit = query_param(query, "limit")
+                    .and_then(|v| v.parse::<i64>().ok())
+                    .unwrap_or(DEFAULT_PULL_LIMIT)
+                    .clamp(1, MAX_PULL_LIMIT);
+                self.store.pull(space, since, limit).map(|response| serde_json::to_value(response).unwrap_or_default())
+            }
+            (_, "push" | "pull") => return Response::error(405, "method not allowed"),
+            _ => return Response::error(404, "not found"),
+        };
+        match result {
+            Ok(body) => Response::json(200, body),
+            Err(e) => Response::error(500, &e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn device() -> Connection {
+        db::initialize_database(":memory:").unwrap()
+    }
+
+    fn request<'a>(method: &'a str, url: &'a str, body: &'a str) -> Request<'a> {
+        Request { method, url, authorization: Some("Bearer secret"), body }
+    }
+
+    fn sync_device(server: &SyncServer, conn: &Connection) {
+        let push = sync::pending_push(conn).unwrap();
+        let body = serde_json::to_string(&push).unwrap();
+        let response = server.handle(&request("POST", "/v1/spaces/family/push", &body));
+        assert_eq!(response.status, 200, "{}", response.body);
+        sync::acknowledge_push(conn, push.through_seq).unwrap();
+
+        let cursor = sync::get_config(conn).unwrap().cursor;
+        let url = format!("/v1/spaces/family/pull?since={}", cursor);
+        let response = server.handle(&request("GET", &url, ""));
+        assert_eq!(response.status, 200, "{}", response.body);
+        let pull: PullResponse = serde_json::from_str(&response.body).unwrap();
+        sync::apply_pull(conn, &pull).unwrap();
+    }
+
+    fn session_count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn rejects_missing_token_and_bad_spaces() {
+        let server = SyncServer::new(SyncStore::open_in_memory().unwrap(), Some("secret".to_string()));
+        let mut anonymous = request("GET", "/v1/spaces/family/pull", "");
+        anonymous.authorization = None;
+        assert_eq!(server.handle(&anonymous).status, 401);
+        assert_eq!(server.handle(&request("GET", "/v1/spaces/no%20spaces/pull", "")).status, 400);
+        assert_eq!(server.handle(&request("GET", "/v1/health", "")).status, 200);
+    }
+
+    #[test]
+    fn sessions_and_deletions_reach_the_other_device() {
+        let server = SyncServer::new(SyncStore::open_in_memory().unwrap(), Some("secret".to_string()));
+        let phone = device();
+        let tablet = device();
+        sync::configure(&phone, "https://example.test", "family").unwrap();
+        sync::configure(&tablet, "https://example.test", "family").unwrap();
+
+        let session = db::start_new_session(&phone, 30, "morning").unwrap();
+        for _ in 0..5 {
+            db::add_rep(&phone, session).unwrap();
+        }
+        db::end_session(&phone, session, "", &Utc::now()).unwrap();
+        sync_device(&server, &phone);
+        sync_device(&server, &tablet);
+
+        assert_eq!(session_count(&tablet), 1);
+        let reps: i64 = tablet.query_row("SELECT COUNT(*) FROM reps", [], |row| row.get(0)).unwrap();
+        assert_eq!(reps, 5);
+        // Applying pulled records doesn't queue them to be pushed back
+        assert_eq!(sync::get_config(&tablet).unwrap().pending_changes, 0);
+
+        let uuid: String = tablet.query_row("SELECT uuid FROM sessions", [], |row| row.get(0)).unwrap();
+        tablet.execute("DELETE FROM sessions WHERE uuid = ?1", [&uuid]).unwrap();
+        sync_device(&server, &tablet);
+        sync_device(&server, &phone);
+        assert_eq!(session_count(&phone), 0);
+    }
+
+    #[test]
+    fn later_edit_wins_a_conflict() {
+        let server = SyncServer::new(SyncStore::open_in_memory().unwrap(), Some("secret".to_string()));
+        let phone = device();
+        let tablet = device();
+        sync::configure(&phone, "https://example.test", "family").unwrap();
+        sync::configure(&tablet, "https://example.test", "family").unwrap();
+        db::start_new_session(&phone, 30, "").unwrap();
+        sync_device(&server, &phone);
+        sync_device(&server, &tablet);
+
+        phone.execute("UPDATE sessions SET notes = 'phone'", []).unwrap();
+        // Change times have millisecond precision; make sure the tablet's edit is later
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        tablet.execute("UPDATE sessions SET notes = 'tablet'", []).unwrap();
+        sync_device(&server, &phone);
+        sync_device(&server, &tablet);
+        sync_device(&server, &phone);
+
+        let notes = |conn: &Connection| -> String { conn.query_row("SELECT notes FROM sessions", [], |row| row.get(0)).unwrap() };
+        assert_eq!(notes(&phone), "tablet");
+        assert_eq!(notes(&tablet), "tablet");
+    }
+}
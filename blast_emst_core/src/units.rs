@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+
+// --- Pressure units ---
+
+// Pressures are always stored in cmH2O. Other units only exist for display.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PressureUnit {
+    #[default]
+    CmH2O,
+    MmHg,
+    KPa,
+}
+
+// Profile setting holding the user's preferred display unit
+pub const PRESSURE_UNIT_KEY: &str = "pressure_unit";
+
+const KPA_PER_CMH2O: f64 = 0.0980665;
+const KPA_PER_MMHG: f64 = 0.133322387415;
+
+impl PressureUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PressureUnit::CmH2O => "cmh2o",
+            PressureUnit::MmHg => "mmhg",
+            PressureUnit::KPa => "kpa",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<PressureUnit> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "cmh2o" => Some(PressureUnit::CmH2O),
+            "mmhg" => Some(PressureUnit::MmHg),
+            "kpa" => Some(PressureUnit::KPa),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            PressureUnit::CmH2O => "cmH2O",
+            PressureUnit::MmHg => "mmHg",
+            PressureUnit::KPa => "kPa",
+        }
+    }
+
+    // Decimal places worth showing: trainers are set in whole cmH2O, which is about
+    // 0.7 mmHg or 0.1 kPa.
+    pub fn decimals(&self) -> usize {
+        match self {
+            PressureUnit::CmH2O => 0,
+            PressureUnit::MmHg => 1,
+            PressureUnit::KPa => 2,
+        }
+    }
+
+    fn kpa_per_unit(&self) -> f64 {
+        match self {
+            PressureUnit::CmH2O => KPA_PER_CMH2O,
+            PressureUnit::MmHg => KPA_PER_MMHG,
+            PressureUnit::KPa => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Pressure {
+    cmh2o: f64,
+}
+
+impl Pressure {
+    pub fn from_cmh2o(cmh2o: f64) -> Self {
+        Pressure { cmh2o }
+    }
+
+    pub fn new(value: f64, unit: PressureUnit) -> Self {
+        Pressure { cmh2o: value * unit.kpa_per_unit() / KPA_PER_CMH2O }
+    }
+
+    pub fn cmh2o(&self) -> f64 {
+        self.cmh2o
+    }
+
+    pub fn value_in(&self, unit: PressureUnit) -> f64 {
+        self.cmh2o * KPA_PER_CMH2O / unit.kpa_per_unit()
+    }
+
+    // Rounded to the unit's display precision
+    pub fn display_value(&self, unit: PressureUnit) -> f64 {
+        let factor = 10f64.powi(unit.decimals() as i32);
+        (self.value_in(unit) * factor).round() / factor
+    }
+
+    pub fn format(&self, unit: PressureUnit) -> String {
+        format!("{:.*} {}", unit.decimals(), self.value_in(unit), unit.symbol())
+    }
+}
+
+// A pressure as shown to the user, attached to session JSON alongside the canonical
+// cmH2O value.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DisplayPressure {
+    pub value: f64,
+    pub unit: PressureUnit,
+    pub text: String,
+}
+
+impl DisplayPressure {
+    pub fn new(pressure: Pressure, unit: PressureUnit) -> Self {
+        DisplayPressure {
+            value: pressure.display_value(unit),
+            unit,
+            text: pressure.format(unit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNITS: [PressureUnit; 3] = [PressureUnit::CmH2O, PressureUnit::MmHg, PressureUnit::KPa];
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn converts_known_values() {
+        let pressure = Pressure::from_cmh2o(100.0);
+        assert!(close(pressure.value_in(PressureUnit::KPa), 9.80665));
+        assert!(close(pressure.value_in(PressureUnit::MmHg), 73.55591352766805));
+        // 1 mmHg is 1.35951 cmH2O
+        assert!(close(Pressure::new(1.0, PressureUnit::MmHg).cmh2o(), 1.35951));
+        assert!(close(Pressure::new(1.0, PressureUnit::KPa).cmh2o(), 10.197162129779283));
+    }
+
+    #[test]
+    fn round_trips_through_every_unit() {
+        for unit in UNITS {
+            for cmh2o in [0.0, 4.0, 30.0, 150.0, 299.5] {
+                let shown = Pressure::from_cmh2o(cmh2o).value_in(unit);
+                assert!(close(Pressure::new(shown, unit).cmh2o(), cmh2o), "{} cmH2O via {:?}", cmh2o, unit);
+            }
+        }
+    }
+
+    #[test]
+    fn display_values_round_to_each_units_precision() {
+        let pressure = Pressure::from_cmh2o(30.0);
+        assert_eq!(pressure.display_value(PressureUnit::CmH2O), 30.0);
+        assert_eq!(pressure.display_value(PressureUnit::MmHg), 22.1);
+        assert_eq!(pressure.display_value(PressureUnit::KPa), 2.94);
+        assert_eq!(Pressure::from_cmh2o(30.6).display_value(PressureUnit::CmH2O), 31.0);
+        assert_eq!(pressure.format(PressureUnit::MmHg), "22.1 mmHg");
+        assert_eq!(DisplayPressure::new(pressure, PressureUnit::KPa).text, "2.94 kPa");
+    }
+
+    #[test]
+    fn parses_unit_names_loosely() {
+        for unit in UNITS {
+            assert_eq!(PressureUnit::parse(unit.as_str()), Some(unit));
+            assert_eq!(PressureUnit::parse(&unit.symbol().to_uppercase()), Some(unit));
+        }
+        assert_eq!(PressureUnit::parse(" kPa "), Some(PressureUnit::KPa));
+        assert_eq!(PressureUnit::parse("psi"), None);
+        assert_eq!(PressureUnit::parse(""), None);
+    }
+}